@@ -0,0 +1,474 @@
+// Horizontal coordinate assignment, using the method of Brandes and Köpf ("Fast and Simple
+// Horizontal Coordinate Assignment").
+//
+// Four candidate layouts are computed, by aligning each vertex with its median neighbors in the
+// layer above or below, and by compacting toward the left or toward the right. The candidates
+// are shifted to line up with the narrowest one, and then combined by taking the average of the
+// two median x values for each vertex.
+//
+// Edges between two virtual vertices ("inner segments") take priority during alignment, so the
+// virtual vertex chains of long edges are drawn as straight vertical lines wherever possible.
+// Callers can give other edges the same priority, and can rank the priorities in levels; the
+// layered layout uses this for cluster borders and for groups of vertices.
+
+use crate::graph::Graph;
+use crate::layering::ProperGraph;
+use crate::ordering::LayerAdjacency;
+use crate::ramp_table::RampTable;
+use crate::topo_sort::topo_sort;
+use crate::V;
+use log::debug;
+use std::collections::HashSet;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum VerticalDir {
+    /// Align each vertex with its neighbors in the layer above, working downward.
+    Up,
+    /// Align each vertex with its neighbors in the layer below, working upward.
+    Down,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum HorizontalDir {
+    Left,
+    Right,
+}
+
+/// Computes the x coordinate of the center of every vertex (real and virtual) in `pg`, using the
+/// current order of each layer.
+///
+/// `v_width` gives the width of every vertex, and `node_sep` is the minimum horizontal space
+/// between adjacent vertices in the same layer. The result is shifted so that the left edge of
+/// the leftmost vertex is at x = 0.
+pub fn assign_x_coordinates(pg: &ProperGraph, v_width: &[f64], node_sep: f64) -> Vec<f64> {
+    assign_x_coordinates_with(
+        pg,
+        v_width,
+        &|u, v| (pg.is_virtual(u) && pg.is_virtual(v)) as u32,
+        &|left, right| (v_width[left as usize] + v_width[right as usize]) / 2.0 + node_sep,
+    )
+}
+
+/// Like `assign_x_coordinates`, but with control over which edges are kept straight and over the
+/// spacing of vertices.
+///
+/// `segment_level(u, v)` gives the level of the edge between `u` and `v`, which may be passed
+/// in either order; it is 0 for ordinary edges. An edge whose level is at least 1 is an inner
+/// segment, which is kept vertical whenever possible. When inner segments cross, the segment
+/// with the higher level is the one that is kept vertical, and a vertex is aligned with the
+/// neighbors of its highest-level segments rather than with its median neighbors.
+/// (`assign_x_coordinates` uses level 1 for the edges between virtual vertices.)
+///
+/// `separation(left, right)` gives the minimum distance between the centers of two vertices
+/// that are adjacent in a layer, where `left` is to the left of `right`.
+pub fn assign_x_coordinates_with(
+    pg: &ProperGraph,
+    v_width: &[f64],
+    segment_level: &dyn Fn(V, V) -> u32,
+    separation: &dyn Fn(V, V) -> f64,
+) -> Vec<f64> {
+    let nv = pg.num_verts();
+    assert_eq!(v_width.len(), nv);
+    if nv == 0 {
+        return Vec::new();
+    }
+
+    let adj = LayerAdjacency::new(pg, &[]);
+    // Mark conflicts for each level of inner segment, so that segments of one level take
+    // priority over the segments of lower levels that cross them.
+    let max_level = pg
+        .edges
+        .all_values()
+        .iter()
+        .map(|&(u, v)| segment_level(u, v))
+        .max()
+        .unwrap_or(0);
+    let mut conflicts: HashSet<(V, V)> = HashSet::new();
+    for level in 1..=max_level {
+        find_type1_conflicts(
+            pg,
+            &adj,
+            &|u, v| segment_level(u, v) >= level,
+            &mut conflicts,
+        );
+    }
+    debug!("found {} type 1 conflicts", conflicts.len());
+
+    let mut candidates: Vec<(HorizontalDir, Vec<f64>)> = Vec::with_capacity(4);
+    for &vert in [VerticalDir::Up, VerticalDir::Down].iter() {
+        for &horiz in [HorizontalDir::Left, HorizontalDir::Right].iter() {
+            let layers = ordered_layers(pg, vert, horiz);
+            let neighbors = match vert {
+                VerticalDir::Up => &adj.up,
+                VerticalDir::Down => &adj.down,
+            };
+            let root = vertical_alignment(&layers, neighbors, segment_level, &conflicts, nv);
+            let mut xs = horizontal_compaction(&layers, &root, horiz, separation);
+            if horiz == HorizontalDir::Right {
+                for x in xs.iter_mut() {
+                    *x = -*x;
+                }
+            }
+            candidates.push((horiz, xs));
+        }
+    }
+
+    align_to_smallest_width(&mut candidates, v_width);
+
+    let mut x: Vec<f64> = (0..nv)
+        .map(|v| {
+            let mut values = [
+                candidates[0].1[v],
+                candidates[1].1[v],
+                candidates[2].1[v],
+                candidates[3].1[v],
+            ];
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            (values[1] + values[2]) / 2.0
+        })
+        .collect();
+
+    let left = x
+        .iter()
+        .zip(v_width.iter())
+        .map(|(&x, &w)| x - w / 2.0)
+        .fold(f64::INFINITY, f64::min);
+    for x in x.iter_mut() {
+        *x -= left;
+    }
+    x
+}
+
+/// Returns the layers in the order that they are visited for one of the four alignments. Layers
+/// are visited from top to bottom for `Up` and from bottom to top for `Down`, and the vertices
+/// within each layer are listed from right to left for `Right`.
+fn ordered_layers(pg: &ProperGraph, vert: VerticalDir, horiz: HorizontalDir) -> Vec<Vec<V>> {
+    let mut layers: Vec<Vec<V>> = pg.verts.iter().rev().map(|l| l.to_vec()).collect();
+    if vert == VerticalDir::Down {
+        layers.reverse();
+    }
+    if horiz == HorizontalDir::Right {
+        for layer in layers.iter_mut() {
+            layer.reverse();
+        }
+    }
+    layers
+}
+
+fn conflict_key(v: V, w: V) -> (V, V) {
+    (v.min(w), v.max(w))
+}
+
+/// Finds the "type 1" conflicts: edges that cross an inner segment (an edge for which `is_inner`
+/// is true) or share an endpoint with one, but which are not inner segments themselves, and
+/// adds them to `conflicts`. These edges are not used for alignment, so that inner segments can
+/// be drawn vertically.
+fn find_type1_conflicts(
+    pg: &ProperGraph,
+    adj: &LayerAdjacency,
+    is_inner: &dyn Fn(V, V) -> bool,
+    conflicts: &mut HashSet<(V, V)>,
+) {
+    // Visit each pair of adjacent layers, from the top down.
+    for lower in (0..pg.edges.len()).rev() {
+        let upper_len = pg.verts.entry_values(lower + 1).len() as u32;
+        let layer = pg.verts.entry_values(lower);
+
+        // k0 and k1 are the positions (in the upper layer) of the upper ends of the inner
+        // segments that bracket the vertices currently being scanned.
+        let mut k0: u32 = 0;
+        let mut scan_pos = 0;
+        for (i, &v) in layer.iter().enumerate() {
+            let inner = adj
+                .up
+                .entry_values(v as usize)
+                .iter()
+                .copied()
+                .find(|&u| is_inner(u, v));
+            let k1 = match inner {
+                Some(u) => {
+                    let others = adj.up.entry_values(v as usize).iter().map(|&w| (w, v));
+                    let others =
+                        others.chain(adj.down.entry_values(u as usize).iter().map(|&w| (u, w)));
+                    for (a, b) in others {
+                        if !is_inner(a, b) {
+                            conflicts.insert(conflict_key(a, b));
+                        }
+                    }
+                    pg.v_pos[u as usize]
+                }
+                None => upper_len,
+            };
+            if inner.is_some() || i + 1 == layer.len() {
+                for &scan_v in layer[scan_pos..=i].iter() {
+                    for &u in adj.up.entry_values(scan_v as usize).iter() {
+                        let u_pos = pg.v_pos[u as usize];
+                        if (u_pos < k0 || k1 < u_pos) && !is_inner(u, scan_v) {
+                            conflicts.insert(conflict_key(u, scan_v));
+                        }
+                    }
+                }
+                scan_pos = i + 1;
+                k0 = k1;
+            }
+        }
+    }
+}
+
+/// Aligns each vertex with one of its median neighbors in the previous layer (according to the
+/// order of `layers`), forming vertical "blocks" of vertices. If some of the edges to those
+/// neighbors are inner segments, then only the neighbors of the highest-level segments are
+/// considered. Returns the root (the first vertex) of the block that contains each vertex.
+fn vertical_alignment(
+    layers: &[Vec<V>],
+    neighbors: &RampTable<V>,
+    segment_level: &dyn Fn(V, V) -> u32,
+    conflicts: &HashSet<(V, V)>,
+    nv: usize,
+) -> Vec<V> {
+    let mut root: Vec<V> = (0..nv as V).collect();
+    let mut align: Vec<V> = (0..nv as V).collect();
+    let mut pos: Vec<usize> = vec![0; nv];
+    for layer in layers.iter() {
+        for (i, &v) in layer.iter().enumerate() {
+            pos[v as usize] = i;
+        }
+    }
+
+    let mut ws: Vec<V> = Vec::new();
+    for layer in layers.iter() {
+        // The position of the most recently aligned neighbor. Alignments must not cross.
+        let mut prev_pos: Option<usize> = None;
+        for &v in layer.iter() {
+            ws.clear();
+            ws.extend_from_slice(neighbors.entry_values(v as usize));
+            ws.sort_unstable_by_key(|&w| pos[w as usize]);
+            ws.dedup();
+            if ws.is_empty() {
+                continue;
+            }
+            let top = ws.iter().map(|&w| segment_level(v, w)).max().unwrap();
+            if top > 0 {
+                ws.retain(|&w| segment_level(v, w) == top);
+            }
+
+            // The one or two median neighbors.
+            let lo = (ws.len() - 1) / 2;
+            let hi = ws.len() / 2;
+            for &w in ws[lo..=hi].iter() {
+                let w_pos = pos[w as usize];
+                if align[v as usize] == v
+                    && !matches!(prev_pos, Some(p) if p >= w_pos)
+                    && !conflicts.contains(&conflict_key(v, w))
+                {
+                    align[w as usize] = v;
+                    root[v as usize] = root[w as usize];
+                    align[v as usize] = root[v as usize];
+                    prev_pos = Some(w_pos);
+                }
+            }
+        }
+    }
+
+    root
+}
+
+/// Assigns coordinates to blocks, placing each block as far toward the start of its layers as
+/// possible and then moving blocks back toward their successors to close gaps. Returns the
+/// coordinate of every vertex; coordinates increase in the order of `layers`.
+fn horizontal_compaction(
+    layers: &[Vec<V>],
+    root: &[V],
+    horiz: HorizontalDir,
+    separation: &dyn Fn(V, V) -> f64,
+) -> Vec<f64> {
+    let nv = root.len();
+
+    // Build the block graph. Its vertices are block roots, and it has an edge from each block
+    // to each block that immediately follows it in some layer, weighted by the separation.
+    let mut pairs: Vec<(V, V)> = Vec::new();
+    let mut pair_sep: Vec<f64> = Vec::new();
+    for layer in layers.iter() {
+        for w in layer.windows(2) {
+            let (u, v) = (w[0], w[1]);
+            pairs.push((root[u as usize], root[v as usize]));
+            pair_sep.push(match horiz {
+                HorizontalDir::Left => separation(u, v),
+                HorizontalDir::Right => separation(v, u),
+            });
+        }
+    }
+    let (block_graph, edge_pair) = Graph::from_edge_list(nv, &pairs);
+    let order = topo_sort(&block_graph).expect("block graph should be acyclic");
+
+    // First pass: place each block at the smallest coordinate allowed by its predecessors.
+    let mut xs: Vec<f64> = vec![0.0; nv];
+    for &b in order.iter() {
+        for e in block_graph.edges.entry_values_range(b as usize) {
+            let to = block_graph.edges.values[e] as usize;
+            let sep = pair_sep[edge_pair[e] as usize];
+            xs[to] = xs[to].max(xs[b as usize] + sep);
+        }
+    }
+
+    // Second pass: move blocks that have successors as close to them as possible. Blocks are
+    // only moved toward their successors, so this cannot violate the first pass's constraints.
+    for &b in order.iter().rev() {
+        let limit = block_graph
+            .edges
+            .entry_values_range(b as usize)
+            .map(|e| {
+                let to = block_graph.edges.values[e] as usize;
+                xs[to] - pair_sep[edge_pair[e] as usize]
+            })
+            .fold(f64::INFINITY, f64::min);
+        if limit.is_finite() {
+            xs[b as usize] = xs[b as usize].max(limit);
+        }
+    }
+
+    (0..nv).map(|v| xs[root[v] as usize]).collect()
+}
+
+/// Finds the candidate with the smallest total width, and shifts the other candidates so that
+/// they line up with it: left-compacted candidates share its minimum x, and right-compacted
+/// candidates share its maximum x.
+fn align_to_smallest_width(candidates: &mut [(HorizontalDir, Vec<f64>)], v_width: &[f64]) {
+    let width = |xs: &[f64]| {
+        let (lo, hi) = xs
+            .iter()
+            .zip(v_width.iter())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), (&x, &w)| {
+                (lo.min(x - w / 2.0), hi.max(x + w / 2.0))
+            });
+        hi - lo
+    };
+    let min_of = |xs: &[f64]| xs.iter().copied().fold(f64::INFINITY, f64::min);
+    let max_of = |xs: &[f64]| xs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    let mut smallest = 0;
+    for i in 1..candidates.len() {
+        if width(&candidates[i].1) < width(&candidates[smallest].1) {
+            smallest = i;
+        }
+    }
+    let align_min = min_of(&candidates[smallest].1);
+    let align_max = max_of(&candidates[smallest].1);
+
+    for (horiz, xs) in candidates.iter_mut() {
+        let delta = match horiz {
+            HorizontalDir::Left => align_min - min_of(xs),
+            HorizontalDir::Right => align_max - max_of(xs),
+        };
+        for x in xs.iter_mut() {
+            *x += delta;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layering::create_proper_graph;
+    use crate::ordering::{minimize_crossings, OrderingOptions};
+    use crate::testing::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn layout(graph: &Graph, v_width: impl Fn(V) -> f64, node_sep: f64) -> (ProperGraph, Vec<f64>) {
+        let mut pg = create_proper_graph(graph).unwrap();
+        minimize_crossings(&mut pg, &OrderingOptions::default());
+        let widths: Vec<f64> = (0..pg.num_verts() as V).map(v_width).collect();
+        let x = assign_x_coordinates(&pg, &widths, node_sep);
+        println!("order: {:?}\nx: {:?}", pg.verts, x);
+
+        // Adjacent vertices must not overlap.
+        for layer in pg.verts.iter() {
+            for w in layer.windows(2) {
+                let (u, v) = (w[0] as usize, w[1] as usize);
+                let sep = (widths[u] + widths[v]) / 2.0 + node_sep;
+                assert!(x[v] - x[u] >= sep - EPSILON, "v{} and v{} overlap", u, v);
+            }
+        }
+        (pg, x)
+    }
+
+    #[test]
+    fn single_vertex() {
+        init_test();
+        let mut graph = Graph::new();
+        graph.finish_from();
+        let (_, x) = layout(&graph, |_| 10.0, 5.0);
+        assert_eq!(x, vec![5.0]);
+    }
+
+    #[test]
+    fn chain_is_vertical() {
+        init_test();
+        let (_, x) = layout(&graph_from_paths(&[&[0, 1, 2, 3]]), |_| 10.0, 5.0);
+        assert_eq!(x, vec![5.0; 4]);
+    }
+
+    #[test]
+    fn parent_is_centered_over_children() {
+        init_test();
+        let (_, x) = layout(&graph_from_paths(&[&[0, 1], &[0, 2]]), |_| 10.0, 10.0);
+        assert!((x[1] - 5.0).abs() < EPSILON);
+        assert!((x[2] - 25.0).abs() < EPSILON);
+        assert!((x[0] - 15.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn long_edges_are_straight() {
+        init_test();
+
+        // 0 -> 5 spans four layers, and crosses the short edges around it.
+        let graph = graph_from_paths(&[
+            &[0, 1, 2, 3, 4, 5],
+            &[0, 5],
+            &[6, 7, 8, 9, 5],
+            &[0, 7],
+            &[6, 2],
+        ]);
+        let (pg, x) = layout(&graph, |v| if v < 10 { 20.0 } else { 0.0 }, 10.0);
+        for (e, virts) in pg.edge_virts.iter().enumerate() {
+            if virts.len() < 2 {
+                continue;
+            }
+            for &v in virts.iter() {
+                assert!(
+                    (x[v as usize] - x[virts[0] as usize]).abs() < EPSILON,
+                    "edge {} is not straight",
+                    e
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn segment_levels_override_medians() {
+        init_test();
+
+        // 3 has the upper neighbors 0, 1 and 2, and 0 has the lower neighbors 3, 4 and 5. The
+        // edge 0 -> 3 is kept vertical, even though neither end is the median of the other's
+        // neighbors.
+        let graph = graph_from_paths(&[&[0, 3], &[1, 3], &[2, 3], &[0, 4], &[0, 5]]);
+        let mut pg = create_proper_graph(&graph).unwrap();
+        minimize_crossings(&mut pg, &OrderingOptions::default());
+        let x = assign_x_coordinates_with(
+            &pg,
+            &[10.0; 6],
+            &|u, v| ((u, v) == (0, 3) || (u, v) == (3, 0)) as u32,
+            &|_, _| 20.0,
+        );
+        println!("order: {:?}\nx: {:?}", pg.verts, x);
+        assert!((x[0] - x[3]).abs() < EPSILON);
+    }
+
+    #[test]
+    fn wide_vertices_are_separated() {
+        init_test();
+        let graph = graph_from_paths(&[&[0, 1], &[0, 2], &[0, 3], &[4, 3]]);
+        layout(&graph, |v| (v + 1) as f64 * 7.0, 3.0);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::layering::ProperGraph;
+use crate::ramp_table::RampTable;
+use crate::V;
+
+/// Counts the crossings between the edges that connect two adjacent layers.
+///
+/// `edges` contains `(upper, lower)` pairs, where `upper` is a vertex in the upper layer and
+/// `lower` is a vertex in the lower layer. (This is the form of each entry of
+/// `ProperGraph::edges`.) `v_pos` gives the position of every vertex within its layer, and
+/// `num_lower` is the number of vertices in the lower layer. Edges that share an endpoint do
+/// not cross, and parallel edges do not cross each other.
+///
+/// This uses the accumulator tree method of Barth, Jünger and Mutzel ("Simple and Efficient
+/// Bilayer Cross Counting"). The edges are sorted by their endpoint positions, using radix sort,
+/// and then the inversions in the sequence of lower positions are counted. This takes
+/// `O(E log V)` time.
+pub fn count_bilayer_crossings(edges: &[(V, V)], v_pos: &[u32], num_lower: usize) -> u64 {
+    if edges.len() < 2 {
+        return 0;
+    }
+
+    // Radix sort the edges by (upper position, lower position). First sort by the lower
+    // position, then do a stable sort by the upper position.
+    let num_upper = edges
+        .iter()
+        .map(|&(upper, _)| v_pos[upper as usize] as usize + 1)
+        .max()
+        .unwrap();
+    let by_lower = counting_sort(edges, num_lower, |&(_, lower)| v_pos[lower as usize]);
+    let sorted = counting_sort(&by_lower, num_upper, |&(upper, _)| v_pos[upper as usize]);
+
+    // Build the accumulator tree. It is a complete binary tree whose leaves are the positions
+    // in the lower layer.
+    let mut first_index: usize = 1;
+    while first_index < num_lower {
+        first_index *= 2;
+    }
+    let tree_size = 2 * first_index - 1;
+    first_index -= 1;
+    let mut tree: Vec<u64> = vec![0; tree_size];
+
+    // For each edge, count the edges that were inserted earlier whose lower positions are
+    // greater; each of those crosses this edge.
+    let mut count: u64 = 0;
+    for &(_, lower) in sorted.iter() {
+        let mut index = v_pos[lower as usize] as usize + first_index;
+        tree[index] += 1;
+        while index > 0 {
+            if index % 2 == 1 {
+                count += tree[index + 1];
+            }
+            index = (index - 1) / 2;
+            tree[index] += 1;
+        }
+    }
+    count
+}
+
+/// Stable counting sort of `items`, where `key` returns values in `0..num_keys`.
+fn counting_sort<T: Copy>(items: &[T], num_keys: usize, key: impl Fn(&T) -> u32) -> Vec<T> {
+    let mut start: Vec<usize> = vec![0; num_keys + 1];
+    for item in items.iter() {
+        start[key(item) as usize + 1] += 1;
+    }
+    for i in 1..start.len() {
+        start[i] += start[i - 1];
+    }
+    let mut sorted: Vec<T> = items.to_vec();
+    for item in items.iter() {
+        let slot = &mut start[key(item) as usize];
+        sorted[*slot] = *item;
+        *slot += 1;
+    }
+    sorted
+}
+
+/// Counts all of the edge crossings in a layered drawing.
+///
+/// `layer_edges` has one key for each pair of adjacent layers; key `L` holds the edges between
+/// layer `L + 1` and layer `L`, as `(upper, lower)` pairs. `layer_verts` has one key for each
+/// layer, and `v_pos` gives the position of every vertex within its layer.
+pub fn count_layered_crossings(
+    layer_edges: &RampTable<(V, V)>,
+    layer_verts: &RampTable<V>,
+    v_pos: &[u32],
+) -> u64 {
+    layer_edges
+        .iter()
+        .enumerate()
+        .map(|(lower_layer, edges)| {
+            let num_lower = layer_verts.entry_values(lower_layer).len();
+            count_bilayer_crossings(edges, v_pos, num_lower)
+        })
+        .sum()
+}
+
+/// Counts all of the edge crossings in a proper graph, using the current order of its layers.
+pub fn count_crossings(pg: &ProperGraph) -> u64 {
+    count_layered_crossings(&pg.edges, &pg.verts, &pg.v_pos)
+}
+
+/// Counts the crossings between edges that share an endpoint, which `count_crossings` does not
+/// count. Such edges cross if they attach to different ports on their shared vertex, in the
+/// opposite order from their other ends.
+///
+/// `port_offsets` is parallel to `pg.edges.all_values()`, and gives the offset of each edge's
+/// port on its `from` vertex and on its `to` vertex, in units of positions. If it is empty,
+/// every edge attaches to the centers of its vertices, and there are no such crossings.
+pub fn count_port_crossings(pg: &ProperGraph, port_offsets: &[(f64, f64)]) -> u64 {
+    if port_offsets.is_empty() {
+        return 0;
+    }
+    let mut count = 0;
+    // (from, to, from x, to x) of each edge between a pair of adjacent layers
+    let mut ends: Vec<(V, V, f64, f64)> = Vec::new();
+    for range in (0..pg.edges.len()).map(|layer| pg.edges.entry_values_range(layer)) {
+        ends.clear();
+        ends.extend(
+            pg.edges.values[range.clone()]
+                .iter()
+                .zip(port_offsets[range].iter())
+                .map(|(&(from, to), &(from_port, to_port))| {
+                    let from_x = pg.v_pos[from as usize] as f64 + from_port;
+                    let to_x = pg.v_pos[to as usize] as f64 + to_port;
+                    (from, to, from_x, to_x)
+                }),
+        );
+        let crossed = |a: &(V, V, f64, f64), b: &(V, V, f64, f64)| (a.2 - b.2) * (a.3 - b.3) < 0.0;
+
+        // Pairs that share a `from` vertex, and then pairs that share only a `to` vertex.
+        ends.sort_by_key(|&(from, _, _, _)| from);
+        let mut start = 0;
+        while start < ends.len() {
+            let from = ends[start].0;
+            let end = start + ends[start..].iter().take_while(|e| e.0 == from).count();
+            let group = &ends[start..end];
+            for (i, a) in group.iter().enumerate() {
+                count += group[i + 1..].iter().filter(|b| crossed(a, b)).count() as u64;
+            }
+            start = end;
+        }
+        ends.sort_by_key(|&(_, to, _, _)| to);
+        let mut start = 0;
+        while start < ends.len() {
+            let to = ends[start].1;
+            let end = start + ends[start..].iter().take_while(|e| e.1 == to).count();
+            let group = &ends[start..end];
+            for (i, a) in group.iter().enumerate() {
+                count += group[i + 1..]
+                    .iter()
+                    .filter(|b| a.0 != b.0 && crossed(a, b))
+                    .count() as u64;
+            }
+            start = end;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    /// Counts crossings by comparing every pair of edges.
+    fn count_bilayer_crossings_simple(edges: &[(V, V)], v_pos: &[u32]) -> u64 {
+        let mut count = 0;
+        for (i, &(u1, l1)) in edges.iter().enumerate() {
+            for &(u2, l2) in edges[i + 1..].iter() {
+                let (pu1, pu2) = (v_pos[u1 as usize], v_pos[u2 as usize]);
+                let (pl1, pl2) = (v_pos[l1 as usize], v_pos[l2 as usize]);
+                if (pu1 < pu2 && pl1 > pl2) || (pu1 > pu2 && pl1 < pl2) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn count_bilayer_crossings_test() {
+        init_test();
+
+        // Upper layer: verts 0..3, lower layer: verts 3..8. Positions are v_pos.
+        let v_pos = [0, 1, 2, 0, 1, 2, 3, 4];
+        fn case(description: &str, edges: &[(V, V)], v_pos: &[u32], expected: u64) {
+            assert_eq!(
+                count_bilayer_crossings(edges, v_pos, 5),
+                expected,
+                "{}",
+                description
+            );
+            assert_eq!(count_bilayer_crossings_simple(edges, v_pos), expected);
+        }
+
+        case("empty", &[], &v_pos, 0);
+        case("one edge", &[(0, 3)], &v_pos, 0);
+        case("parallel", &[(0, 3), (1, 4), (2, 5)], &v_pos, 0);
+        case("crossed", &[(0, 4), (1, 3)], &v_pos, 1);
+        case("shared upper", &[(0, 3), (0, 7), (1, 5)], &v_pos, 1);
+        case("shared lower", &[(0, 5), (2, 5), (1, 3)], &v_pos, 1);
+        case("multi-edge", &[(0, 7), (0, 7), (1, 3)], &v_pos, 2);
+        case("reversed", &[(0, 7), (1, 6), (2, 5)], &v_pos, 3);
+        case(
+            "several crossings",
+            &[(0, 3), (0, 4), (1, 3), (1, 5), (1, 6), (2, 4), (2, 7)],
+            &v_pos,
+            3,
+        );
+    }
+
+    #[test]
+    fn count_bilayer_crossings_random_test() {
+        let mut seed: u64 = 0x1234_5678;
+        let mut next = move |n: u32| {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            ((seed >> 33) % n as u64) as u32
+        };
+        for _ in 0..100 {
+            let num_upper = 1 + next(8);
+            let num_lower = 1 + next(8);
+            let v_pos: Vec<u32> = (0..num_upper).chain(0..num_lower).collect();
+            let edges: Vec<(V, V)> = (0..next(20))
+                .map(|_| (next(num_upper), num_upper + next(num_lower)))
+                .collect();
+            assert_eq!(
+                count_bilayer_crossings(&edges, &v_pos, num_lower as usize),
+                count_bilayer_crossings_simple(&edges, &v_pos),
+                "edges: {:?}",
+                edges
+            );
+        }
+    }
+
+    #[test]
+    fn count_crossings_test() {
+        let graph = graph_from_paths(&[&[0, 3], &[1, 2], &[0, 4], &[1, 4]]);
+        let mut pg = crate::layering::create_proper_graph(&graph).unwrap();
+        let layer = pg.v_layer[0] as usize;
+        pg.set_layer_order(layer, &[0, 1]);
+        let lower: Vec<V> = pg.verts.entry_values(0).to_vec();
+        assert_eq!(lower, vec![2, 3, 4]);
+        // 0 -> 3 and 0 -> 4 both cross 1 -> 2.
+        assert_eq!(count_crossings(&pg), 2);
+        pg.set_layer_order(0, &[3, 2, 4]);
+        // 0 -> 3, 1 -> 2 no longer cross, but 0 -> 4 now crosses 1 -> 2.
+        assert_eq!(count_crossings(&pg), 1);
+        pg.set_layer_order(0, &[3, 4, 2]);
+        assert_eq!(count_crossings(&pg), 0);
+    }
+
+    #[test]
+    fn count_port_crossings_test() {
+        // 0 -> 1, 0 -> 2 and 3 -> 2, with the lower layer in the order [1, 2].
+        let graph = graph_from_paths(&[&[0, 1], &[0, 2], &[3, 2]]);
+        let mut pg = crate::layering::create_proper_graph(&graph).unwrap();
+        pg.set_layer_order(1, &[0, 3]);
+        pg.set_layer_order(0, &[1, 2]);
+        assert_eq!(pg.edges.all_values(), &[(0, 1), (0, 2), (3, 2)]);
+        assert_eq!(count_port_crossings(&pg, &[]), 0);
+        assert_eq!(count_port_crossings(&pg, &[(0.0, 0.0); 3]), 0);
+        // The ports of 0 are in the same order as 1 and 2.
+        let same = [(-0.25, 0.0), (0.25, 0.0), (0.0, 0.0)];
+        assert_eq!(count_port_crossings(&pg, &same), 0);
+        // The ports of 0 are in the opposite order.
+        let opposite = [(0.25, 0.0), (-0.25, 0.0), (0.0, 0.0)];
+        assert_eq!(count_port_crossings(&pg, &opposite), 1);
+        // The ports of 2 are in the opposite order from 0 and 3.
+        let into_2 = [(0.0, 0.0), (0.0, 0.25), (0.0, -0.25)];
+        assert_eq!(count_port_crossings(&pg, &into_2), 1);
+    }
+}
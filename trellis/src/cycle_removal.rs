@@ -0,0 +1,339 @@
+// Cycle removal: the first phase of layered graph drawing.
+//
+// Layering requires a directed acyclic graph. This phase finds a set of edges (a "feedback
+// arc set") whose reversal makes the graph acyclic. The edges are only reversed for the purpose
+// of layout; later phases use `CycleRemoval::reversed` to draw them in their original
+// direction.
+
+use crate::graph::Graph;
+use crate::{E, V};
+use core::cmp::Reverse;
+use log::debug;
+use std::collections::BTreeSet;
+
+/// Selects the algorithm used to choose which edges to reverse.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum CycleRemovalMethod {
+    /// The greedy heuristic of Eades, Lin and Smyth. This usually reverses fewer edges than
+    /// `DepthFirst`.
+    #[default]
+    Greedy,
+
+    /// Reverses the back edges found by a depth-first search. This preserves the "natural"
+    /// direction of graphs that were built by traversal, such as call graphs.
+    DepthFirst,
+}
+
+/// The result of cycle removal.
+#[derive(Clone, Debug)]
+pub struct CycleRemoval {
+    /// The acyclic graph. It has the same vertices as the input graph. Reversed edges point in the
+    /// opposite direction from the input, and self-loops are not present.
+    pub dag: Graph,
+
+    /// For each edge in `dag`, the index of the edge in the input graph that it came from.
+    pub dag_edge_orig: Vec<E>,
+
+    /// For each edge in the input graph, the index of the corresponding edge in `dag`, or `None`
+    /// if the edge is a self-loop.
+    pub orig_edge_dag: Vec<Option<E>>,
+
+    /// For each edge in the input graph, `true` if the edge was reversed.
+    pub reversed: Vec<bool>,
+
+    /// The input edges that are self-loops. These cannot be made acyclic by reversal, so they
+    /// are removed from `dag`.
+    pub self_loops: Vec<E>,
+}
+
+impl CycleRemoval {
+    /// Iterates the indices of the input edges that were reversed.
+    pub fn reversed_edges(&self) -> impl Iterator<Item = E> + '_ {
+        self.reversed
+            .iter()
+            .enumerate()
+            .filter(|(_, &r)| r)
+            .map(|(e, _)| e as E)
+    }
+
+    pub fn num_reversed(&self) -> usize {
+        self.reversed.iter().filter(|&&r| r).count()
+    }
+}
+
+/// Finds a set of edges whose reversal makes `graph` acyclic, and builds the acyclic graph.
+///
+/// If `graph` is already acyclic, then no edges are reversed (for either method).
+pub fn remove_cycles(graph: &Graph, method: CycleRemovalMethod) -> CycleRemoval {
+    let reversed = match method {
+        CycleRemovalMethod::Greedy => find_reversed_edges_greedy(graph),
+        CycleRemovalMethod::DepthFirst => find_reversed_edges_dfs(graph),
+    };
+    build_dag(graph, reversed)
+}
+
+/// Builds the acyclic graph, given the set of edges to reverse. Reversing the edges must make
+/// the graph acyclic (apart from self-loops, which are removed).
+pub fn build_dag(graph: &Graph, reversed: Vec<bool>) -> CycleRemoval {
+    let mut pairs: Vec<(V, V)> = Vec::with_capacity(graph.num_edges());
+    let mut pair_orig: Vec<E> = Vec::with_capacity(graph.num_edges());
+    let mut self_loops: Vec<E> = Vec::new();
+    for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+        if from == to {
+            self_loops.push(e as E);
+            continue;
+        }
+        if reversed[e] {
+            pairs.push((to, from));
+        } else {
+            pairs.push((from, to));
+        }
+        pair_orig.push(e as E);
+    }
+
+    let (dag, dag_edge_pair) = Graph::from_edge_list(graph.num_verts(), &pairs);
+    let dag_edge_orig: Vec<E> = dag_edge_pair
+        .iter()
+        .map(|&pair| pair_orig[pair as usize])
+        .collect();
+    let mut orig_edge_dag: Vec<Option<E>> = vec![None; graph.num_edges()];
+    for (dag_e, &orig_e) in dag_edge_orig.iter().enumerate() {
+        orig_edge_dag[orig_e as usize] = Some(dag_e as E);
+    }
+
+    debug!(
+        "cycle removal: reversed {} edges, removed {} self-loops",
+        reversed.iter().filter(|&&r| r).count(),
+        self_loops.len()
+    );
+
+    CycleRemoval {
+        dag,
+        dag_edge_orig,
+        orig_edge_dag,
+        reversed,
+        self_loops,
+    }
+}
+
+/// The Eades-Lin-Smyth heuristic. Builds a vertex sequence by repeatedly removing sinks (which
+/// go to the end of the sequence), sources (which go to the start), and otherwise the vertex
+/// with the largest `out_degree - in_degree`. Edges that point backward in the sequence are
+/// reversed. Ties are broken by vertex number, so the result is deterministic.
+fn find_reversed_edges_greedy(graph: &Graph) -> Vec<bool> {
+    let nv = graph.num_verts();
+    let t_graph = graph.transpose();
+
+    let mut in_degree: Vec<i64> = vec![0; nv];
+    let mut out_degree: Vec<i64> = vec![0; nv];
+    for (from, to) in graph.iter_edges_flattened() {
+        if from != to {
+            out_degree[from as usize] += 1;
+            in_degree[to as usize] += 1;
+        }
+    }
+
+    let delta = |v: usize, in_degree: &[i64], out_degree: &[i64]| out_degree[v] - in_degree[v];
+
+    // Vertices that have not yet been removed, ordered by decreasing delta.
+    let mut by_delta: BTreeSet<(Reverse<i64>, V)> = (0..nv)
+        .map(|v| (Reverse(delta(v, &in_degree, &out_degree)), v as V))
+        .collect();
+    let mut removed: Vec<bool> = vec![false; nv];
+    let mut sinks: Vec<V> = (0..nv as V)
+        .rev()
+        .filter(|&v| out_degree[v as usize] == 0)
+        .collect();
+    let mut sources: Vec<V> = (0..nv as V)
+        .rev()
+        .filter(|&v| in_degree[v as usize] == 0)
+        .collect();
+
+    // s1 is built from the front, s2 from the back.
+    let mut s1: Vec<V> = Vec::with_capacity(nv);
+    let mut s2: Vec<V> = Vec::new();
+
+    while !by_delta.is_empty() {
+        let v = if let Some(v) = sinks.pop() {
+            if removed[v as usize] {
+                continue;
+            }
+            s2.push(v);
+            v
+        } else if let Some(v) = sources.pop() {
+            if removed[v as usize] {
+                continue;
+            }
+            s1.push(v);
+            v
+        } else {
+            let &(_, v) = by_delta.iter().next().unwrap();
+            s1.push(v);
+            v
+        };
+
+        removed[v as usize] = true;
+        by_delta.remove(&(Reverse(delta(v as usize, &in_degree, &out_degree)), v));
+
+        for &to in graph.edges_from(v).iter() {
+            if to == v || removed[to as usize] {
+                continue;
+            }
+            let to = to as usize;
+            by_delta.remove(&(Reverse(delta(to, &in_degree, &out_degree)), to as V));
+            in_degree[to] -= 1;
+            by_delta.insert((Reverse(delta(to, &in_degree, &out_degree)), to as V));
+            if in_degree[to] == 0 {
+                sources.push(to as V);
+            }
+        }
+        for &from in t_graph.edges_from(v).iter() {
+            if from == v || removed[from as usize] {
+                continue;
+            }
+            let from = from as usize;
+            by_delta.remove(&(Reverse(delta(from, &in_degree, &out_degree)), from as V));
+            out_degree[from] -= 1;
+            by_delta.insert((Reverse(delta(from, &in_degree, &out_degree)), from as V));
+            if out_degree[from] == 0 {
+                sinks.push(from as V);
+            }
+        }
+    }
+
+    let mut v_seq: Vec<u32> = vec![0; nv];
+    for (i, &v) in s1.iter().chain(s2.iter().rev()).enumerate() {
+        v_seq[v as usize] = i as u32;
+    }
+
+    graph
+        .iter_edges_flattened()
+        .map(|(from, to)| from != to && v_seq[from as usize] > v_seq[to as usize])
+        .collect()
+}
+
+/// Reverses every edge that points to a vertex that is currently on the depth-first search
+/// stack. Vertices are used as search roots in order of vertex number, sources first.
+fn find_reversed_edges_dfs(graph: &Graph) -> Vec<bool> {
+    let nv = graph.num_verts();
+    let mut reversed: Vec<bool> = vec![false; graph.num_edges()];
+    let mut visited: Vec<bool> = vec![false; nv];
+    let mut on_stack: Vec<bool> = vec![false; nv];
+
+    let mut has_in_edges: Vec<bool> = vec![false; nv];
+    for (from, to) in graph.iter_edges_flattened() {
+        if from != to {
+            has_in_edges[to as usize] = true;
+        }
+    }
+    let roots = (0..nv as V)
+        .filter(|&v| !has_in_edges[v as usize])
+        .chain((0..nv as V).filter(|&v| has_in_edges[v as usize]));
+
+    // Each stack entry is a vertex and the index of its next unexamined edge.
+    let mut work_stack: Vec<(V, usize)> = Vec::new();
+    for root in roots {
+        if visited[root as usize] {
+            continue;
+        }
+        visited[root as usize] = true;
+        on_stack[root as usize] = true;
+        work_stack.push((root, graph.edges.index[root as usize] as usize));
+        while let Some(top) = work_stack.last_mut() {
+            let (v, e) = *top;
+            if e == graph.edges.index[v as usize + 1] as usize {
+                on_stack[v as usize] = false;
+                work_stack.pop();
+                continue;
+            }
+            top.1 += 1;
+            let to = graph.edges.values[e];
+            if to == v {
+                // Self-loops are handled separately.
+                continue;
+            }
+            if on_stack[to as usize] {
+                debug!("back edge v{} --> v{}, reversing", v, to);
+                reversed[e] = true;
+            } else if !visited[to as usize] {
+                visited[to as usize] = true;
+                on_stack[to as usize] = true;
+                work_stack.push((to, graph.edges.index[to as usize] as usize));
+            }
+        }
+    }
+
+    reversed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+    use crate::topo_sort::topo_sort;
+
+    #[test]
+    fn remove_cycles_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph, expected_num_reversed: usize) {
+            for &method in [CycleRemovalMethod::Greedy, CycleRemovalMethod::DepthFirst].iter() {
+                let removal = remove_cycles(graph, method);
+                println!("--- {} ({:?})\n{:#?}", description, method, removal);
+                assert!(
+                    topo_sort(&removal.dag).is_ok(),
+                    "{}: result should be acyclic",
+                    description
+                );
+                assert_eq!(
+                    removal.num_reversed(),
+                    expected_num_reversed,
+                    "{} ({:?})",
+                    description,
+                    method
+                );
+                assert_eq!(
+                    removal.dag.num_edges() + removal.self_loops.len(),
+                    graph.num_edges()
+                );
+
+                // Every dag edge should be an input edge, possibly reversed.
+                for (dag_e, (from, to)) in removal.dag.iter_edges_flattened().enumerate() {
+                    let orig_e = removal.dag_edge_orig[dag_e];
+                    assert_eq!(removal.orig_edge_dag[orig_e as usize], Some(dag_e as E));
+                    let (orig_from, orig_to) = graph.edge_endpoints(orig_e);
+                    if removal.reversed[orig_e as usize] {
+                        assert_eq!((orig_from, orig_to), (to, from));
+                    } else {
+                        assert_eq!((orig_from, orig_to), (from, to));
+                    }
+                }
+            }
+        }
+
+        case("empty", &Graph::new(), 0);
+        case("acyclic", &graph_from_paths(&[&[0, 1, 2], &[0, 2]]), 0);
+        case("self-edge", &graph_from_paths(&[&[0, 0, 1]]), 0);
+        case("simple loop", &graph_from_paths(&[&[0, 1, 2, 0]]), 1);
+        case(
+            "two loops sharing a vertex",
+            &graph_from_paths(&[&[0, 1, 2, 0], &[2, 3, 4, 2]]),
+            2,
+        );
+        case(
+            "lots of small loops",
+            &graph_from_paths(&[&[1, 2, 3, 4, 5], &[5, 4, 3, 2, 1]]),
+            4,
+        );
+    }
+
+    #[test]
+    fn layering_after_cycle_removal_test() {
+        let graph = graph_from_paths(&[&[0, 1, 2, 3, 0], &[1, 3]]);
+        let removal = remove_cycles(&graph, CycleRemovalMethod::Greedy);
+        let layers = crate::layering::create_layer_map(&removal.dag).unwrap();
+        for (from, to) in removal.dag.iter_edges_flattened() {
+            assert!(layers.v_layer[from as usize] > layers.v_layer[to as usize]);
+        }
+    }
+}
@@ -1,5 +1,19 @@
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+use crate::V;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Error {
-    FoundCycle,
+    /// The graph contains a cycle. The vertices of one cycle are given in path order; the last
+    /// vertex has an edge to the first. A self-loop is reported as a single vertex. Use
+    /// `scc::find_cyclic_components` to find all of the cycles in a graph.
+    FoundCycle(Vec<V>),
     EmptyGraph,
+
+    /// The rank constraints cannot be satisfied, because they conflict with each other or with
+    /// the direction of the edges. The vertices are some of those involved in the conflict.
+    ConflictingRankConstraints(Vec<V>),
+
+    /// The graph is not a rooted tree. The vertices are the roots (the vertices without incoming
+    /// edges) if there is not exactly one; otherwise they are the vertices that have more than
+    /// one incoming edge, or else the vertices that cannot be reached from the root.
+    NotATree(Vec<V>),
 }
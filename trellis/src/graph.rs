@@ -1,5 +1,5 @@
 use crate::ramp_table::RampTable;
-use crate::V;
+use crate::{E, V};
 
 /// Represents a set of edges; a graph.
 ///
@@ -25,6 +25,40 @@ impl Graph {
         }
     }
 
+    /// Builds a graph with `nv` vertices from a list of `(from, to)` pairs, given in any order.
+    ///
+    /// Returns the graph and a table which gives, for each edge in the new graph, the index
+    /// within `pairs` of the pair that it was built from. Pairs that have the same `from`
+    /// vertex keep their relative order.
+    pub fn from_edge_list(nv: usize, pairs: &[(V, V)]) -> (Graph, Vec<E>) {
+        // Count the out-degree of every vertex, then integrate to get the index table.
+        let mut index: Vec<u32> = vec![0; nv + 1];
+        for &(from, to) in pairs.iter() {
+            assert!((from as usize) < nv && (to as usize) < nv);
+            index[from as usize + 1] += 1;
+        }
+        for i in 1..index.len() {
+            index[i] += index[i - 1];
+        }
+
+        let mut next = index.clone();
+        let mut values: Vec<V> = vec![0; pairs.len()];
+        let mut edge_pair: Vec<E> = vec![0; pairs.len()];
+        for (i, &(from, to)) in pairs.iter().enumerate() {
+            let slot = &mut next[from as usize];
+            values[*slot as usize] = to;
+            edge_pair[*slot as usize] = i as E;
+            *slot += 1;
+        }
+
+        (
+            Graph {
+                edges: RampTable { index, values },
+            },
+            edge_pair,
+        )
+    }
+
     pub fn edges(&self) -> &RampTable<V> {
         &self.edges
     }
@@ -53,6 +87,14 @@ impl Graph {
         self.edges.entry_values(from as usize)
     }
 
+    /// Returns the `(from, to)` vertices of edge `e`, where `e` is an index into the flattened
+    /// edge list (the same order as `iter_edges_flattened`).
+    pub fn edge_endpoints(&self, e: E) -> (V, V) {
+        // The 'from' vertex is the last key whose range starts at or before 'e'.
+        let from = self.edges.index.partition_point(|&start| start <= e) - 1;
+        (from as V, self.edges.values[e as usize])
+    }
+
     pub fn iter_edges_flattened(&self) -> impl Iterator<Item = (V, V)> + '_ {
         self.edges
             .iter()
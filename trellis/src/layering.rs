@@ -1,5 +1,8 @@
 use crate::error::Error;
 use crate::graph::Graph;
+use crate::ramp_table::{RampTable, RampTableBuilder};
+use crate::{E, V};
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct LayerMap {
@@ -39,6 +42,677 @@ pub fn create_layer_map(graph: &Graph) -> Result<LayerMap, Error> {
     })
 }
 
+/// Assigns every vertex to a layer using network simplex ranking, which minimizes the total
+/// (weighted) length of the edges. This usually produces drawings that are much less tall and
+/// wide than `create_layer_map`, which uses longest-path layering.
+///
+/// `edge_minlen` and `edge_weight` are parallel to the edges of `graph`. For every edge
+/// `f -> t`, `v_layer[f] - v_layer[t] >= edge_minlen[e]`. If any `minlen` is 0, then the layer
+/// map may contain flat edges (edges whose vertices are in the same layer).
+pub fn create_layer_map_network_simplex(
+    graph: &Graph,
+    edge_minlen: &[u32],
+    edge_weight: &[u32],
+) -> Result<LayerMap, Error> {
+    let rank = crate::network_simplex::network_simplex_ranks(graph, edge_minlen, edge_weight)?;
+    Ok(layer_map_from_ranks(&rank))
+}
+
+/// Assigns every vertex to a layer using the Coffman-Graham algorithm, so that no layer
+/// contains more than `max_width` vertices of the graph. (Virtual vertices that are added later
+/// by `create_proper_graph_from_layers` are not counted.) The height of the result is given by
+/// `LayerMap::num_layers`; a smaller `max_width` generally produces a taller layer map. A
+/// `max_width` of 0 means that the width of the layers is not bounded.
+///
+/// The vertices are first labeled in a topological order that prefers vertices whose
+/// predecessors were labeled earliest. Then, starting from the sinks, each vertex is placed in
+/// the lowest layer that is above all of its successors and that is not yet full. Edges that
+/// are implied by other paths (transitive edges) are not removed first, so the bound on height
+/// given by Coffman and Graham only holds for graphs that are already transitively reduced.
+pub fn create_layer_map_coffman_graham(graph: &Graph, max_width: usize) -> Result<LayerMap, Error> {
+    let max_width = if max_width == 0 {
+        usize::MAX
+    } else {
+        max_width
+    };
+    let nv = graph.num_verts();
+
+    // Check for cycles first, so that the caller gets a useful error.
+    crate::topo_sort::topo_sort_reverse(graph)?;
+
+    let t_graph = graph.transpose();
+
+    // Phase 1: label the vertices. A vertex becomes ready when all of its predecessors have been
+    // labeled. Among the ready vertices, choose the one whose predecessor labels, sorted in
+    // decreasing order, are lexicographically smallest. Ties are broken by vertex number.
+    let mut label: Vec<u32> = vec![0; nv];
+    let mut num_unlabeled_preds: Vec<u32> = t_graph
+        .edges
+        .iter()
+        .map(|preds| preds.len() as u32)
+        .collect();
+    let mut ready: BTreeSet<(Vec<u32>, V)> = (0..nv as V)
+        .filter(|&v| num_unlabeled_preds[v as usize] == 0)
+        .map(|v| (Vec::new(), v))
+        .collect();
+    let mut next_label: u32 = 0;
+    let mut labeled_order: Vec<V> = Vec::with_capacity(nv);
+    while let Some(first) = ready.iter().next().cloned() {
+        ready.remove(&first);
+        let v = first.1;
+        label[v as usize] = next_label;
+        next_label += 1;
+        labeled_order.push(v);
+        for &to in graph.edges_from(v).iter() {
+            let count = &mut num_unlabeled_preds[to as usize];
+            *count -= 1;
+            if *count == 0 {
+                let mut key: Vec<u32> = t_graph
+                    .edges_from(to)
+                    .iter()
+                    .map(|&pred| label[pred as usize])
+                    .collect();
+                key.sort_unstable_by(|a, b| b.cmp(a));
+                ready.insert((key, to));
+            }
+        }
+    }
+    assert_eq!(labeled_order.len(), nv);
+
+    // Phase 2: assign layers, starting with the vertex that has the highest label.
+    let mut v_layer: Vec<u32> = vec![0; nv];
+    let mut layer_count: Vec<usize> = Vec::new();
+    for &v in labeled_order.iter().rev() {
+        let mut layer = graph
+            .edges_from(v)
+            .iter()
+            .map(|&to| v_layer[to as usize] + 1)
+            .max()
+            .unwrap_or(0) as usize;
+        while layer < layer_count.len() && layer_count[layer] >= max_width {
+            layer += 1;
+        }
+        if layer == layer_count.len() {
+            layer_count.push(0);
+        }
+        layer_count[layer] += 1;
+        v_layer[v as usize] = layer as u32;
+    }
+
+    Ok(LayerMap {
+        num_layers: layer_count.len().max(1),
+        v_layer,
+    })
+}
+
+/// A constraint on the layers of a set of vertices. These correspond to the `rank` attribute of
+/// subgraphs in Graphviz. In Graphviz, the minimum rank is at the top of the drawing; in a
+/// `LayerMap` the top layer is `num_layers - 1`. So `Min` and `Source` refer to the top layer,
+/// and `Max` and `Sink` refer to layer 0.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RankConstraint {
+    /// All of the vertices are in the same layer.
+    Same(Vec<V>),
+
+    /// All of the vertices are in the top layer. Other vertices may also be in the top layer.
+    Min(Vec<V>),
+
+    /// All of the vertices are in the top layer, and no other vertices are in it.
+    Source(Vec<V>),
+
+    /// All of the vertices are in the bottom layer. Other vertices may also be in the bottom
+    /// layer.
+    Max(Vec<V>),
+
+    /// All of the vertices are in the bottom layer, and no other vertices are in it.
+    Sink(Vec<V>),
+}
+
+impl RankConstraint {
+    pub fn verts(&self) -> &[V] {
+        match self {
+            RankConstraint::Same(verts)
+            | RankConstraint::Min(verts)
+            | RankConstraint::Source(verts)
+            | RankConstraint::Max(verts)
+            | RankConstraint::Sink(verts) => verts,
+        }
+    }
+}
+
+/// Assigns every vertex to a layer using network simplex ranking (as in
+/// `create_layer_map_network_simplex`), while satisfying a set of rank constraints.
+///
+/// The vertices of each constraint are merged into a single vertex before ranking, so all of
+/// them are given the same layer. (All `Min` and `Source` vertices are merged together, and
+/// likewise all `Max` and `Sink` vertices.) Edges between vertices that are merged become flat
+/// edges. The merged top vertex is then given an edge to every source, and every sink is given
+/// an edge to the merged bottom vertex; these edges have `minlen` 1 for `Source` and `Sink`
+/// constraints, so that no other vertices share those layers.
+///
+/// Returns `Error::FoundCycle` if `graph` contains a cycle, and
+/// `Error::ConflictingRankConstraints` if the constraints cannot be satisfied, such as when two
+/// vertices in the same `Same` group are connected by a path, or when an edge points into the
+/// top group.
+pub fn create_layer_map_with_constraints(
+    graph: &Graph,
+    edge_minlen: &[u32],
+    edge_weight: &[u32],
+    constraints: &[RankConstraint],
+) -> Result<LayerMap, Error> {
+    let nv = graph.num_verts();
+    assert_eq!(edge_minlen.len(), graph.num_edges());
+    assert_eq!(edge_weight.len(), graph.num_edges());
+
+    // Report cycles in the input before looking for conflicts between constraints.
+    crate::topo_sort::topo_sort_reverse(graph)?;
+
+    // Merge the vertices of each constraint, using a union-find table. The representative of
+    // each set is its smallest vertex.
+    let mut v_set: Vec<V> = (0..nv as V).collect();
+    fn find(v_set: &mut [V], v: V) -> V {
+        let mut root = v;
+        while v_set[root as usize] != root {
+            root = v_set[root as usize];
+        }
+        let mut v = v;
+        while v_set[v as usize] != root {
+            let next = v_set[v as usize];
+            v_set[v as usize] = root;
+            v = next;
+        }
+        root
+    }
+    fn union(v_set: &mut [V], a: V, b: V) -> V {
+        let a = find(v_set, a);
+        let b = find(v_set, b);
+        let (low, high) = (a.min(b), a.max(b));
+        v_set[high as usize] = low;
+        low
+    }
+
+    let mut top: Option<V> = None;
+    let mut top_exclusive = false;
+    let mut bottom: Option<V> = None;
+    let mut bottom_exclusive = false;
+    for constraint in constraints.iter() {
+        let verts = constraint.verts();
+        let first = match verts.first() {
+            Some(&first) => first,
+            None => continue,
+        };
+        for &v in verts.iter() {
+            assert!((v as usize) < nv, "rank constraint vertex is out of range");
+            union(&mut v_set, first, v);
+        }
+        match constraint {
+            RankConstraint::Same(_) => {}
+            RankConstraint::Min(_) | RankConstraint::Source(_) => {
+                top = Some(match top {
+                    Some(t) => union(&mut v_set, t, first),
+                    None => first,
+                });
+                top_exclusive |= matches!(constraint, RankConstraint::Source(_));
+            }
+            RankConstraint::Max(_) | RankConstraint::Sink(_) => {
+                bottom = Some(match bottom {
+                    Some(b) => union(&mut v_set, b, first),
+                    None => first,
+                });
+                bottom_exclusive |= matches!(constraint, RankConstraint::Sink(_));
+            }
+        }
+    }
+    for v in 0..nv as V {
+        find(&mut v_set, v);
+    }
+    let top = top.map(|t| v_set[t as usize]);
+    let bottom = bottom.map(|b| v_set[b as usize]);
+    if let (Some(t), Some(b)) = (top, bottom) {
+        if v_set[t as usize] == v_set[b as usize] {
+            return Err(Error::ConflictingRankConstraints(vec![t]));
+        }
+    }
+
+    // Build the quotient graph, whose vertices are the representatives. Vertices that are not
+    // representatives have no edges. Edges within a set are flat, and are not ranked.
+    let mut pairs: Vec<(V, V)> = Vec::with_capacity(graph.num_edges());
+    let mut minlen: Vec<u32> = Vec::with_capacity(graph.num_edges());
+    let mut weight: Vec<u32> = Vec::with_capacity(graph.num_edges());
+    let mut has_in_edges: Vec<bool> = vec![false; nv];
+    let mut has_out_edges: Vec<bool> = vec![false; nv];
+    for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+        let set_from = v_set[from as usize];
+        let set_to = v_set[to as usize];
+        if set_from == set_to {
+            continue;
+        }
+        if Some(set_to) == top || Some(set_from) == bottom {
+            return Err(Error::ConflictingRankConstraints(vec![from, to]));
+        }
+        pairs.push((set_from, set_to));
+        minlen.push(edge_minlen[e]);
+        weight.push(edge_weight[e]);
+        has_out_edges[set_from as usize] = true;
+        has_in_edges[set_to as usize] = true;
+    }
+    for set in 0..nv as V {
+        if v_set[set as usize] != set {
+            continue;
+        }
+        if let Some(t) = top {
+            if set != t && !has_in_edges[set as usize] {
+                pairs.push((t, set));
+                minlen.push(top_exclusive as u32);
+                weight.push(0);
+            }
+        }
+        if let Some(b) = bottom {
+            if set != b && !has_out_edges[set as usize] {
+                pairs.push((set, b));
+                minlen.push(bottom_exclusive as u32);
+                weight.push(0);
+            }
+        }
+    }
+
+    let (quotient, quotient_edge_pair) = Graph::from_edge_list(nv, &pairs);
+    let quotient_minlen: Vec<u32> = quotient_edge_pair
+        .iter()
+        .map(|&i| minlen[i as usize])
+        .collect();
+    let quotient_weight: Vec<u32> = quotient_edge_pair
+        .iter()
+        .map(|&i| weight[i as usize])
+        .collect();
+
+    let set_rank = match crate::network_simplex::network_simplex_ranks(
+        &quotient,
+        &quotient_minlen,
+        &quotient_weight,
+    ) {
+        Ok(set_rank) => set_rank,
+        Err(Error::FoundCycle(cycle)) => return Err(Error::ConflictingRankConstraints(cycle)),
+        Err(e) => return Err(e),
+    };
+
+    let rank: Vec<u32> = v_set.iter().map(|&set| set_rank[set as usize]).collect();
+    Ok(layer_map_from_ranks(&rank))
+}
+
+/// Converts ranks (where edges point from lower ranks to higher ranks) to a layer map (where
+/// edges point from higher layers to lower layers).
+fn layer_map_from_ranks(rank: &[u32]) -> LayerMap {
+    let max_rank = rank.iter().copied().max().unwrap_or(0);
+    LayerMap {
+        num_layers: max_rank as usize + 1,
+        v_layer: rank.iter().map(|&r| max_rank - r).collect(),
+    }
+}
+
+/// A layered graph in which every edge connects vertices in adjacent layers, called a
+/// "proper graph".
+///
+/// Edges of the original graph that span more than one layer are replaced by chains of
+/// "virtual" vertices, one in each intermediate layer. All of the vertex numbers of the input
+/// graph are preserved: vertices `0..num_real_verts` are the original vertices, and virtual
+/// vertices are numbered after them. Edge numbers are not preserved, but every virtual vertex
+/// and every proper edge records the original edge that it came from, so that later phases can
+/// reassemble long edges.
+///
+/// Edges whose endpoints are in the same layer (which can happen when rank constraints put
+/// them there) are "flat" edges. They have no virtual vertices, and are kept separately from
+/// the edges between layers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProperGraph {
+    /// The number of vertices in the original graph.
+    pub num_real_verts: usize,
+
+    /// The layer of each vertex, both real and virtual.
+    pub v_layer: Vec<u32>,
+
+    /// For each layer, the vertices in that layer, in left-to-right order.
+    pub verts: RampTable<V>,
+
+    /// The position of each vertex within its layer.
+    /// `verts[v_layer[v]][v_pos[v]] == v`
+    pub v_pos: Vec<u32>,
+
+    /// For each layer `L`, the edges `(from, to)` that go from layer `L + 1` to layer `L`.
+    /// There is one entry for every layer except the top layer.
+    pub edges: RampTable<(V, V)>,
+
+    /// For each proper edge (in the same order as `edges.all_values()`), the index of the
+    /// edge in the original graph.
+    pub edge_orig: Vec<E>,
+
+    /// For each edge in the original graph, the virtual vertices that replace it, in order from
+    /// the `from` vertex to the `to` vertex. Edges that span a single layer have no entries.
+    pub edge_virts: RampTable<V>,
+
+    /// For each virtual vertex `v`, `virt_orig[v - num_real_verts]` is the index of the
+    /// original edge that the virtual vertex belongs to. If the vertex is shared by several
+    /// edges (see `concentrate_edges`), this is the first of them.
+    pub virt_orig: Vec<E>,
+
+    /// For each layer, the flat edges `(from, to)` whose endpoints are both in that layer.
+    pub flat_edges: RampTable<(V, V)>,
+
+    /// For each flat edge (in the same order as `flat_edges.all_values()`), the index of the
+    /// edge in the original graph.
+    pub flat_edge_orig: Vec<E>,
+}
+
+impl ProperGraph {
+    /// The total number of vertices, both real and virtual.
+    pub fn num_verts(&self) -> usize {
+        self.v_layer.len()
+    }
+
+    pub fn num_layers(&self) -> usize {
+        self.verts.len()
+    }
+
+    pub fn is_virtual(&self, v: V) -> bool {
+        v as usize >= self.num_real_verts
+    }
+
+    /// If `v` is a virtual vertex, returns the original edge that it belongs to.
+    pub fn orig_edge_of_virt(&self, v: V) -> Option<E> {
+        if self.is_virtual(v) {
+            Some(self.virt_orig[v as usize - self.num_real_verts])
+        } else {
+            None
+        }
+    }
+
+    /// Returns the full chain of vertices for an original edge, including the real endpoints.
+    pub fn edge_chain(&self, graph: &Graph, e: E) -> Vec<V> {
+        let (from, to) = graph.edge_endpoints(e);
+        let virts = self.edge_virts.entry_values(e as usize);
+        let mut chain = Vec::with_capacity(virts.len() + 2);
+        chain.push(from);
+        chain.extend(virts.iter().copied());
+        chain.push(to);
+        chain
+    }
+
+    /// Replaces the left-to-right order of the vertices in `layer`, and updates `v_pos` to match.
+    /// `order` must be a permutation of the vertices already in that layer.
+    pub fn set_layer_order(&mut self, layer: usize, order: &[V]) {
+        let layer_verts = self.verts.entry_values_mut(layer);
+        assert_eq!(layer_verts.len(), order.len());
+        layer_verts.copy_from_slice(order);
+        for (pos, &v) in order.iter().enumerate() {
+            assert_eq!(self.v_layer[v as usize] as usize, layer);
+            self.v_pos[v as usize] = pos as u32;
+        }
+    }
+}
+
+/// Computes a layer map for `graph` and then builds its proper graph.
+pub fn create_proper_graph(graph: &Graph) -> Result<ProperGraph, Error> {
+    let layers = create_layer_map(graph)?;
+    Ok(create_proper_graph_from_layers(graph, &layers))
+}
+
+/// Given a graph and a layer assignment, constructs the proper graph, which contains "virtual"
+/// vertices so that every edge crosses exactly one layer.
+///
+/// Virtual vertices are numbered after the original vertices, in the order of the original
+/// edges that they belong to. Within each layer, vertices are initially ordered by vertex number.
+///
+/// Every edge must point down (from a higher layer to a lower layer) or be flat.
+pub fn create_proper_graph_from_layers(graph: &Graph, layers: &LayerMap) -> ProperGraph {
+    let nv = graph.num_verts();
+    let v_layer_in = &layers.v_layer;
+    assert_eq!(v_layer_in.len(), nv);
+
+    // Scan the input graph and determine how many virtual vertices the proper graph
+    // will contain. This allows us to allocate the output buffers at their final size.
+    let num_virt: usize = graph
+        .iter_edges_flattened()
+        .map(|(from, to)| {
+            let from_layer = v_layer_in[from as usize];
+            let to_layer = v_layer_in[to as usize];
+            assert!(from_layer >= to_layer, "edges must not point up");
+            (from_layer - to_layer).saturating_sub(1) as usize
+        })
+        .sum();
+
+    let mut v_layer: Vec<u32> = Vec::with_capacity(nv + num_virt);
+    v_layer.extend_from_slice(v_layer_in);
+    let mut virt_orig: Vec<E> = Vec::with_capacity(num_virt);
+    let mut edge_virts: RampTable<V> = RampTable::with_capacity(graph.num_edges(), num_virt);
+
+    // (to_layer, (from, to)) and the original edge of each, in parallel.
+    let mut proper_edges: Vec<(u32, (V, V))> = Vec::with_capacity(graph.num_edges() + num_virt);
+    let mut proper_edges_orig: Vec<E> = Vec::with_capacity(graph.num_edges() + num_virt);
+    let mut flat_edges: Vec<(u32, (V, V))> = Vec::new();
+    let mut flat_edges_orig: Vec<E> = Vec::new();
+
+    for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+        let e = e as E;
+        let from_layer = v_layer_in[from as usize];
+        let to_layer = v_layer_in[to as usize];
+        if from_layer == to_layer {
+            edge_virts.finish_key();
+            flat_edges.push((from_layer, (from, to)));
+            flat_edges_orig.push(e);
+            continue;
+        }
+        let mut prev_v = from;
+        for layer in (to_layer + 1..from_layer).rev() {
+            let virt_v = v_layer.len() as V;
+            v_layer.push(layer);
+            virt_orig.push(e);
+            edge_virts.push_value(virt_v);
+            proper_edges.push((layer, (prev_v, virt_v)));
+            proper_edges_orig.push(e);
+            prev_v = virt_v;
+        }
+        edge_virts.finish_key();
+        proper_edges.push((to_layer, (prev_v, to)));
+        proper_edges_orig.push(e);
+    }
+    assert_eq!(v_layer.len(), nv + num_virt);
+
+    let num_layers = layers.num_layers;
+
+    // Bucket the vertices by layer, in order of vertex number.
+    let mut verts_builder = RampTableBuilder::with_capacity(v_layer.len());
+    verts_builder.extend(
+        v_layer
+            .iter()
+            .enumerate()
+            .map(|(v, &layer)| (layer, v as V)),
+    );
+    let verts = verts_builder.finish_with_len(num_layers);
+
+    let mut v_pos: Vec<u32> = vec![0; v_layer.len()];
+    for layer_verts in verts.iter() {
+        for (pos, &v) in layer_verts.iter().enumerate() {
+            v_pos[v as usize] = pos as u32;
+        }
+    }
+
+    // Bucket the edges by the layer of their 'to' vertex. We carry the original edge
+    // index along with each edge so that the two tables stay parallel.
+    let mut edges_builder = RampTableBuilder::with_capacity(proper_edges.len());
+    edges_builder.extend(
+        proper_edges
+            .iter()
+            .zip(proper_edges_orig.iter())
+            .map(|(&(layer, edge), &e)| (layer, (edge, e))),
+    );
+    let (edges, edge_orig) = split_edge_table(edges_builder.finish_with_len(num_layers - 1));
+
+    let mut flat_builder = RampTableBuilder::with_capacity(flat_edges.len());
+    flat_builder.extend(
+        flat_edges
+            .iter()
+            .zip(flat_edges_orig.iter())
+            .map(|(&(layer, edge), &e)| (layer, (edge, e))),
+    );
+    let (flat_edges, flat_edge_orig) = split_edge_table(flat_builder.finish_with_len(num_layers));
+
+    ProperGraph {
+        num_real_verts: nv,
+        v_layer,
+        verts,
+        v_pos,
+        edges,
+        edge_orig,
+        edge_virts,
+        virt_orig,
+        flat_edges,
+        flat_edge_orig,
+    }
+}
+
+/// Merges the chains of virtual vertices of edges that share an endpoint, so that a bundle of
+/// edges is drawn as a single edge that splits near the ends where the edges differ.
+///
+/// Only edges that are in the same group can be merged, and edges whose group is `None` are
+/// never merged. First, the chains of edges that have the same `from` vertex are merged, going
+/// down from that vertex for as long as both edges have virtual vertices. Then the chains of
+/// edges that have the same `to` vertex are merged going up, using only the virtual vertices
+/// that were not merged in the first step, so that every merged chain forms a tree.
+///
+/// Each original edge keeps its chain in `edge_virts`, but the chains now share vertices, and
+/// proper edges that connect the same vertices are kept only once (with the first of their
+/// original edges in `edge_orig`). The virtual vertices are renumbered, in their original order.
+pub fn concentrate_edges(pg: &mut ProperGraph, graph: &Graph, groups: &[Option<u32>]) {
+    let nv = pg.num_real_verts;
+    let num_virt = pg.num_verts() - nv;
+
+    // Each virtual vertex is replaced by the first vertex that it is merged with.
+    let mut merged_into: Vec<V> = (nv as V..pg.num_verts() as V).collect();
+    let mut merged: Vec<bool> = vec![false; num_virt];
+    for &to_end in [false, true].iter() {
+        let mut bundles: BTreeMap<(u32, V), Vec<E>> = BTreeMap::new();
+        for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+            if let Some(Some(group)) = groups.get(e) {
+                if !pg.edge_virts.entry_values(e).is_empty() {
+                    let end = if to_end { to } else { from };
+                    bundles.entry((*group, end)).or_default().push(e as E);
+                }
+            }
+        }
+
+        // The i-th virtual vertex of an edge, counting from the shared end.
+        let virt_at = |e: E, i: usize| -> Option<V> {
+            let virts = pg.edge_virts.entry_values(e as usize);
+            if i >= virts.len() {
+                None
+            } else if to_end {
+                Some(virts[virts.len() - 1 - i])
+            } else {
+                Some(virts[i])
+            }
+        };
+        let mut newly_merged: Vec<usize> = Vec::new();
+        for bundle in bundles.values_mut() {
+            let mut i = 0;
+            while bundle.len() > 1 {
+                bundle.retain(|&e| match virt_at(e, i) {
+                    Some(v) => !merged[v as usize - nv],
+                    None => false,
+                });
+                if let Some(&first) = bundle.first() {
+                    let target = virt_at(first, i).unwrap();
+                    for &e in bundle[1..].iter() {
+                        let v = virt_at(e, i).unwrap() as usize - nv;
+                        merged_into[v] = target;
+                        newly_merged.push(v);
+                    }
+                    if bundle.len() > 1 {
+                        newly_merged.push(target as usize - nv);
+                    }
+                }
+                i += 1;
+            }
+        }
+        for v in newly_merged {
+            merged[v] = true;
+        }
+    }
+
+    // Renumber the vertices that remain.
+    let mut new_index: Vec<V> = (0..nv as V).collect();
+    let mut v_layer: Vec<u32> = pg.v_layer[..nv].to_vec();
+    let mut virt_orig: Vec<E> = Vec::new();
+    for (i, &target) in merged_into.iter().enumerate() {
+        let v = nv + i;
+        if target as usize == v {
+            new_index.push(v_layer.len() as V);
+            v_layer.push(pg.v_layer[v]);
+            virt_orig.push(pg.virt_orig[i]);
+        } else {
+            new_index.push(new_index[target as usize]);
+        }
+    }
+    let map = |v: V| new_index[v as usize];
+
+    let mut edge_virts: RampTable<V> =
+        RampTable::with_capacity(pg.edge_virts.len(), pg.edge_virts.num_values());
+    for virts in pg.edge_virts.iter() {
+        edge_virts.push_entry_extend(virts.iter().map(|&v| map(v)));
+    }
+
+    let mut seen: BTreeSet<(V, V)> = BTreeSet::new();
+    let mut edges: RampTable<(V, V)> = RampTable::with_capacity(pg.edges.len(), 0);
+    let mut edge_orig: Vec<E> = Vec::new();
+    for layer in 0..pg.edges.len() {
+        for (i, &(from, to)) in pg
+            .edges
+            .entry_values_range(layer)
+            .zip(pg.edges.entry_values(layer))
+        {
+            let edge = (map(from), map(to));
+            if seen.insert(edge) {
+                edges.push_value(edge);
+                edge_orig.push(pg.edge_orig[i]);
+            }
+        }
+        edges.finish_key();
+    }
+
+    let mut verts_builder = RampTableBuilder::with_capacity(v_layer.len());
+    verts_builder.extend(
+        v_layer
+            .iter()
+            .enumerate()
+            .map(|(v, &layer)| (layer, v as V)),
+    );
+    let verts = verts_builder.finish_with_len(pg.num_layers());
+    let mut v_pos: Vec<u32> = vec![0; v_layer.len()];
+    for layer_verts in verts.iter() {
+        for (pos, &v) in layer_verts.iter().enumerate() {
+            v_pos[v as usize] = pos as u32;
+        }
+    }
+
+    pg.v_layer = v_layer;
+    pg.verts = verts;
+    pg.v_pos = v_pos;
+    pg.edges = edges;
+    pg.edge_orig = edge_orig;
+    pg.edge_virts = edge_virts;
+    pg.virt_orig = virt_orig;
+}
+
+/// Splits a table of `(edge, original edge)` pairs into a table of edges and a parallel list of
+/// original edges.
+fn split_edge_table(table: RampTable<((V, V), E)>) -> (RampTable<(V, V)>, Vec<E>) {
+    let orig: Vec<E> = table.values.iter().map(|&(_, e)| e).collect();
+    let edges = RampTable {
+        index: table.index,
+        values: table.values.iter().map(|&(edge, _)| edge).collect(),
+    };
+    (edges, orig)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -80,115 +754,276 @@ mod tests {
             &graph_from_paths(&[&[10, 11, 1, 12, 13], &[20, 21, 1, 22, 23]]),
         );
     }
-}
 
-/// Given a graph, constructs a new graph that contains "virtual" edges and nodes,
-/// called a "proper graph". In a proper graph, each edge crosses exactly one layer.
-///
-/// All of the vertex numbers of the input graph are preserved in the proper graph.
-/// However, edge numbers are not preserved, because the edges in the proper graph
-/// are usually virtual edges. No effort is made to preserve edge numbers. Because
-/// this function creates virtual vertices, those are numbered after the original
-/// vertices (in the output).
-///
-/// The algorithm first scans the input and determines the number of edges that will
-/// be created for each vertex. This is used to construct a RampTable. Placing the
-/// edges is relatively easy.
-///
-pub fn create_proper_graph(graph: &Graph, layers: &LayerMap) -> Graph {
-    let nv = graph.num_verts();
-    let v_layer = &layers.v_layer;
-    let mut next_virt_v: u32 = nv as u32;
-
-    // Scan the input graph and determine how many edges the proper graph
-    // will contain. This allows us to allocate the output graph buffers
-    // at their final size, avoiding reallocations.
-    let proper_num_virt_v: usize = graph
-        .iter_from_edges()
-        .map(|(from, to_list)| {
-            let from_layer = v_layer[from as usize];
-            to_list
-                .iter()
-                .map(move |&to| {
-                    let to_layer = v_layer[to as usize];
-                    assert!(from_layer > to_layer);
-                    from_layer - to_layer - 1
-                })
-                .sum::<u32>()
-        })
-        .sum::<u32>() as usize;
+    #[test]
+    fn create_layer_map_network_simplex_test() {
+        init_test();
 
-    let expected_num_vert_proper = graph.num_verts() + proper_num_virt_v;
-    let expected_num_edge_proper = graph.num_edges() + proper_num_virt_v;
+        // Longest-path layering puts every sink in the bottom layer, so the edge 0 -> 10
+        // would span all three layers. Network simplex keeps it short.
+        let graph = graph_from_paths(&[&[0, 1, 2, 3], &[0, 10]]);
+        let ne = graph.num_edges();
+        let longest_path = create_layer_map(&graph).unwrap();
+        assert_eq!(longest_path.v_layer[10], 0);
 
-    let mut proper_from: Vec<u32> = Vec::with_capacity(expected_num_edge_proper);
-    let mut proper_to: Vec<u32> = Vec::with_capacity(expected_num_edge_proper);
+        let layer_map =
+            create_layer_map_network_simplex(&graph, &vec![1; ne], &vec![1; ne]).unwrap();
+        assert_eq!(layer_map.num_layers, 4);
+        assert_eq!(layer_map.v_layer[0], 3);
+        assert_eq!(layer_map.v_layer[3], 0);
+        assert_eq!(layer_map.v_layer[10], 2);
+        for (from, to) in graph.iter_edges_flattened() {
+            assert!(layer_map.v_layer[from as usize] > layer_map.v_layer[to as usize]);
+        }
+    }
 
-    let mut add_edge = |f, t| {
-        proper_from.push(f);
-        proper_to.push(t);
-    };
+    #[test]
+    fn create_layer_map_coffman_graham_test() {
+        init_test();
 
-    for (from, to) in graph.iter_edges_flattened() {
-        let from = from as u32;
-        let from_layer = layers.v_layer[from as usize];
-        let to_layer = layers.v_layer[to as usize];
-        assert!(from_layer > to_layer);
-        let span = from_layer - to_layer;
-        let mut prev_v = from;
-        for i in 0..span - 1 {
-            let virt_v = next_virt_v;
-            next_virt_v += 1;
-            add_edge(prev_v, virt_v);
-            prev_v = virt_v;
+        fn case(description: &str, graph: &Graph, max_width: usize, expected_height: usize) {
+            let layer_map = create_layer_map_coffman_graham(graph, max_width).unwrap();
+            println!(
+                "--- {} (max_width {})\nlayer_map: {:?}",
+                description, max_width, layer_map
+            );
+            assert_eq!(layer_map.num_layers, expected_height, "{}", description);
+            for (from, to) in graph.iter_edges_flattened() {
+                assert!(layer_map.v_layer[from as usize] > layer_map.v_layer[to as usize]);
+            }
+            let mut layer_count = vec![0; layer_map.num_layers];
+            for &layer in layer_map.v_layer.iter() {
+                layer_count[layer as usize] += 1;
+            }
+            assert!(max_width == 0 || layer_count.iter().all(|&count| count <= max_width));
         }
-        add_edge(prev_v, to);
+
+        case("empty", &Graph::new(), 3, 1);
+        case("linear path", &graph_from_paths(&[&[0, 1, 2]]), 1, 3);
+
+        let fan_out = graph_from_paths(&[&[0, 1], &[0, 2], &[0, 3], &[0, 4], &[0, 5], &[0, 6]]);
+        case("fan out, unbounded", &fan_out, 6, 2);
+        case("fan out, no bound", &fan_out, 0, 2);
+        case("fan out, width 2", &fan_out, 2, 4);
+        case("fan out, width 1", &fan_out, 1, 7);
+
+        case(
+            "two paths, width 1",
+            &graph_from_paths(&[&[0, 1, 2], &[3, 4, 5]]),
+            1,
+            6,
+        );
+
+        assert!(matches!(
+            create_layer_map_coffman_graham(&graph_from_paths(&[&[0, 1, 0]]), 2),
+            Err(Error::FoundCycle(_))
+        ));
     }
 
-    // Because of the iteration order, we know that values in 'proper_form'
-    // are in non-decreasing order. Next, we transform that into an index
-    // table for a RampTable.
+    #[test]
+    fn create_layer_map_with_constraints_test() {
+        init_test();
+
+        fn layers(graph: &Graph, constraints: &[RankConstraint]) -> Result<LayerMap, Error> {
+            let ne = graph.num_edges();
+            let result =
+                create_layer_map_with_constraints(graph, &vec![1; ne], &vec![1; ne], constraints);
+            println!("constraints: {:?}\nresult: {:?}", constraints, result);
+            if let Ok(layer_map) = &result {
+                for (from, to) in graph.iter_edges_flattened() {
+                    assert!(layer_map.v_layer[from as usize] >= layer_map.v_layer[to as usize]);
+                }
+            }
+            result
+        }
 
-    let nv_proper = next_virt_v;
+        // same
+        let graph = graph_from_paths(&[&[0, 1, 2], &[0, 3]]);
+        let layer_map = layers(&graph, &[RankConstraint::Same(vec![2, 3])]).unwrap();
+        assert_eq!(layer_map.v_layer[2], layer_map.v_layer[3]);
+        assert_eq!(layer_map.v_layer[2], 0);
 
-    let index = convert_runs_to_index(proper_from.iter().copied(), nv_proper as usize);
+        // Vertices in a same-rank group that are connected by an edge produce a flat edge.
+        let graph = graph_from_paths(&[&[0, 1], &[0, 2], &[1, 2]]);
+        let layer_map = layers(&graph, &[RankConstraint::Same(vec![1, 2])]).unwrap();
+        assert_eq!(layer_map.v_layer[1], layer_map.v_layer[2]);
 
-    unimplemented!();
-}
+        // min: 3 would normally be placed just above 2.
+        let graph = graph_from_paths(&[&[0, 1, 2], &[3, 2]]);
+        let layer_map = layers(&graph, &[RankConstraint::Min(vec![3])]).unwrap();
+        assert_eq!(layer_map.v_layer[3], layer_map.v_layer[0]);
+        assert_eq!(layer_map.v_layer[3] as usize, layer_map.num_layers - 1);
 
-/*
-#[test]
-fn convert_runs_to_index_test() {
-    fn case(input: &[u32], len: usize, expected: &[u32]) {
-        let actual = convert_runs_to_index(input.iter().copied(), len);
-        assert_eq!(
-            actual.as_slice(),
-            expected,
-            "len: {} input: {:?}",
-            len,
-            input
-        );
+        // source: 0 is alone in the top layer.
+        let graph = graph_from_paths(&[&[0, 1], &[2, 3]]);
+        let layer_map = layers(&graph, &[RankConstraint::Source(vec![0])]).unwrap();
+        assert_eq!(layer_map.v_layer[0] as usize, layer_map.num_layers - 1);
+        assert!(layer_map.v_layer[2] < layer_map.v_layer[0]);
+
+        // max and sink
+        let graph = graph_from_paths(&[&[0, 1, 2], &[0, 3]]);
+        let layer_map = layers(&graph, &[RankConstraint::Max(vec![3])]).unwrap();
+        assert_eq!(layer_map.v_layer[3], 0);
+        assert_eq!(layer_map.v_layer[2], 0);
+        let layer_map = layers(&graph, &[RankConstraint::Sink(vec![2])]).unwrap();
+        assert_eq!(layer_map.v_layer[2], 0);
+        assert!(layer_map.v_layer[3] > 0);
+
+        // conflicts
+        let graph = graph_from_paths(&[&[0, 1, 2]]);
+        assert!(matches!(
+            layers(&graph, &[RankConstraint::Same(vec![0, 2])]),
+            Err(Error::ConflictingRankConstraints(_))
+        ));
+        assert!(matches!(
+            layers(&graph, &[RankConstraint::Min(vec![1])]),
+            Err(Error::ConflictingRankConstraints(_))
+        ));
+        assert!(matches!(
+            layers(&graph, &[RankConstraint::Sink(vec![1])]),
+            Err(Error::ConflictingRankConstraints(_))
+        ));
+        assert!(matches!(
+            layers(
+                &graph,
+                &[RankConstraint::Min(vec![0]), RankConstraint::Max(vec![0])]
+            ),
+            Err(Error::ConflictingRankConstraints(_))
+        ));
     }
 
-    case(&[0, 0, 1, 4, 7, 7], 10, &[0, 2]);
-}
-*/
+    #[test]
+    fn create_proper_graph_test() {
+        init_test();
 
-fn convert_runs_to_index(iter: impl Iterator<Item = u32>, len: usize) -> Vec<u32> {
-    let mut index: Vec<u32> = Vec::with_capacity(len + 1);
-    index.push(0);
-    let mut base: u32 = 0;
-    for key in iter {
-        assert!((key as usize) < len);
-        while index.len() <= key as usize {
-            index.push(base);
+        fn check(description: &str, graph: &Graph) -> ProperGraph {
+            let pg = create_proper_graph(graph).unwrap();
+            println!(
+                "--- {}
+proper graph: {:#?}",
+                description, pg
+            );
+
+            for (layer, layer_verts) in pg.verts.iter().enumerate() {
+                for (pos, &v) in layer_verts.iter().enumerate() {
+                    assert_eq!(pg.v_layer[v as usize] as usize, layer);
+                    assert_eq!(pg.v_pos[v as usize] as usize, pos);
+                }
+            }
+            assert_eq!(pg.edge_orig.len(), pg.edges.num_values());
+            for (to_layer, layer_edges) in pg.edges.iter().enumerate() {
+                for &(from, to) in layer_edges.iter() {
+                    assert_eq!(pg.v_layer[from as usize] as usize, to_layer + 1);
+                    assert_eq!(pg.v_layer[to as usize] as usize, to_layer);
+                }
+            }
+            for e in 0..graph.num_edges() as E {
+                let chain = pg.edge_chain(graph, e);
+                for w in chain.windows(2) {
+                    assert_eq!(pg.v_layer[w[0] as usize], pg.v_layer[w[1] as usize] + 1);
+                }
+                for &v in chain[1..chain.len() - 1].iter() {
+                    assert_eq!(pg.orig_edge_of_virt(v), Some(e));
+                }
+            }
+            pg
         }
-        base += 1;
+
+        let pg = check("empty", &Graph::new());
+        assert_eq!(pg.num_verts(), 0);
+
+        let pg = check("linear path", &graph_from_paths(&[&[0, 1, 2]]));
+        assert_eq!(pg.num_verts(), 3);
+        assert_eq!(pg.num_layers(), 3);
+
+        // 0 -> 1 -> 2 -> 3, plus a long edge 0 -> 3, which needs two virtual vertices.
+        let graph = graph_from_paths(&[&[0, 1, 2, 3], &[0, 3]]);
+        let pg = check("long edge", &graph);
+        assert_eq!(pg.num_verts(), 6);
+        assert_eq!(pg.edge_virts.entry_values(1), &[4, 5]);
+        assert_eq!(pg.edge_chain(&graph, 1), vec![0, 4, 5, 3]);
+        assert_eq!(pg.virt_orig, vec![1, 1]);
+
+        check(
+            "two linear paths, connected at middle",
+            &graph_from_paths(&[&[10, 11, 1, 12, 13], &[20, 21, 1, 22, 23], &[10, 23]]),
+        );
     }
-    while index.len() < len {
-        index.push(base);
+
+    #[test]
+    fn create_proper_graph_flat_edges_test() {
+        init_test();
+
+        // 0 -> 1 and 0 -> 2, where 1 -> 2 is flat, plus a long edge 0 -> 3.
+        let graph = graph_from_paths(&[&[0, 1, 2], &[0, 2], &[0, 3]]);
+        let layers = LayerMap {
+            num_layers: 3,
+            v_layer: vec![2, 1, 1, 0],
+        };
+        let pg = create_proper_graph_from_layers(&graph, &layers);
+        println!("proper graph: {:#?}", pg);
+
+        assert_eq!(pg.num_verts(), 5);
+        assert_eq!(pg.flat_edges.len(), 3);
+        assert!(pg.flat_edges.entry_values(0).is_empty());
+        assert_eq!(pg.flat_edges.entry_values(1), &[(1, 2)]);
+        assert!(pg.flat_edges.entry_values(2).is_empty());
+        let flat_e = pg.flat_edge_orig[0];
+        assert_eq!(graph.edge_endpoints(flat_e), (1, 2));
+        assert!(pg.edge_virts.entry_values(flat_e as usize).is_empty());
+        assert_eq!(pg.edge_chain(&graph, flat_e), vec![1, 2]);
+
+        // The flat edge is not one of the edges between layers: 0 -> 1, 0 -> 2, and the two
+        // halves of 0 -> 3.
+        assert_eq!(pg.edges.num_values(), 4);
+        assert!(!pg.edge_orig.contains(&flat_e));
     }
-    index
-}
 
-// Idea for language: Si# or Si++
+    #[test]
+    fn concentrate_edges_test() {
+        init_test();
+
+        // 0 fans out to 1, 2 and 3, and 4 and 5 fan in to 1. The edge 4 -> 2 is not merged.
+        let (graph, _) =
+            Graph::from_edge_list(6, &[(0, 1), (0, 2), (0, 3), (4, 1), (4, 2), (5, 1)]);
+        let layers = LayerMap {
+            num_layers: 4,
+            v_layer: vec![3, 0, 0, 1, 3, 3],
+        };
+        let mut pg = create_proper_graph_from_layers(&graph, &layers);
+        assert_eq!(pg.num_verts(), 6 + 11);
+        let groups = [Some(0), Some(0), Some(0), Some(0), None, Some(0)];
+        concentrate_edges(&mut pg, &graph, &groups);
+        println!("proper graph: {:#?}", pg);
+
+        for (layer, layer_verts) in pg.verts.iter().enumerate() {
+            for (pos, &v) in layer_verts.iter().enumerate() {
+                assert_eq!(pg.v_layer[v as usize] as usize, layer);
+                assert_eq!(pg.v_pos[v as usize] as usize, pos);
+            }
+        }
+        assert_eq!(pg.edge_orig.len(), pg.edges.num_values());
+        for e in 0..graph.num_edges() as E {
+            let chain = pg.edge_chain(&graph, e);
+            for w in chain.windows(2) {
+                let layer = pg.v_layer[w[1] as usize] as usize;
+                assert!(pg.edges.entry_values(layer).contains(&(w[0], w[1])));
+            }
+        }
+
+        // 0 -> 1 and 0 -> 2 share both virtual vertices, and 0 -> 3 shares the first.
+        let virts = |e: usize| pg.edge_virts.entry_values(e);
+        assert_eq!(virts(0), virts(1));
+        assert_eq!(virts(2), &virts(0)[..1]);
+        // 4 -> 1 and 5 -> 1 share both virtual vertices, but not with 0 -> 1, whose vertices
+        // were already merged with those of 0 -> 2.
+        assert_eq!(virts(3), virts(5));
+        assert!(virts(3).iter().all(|v| !virts(0).contains(v)));
+        assert!(virts(4)
+            .iter()
+            .all(|v| (0..6).all(|e| e == 4 || !virts(e).contains(v))));
+        assert_eq!(pg.num_verts(), 6 + 6);
+        assert_eq!(pg.edges.num_values(), 12);
+        assert_eq!(pg.virt_orig, vec![0, 0, 3, 3, 4, 4]);
+    }
+}
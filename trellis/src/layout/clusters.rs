@@ -0,0 +1,236 @@
+// Support for clusters in layered layouts.
+//
+// Each cluster is drawn as a box. To keep the box free of other vertices, a "border" vertex is
+// added at each end of the cluster, on every layer that the cluster spans. The border vertices
+// on each side form a vertical chain of edges, which ordering keeps at the ends of the cluster
+// and which coordinate assignment draws as a straight line. This is the approach of dagre
+// (which follows Sander, "Layout of Compound Directed Graphs").
+
+use super::Cluster;
+use crate::graph::Graph;
+use crate::layering::LayerMap;
+use crate::ordering::{ClusterBorder, OrderingClusters};
+use crate::ramp_table::RampTable;
+use crate::V;
+
+/// The layering graph, extended with the border vertices (and label vertices) of clusters.
+pub(super) struct ClusterGraph {
+    /// The input graph, plus the border vertices and their edges. The vertices and edges of the
+    /// input graph keep their indices.
+    pub graph: Graph,
+
+    pub layers: LayerMap,
+
+    /// Cluster membership of every vertex of `graph`. (Ordering also needs the membership of
+    /// virtual vertices; see `add_virtual_verts`.)
+    pub clusters: OrderingClusters,
+
+    /// For each cluster, the lowest and highest layer that it spans, or `None` if it has no
+    /// vertices.
+    pub span: Vec<Option<(u32, u32)>>,
+
+    /// For each cluster, its left border vertices (one per layer in its span).
+    pub left: RampTable<V>,
+
+    /// For each cluster, its right border vertices (one per layer in its span).
+    pub right: RampTable<V>,
+
+    /// For each added vertex that reserves space for a cluster label, the vertex and its width.
+    pub labels: Vec<(V, f64)>,
+}
+
+/// Checks the cluster tree and finds the innermost cluster of each vertex.
+pub(super) fn cluster_tree(num_verts: usize, clusters: &[Cluster]) -> OrderingClusters {
+    let parent: Vec<Option<u32>> = clusters
+        .iter()
+        .map(|c| {
+            c.parent.map(|p| {
+                assert!(p < clusters.len(), "cluster parent is out of range");
+                p as u32
+            })
+        })
+        .collect();
+    let mut v_cluster: Vec<Option<u32>> = vec![None; num_verts];
+    for (c, cluster) in clusters.iter().enumerate() {
+        // A cycle in the parent links would have a path longer than the number of clusters.
+        let mut depth = 0;
+        let mut p = cluster.parent;
+        while let Some(pp) = p {
+            depth += 1;
+            assert!(depth <= clusters.len(), "cluster parents form a cycle");
+            p = clusters[pp].parent;
+        }
+        for &v in cluster.verts.iter() {
+            assert!(
+                v_cluster[v as usize].is_none(),
+                "vertex {} is in more than one cluster",
+                v
+            );
+            v_cluster[v as usize] = Some(c as u32);
+        }
+    }
+    OrderingClusters {
+        parent,
+        v_cluster,
+        v_border: Vec::new(),
+    }
+}
+
+/// Adds the border vertices of each cluster to `dag`, on every layer that the cluster spans.
+/// If `label_width` is non-zero for a cluster, a vertex of that width is also added to the
+/// cluster's top layer, so that its box is at least as wide as its label.
+pub(super) fn add_border_verts(
+    dag: &Graph,
+    layers: &LayerMap,
+    tree: OrderingClusters,
+    label_width: &[f64],
+) -> ClusterGraph {
+    let nv = dag.num_verts();
+    let num_clusters = tree.parent.len();
+
+    let mut span: Vec<Option<(u32, u32)>> = vec![None; num_clusters];
+    for v in 0..nv as V {
+        let layer = layers.v_layer[v as usize];
+        for c in tree.path(tree.cluster_of(v)) {
+            let s = &mut span[c as usize];
+            *s = Some(match *s {
+                Some((lo, hi)) => (lo.min(layer), hi.max(layer)),
+                None => (layer, layer),
+            });
+        }
+    }
+
+    let mut pairs: Vec<(V, V)> = dag.iter_edges_flattened().collect();
+    let mut v_layer: Vec<u32> = layers.v_layer.clone();
+    let mut v_cluster: Vec<Option<u32>> = tree.v_cluster.clone();
+    let mut v_border: Vec<Option<ClusterBorder>> = vec![None; nv];
+    let mut left: RampTable<V> = RampTable::new();
+    let mut right: RampTable<V> = RampTable::new();
+    let mut labels: Vec<(V, f64)> = Vec::new();
+
+    for c in 0..num_clusters {
+        if let Some((lo, hi)) = span[c] {
+            for &side in [ClusterBorder::Left, ClusterBorder::Right].iter() {
+                let table = match side {
+                    ClusterBorder::Left => &mut left,
+                    ClusterBorder::Right => &mut right,
+                };
+                // Add the chain from the top layer down, so that each vertex has an edge to
+                // the next one.
+                for layer in (lo..=hi).rev() {
+                    let v = v_layer.len() as V;
+                    v_layer.push(layer);
+                    v_cluster.push(Some(c as u32));
+                    v_border.push(Some(side));
+                    table.push_value(v);
+                    if layer != hi {
+                        pairs.push((v - 1, v));
+                    }
+                }
+                table.finish_key();
+            }
+            if label_width[c] > 0.0 {
+                let v = v_layer.len() as V;
+                v_layer.push(hi);
+                v_cluster.push(Some(c as u32));
+                v_border.push(None);
+                labels.push((v, label_width[c]));
+            }
+        } else {
+            left.finish_key();
+            right.finish_key();
+        }
+    }
+
+    // The new vertices are numbered after the vertices of `dag`, so the edges of `dag` keep
+    // their indices.
+    let (graph, _) = Graph::from_edge_list(v_layer.len(), &pairs);
+    ClusterGraph {
+        graph,
+        layers: LayerMap {
+            num_layers: layers.num_layers,
+            v_layer,
+        },
+        clusters: OrderingClusters {
+            parent: tree.parent,
+            v_cluster,
+            v_border,
+        },
+        span,
+        left,
+        right,
+        labels,
+    }
+}
+
+/// Assigns each virtual vertex in `v_edge_endpoints` to the innermost cluster that contains
+/// both ends of its edge.
+pub(super) fn add_virtual_verts(
+    clusters: &mut OrderingClusters,
+    v_edge_endpoints: impl Iterator<Item = (V, V)>,
+) {
+    for (from, to) in v_edge_endpoints {
+        let c = clusters.common_ancestor(clusters.cluster_of(from), clusters.cluster_of(to));
+        clusters.v_cluster.push(c);
+    }
+}
+
+/// Computes how far each cluster's box extends above its top layer and below its bottom layer.
+/// A cluster extends `padding` beyond its contents, and its label is placed above its top
+/// layer. If a child cluster has the same top (or bottom) layer, then the parent extends beyond
+/// the child.
+pub(super) fn cluster_extents(
+    clusters: &OrderingClusters,
+    span: &[Option<(u32, u32)>],
+    label_height: &[f64],
+    padding: f64,
+) -> Vec<(f64, f64)> {
+    let num_clusters = clusters.parent.len();
+    let mut by_depth: Vec<(usize, usize)> = (0..num_clusters)
+        .map(|c| (clusters.path(Some(c as u32)).len(), c))
+        .collect();
+    by_depth.sort_unstable_by(|a, b| b.cmp(a));
+
+    // Children are visited before their parents.
+    let mut extents: Vec<(f64, f64)> = vec![(0.0, 0.0); num_clusters];
+    for &(_, c) in by_depth.iter() {
+        let (lo, hi) = match span[c] {
+            Some(s) => s,
+            None => continue,
+        };
+        let above = padding + label_height[c] + extents[c].0;
+        let below = padding + extents[c].1;
+        extents[c] = (above, below);
+        if let Some(p) = clusters.parent[c] {
+            let p = p as usize;
+            if let Some((p_lo, p_hi)) = span[p] {
+                if p_hi == hi {
+                    extents[p].0 = extents[p].0.max(above);
+                }
+                if p_lo == lo {
+                    extents[p].1 = extents[p].1.max(below);
+                }
+            }
+        }
+    }
+    extents
+}
+
+/// Computes the extra space needed above and below each layer for the clusters that start or
+/// end at that layer.
+pub(super) fn layer_margins(
+    num_layers: usize,
+    span: &[Option<(u32, u32)>],
+    extents: &[(f64, f64)],
+) -> Vec<(f64, f64)> {
+    let mut margins: Vec<(f64, f64)> = vec![(0.0, 0.0); num_layers];
+    for (s, &(above, below)) in span.iter().zip(extents.iter()) {
+        if let Some((lo, hi)) = *s {
+            let top = &mut margins[hi as usize].0;
+            *top = top.max(above);
+            let bottom = &mut margins[lo as usize].1;
+            *bottom = bottom.max(below);
+        }
+    }
+    margins
+}
@@ -0,0 +1,3177 @@
+// Layout: turns a graph into geometry.
+//
+// `layered` runs the phases of a layered ("Sugiyama") drawing, in order:
+//
+// 1. Cycle removal (`cycle_removal`), which reverses some edges so that the graph is acyclic.
+// 2. Layering (`layering`), which assigns every vertex to a layer.
+// 3. Proper graph construction, which replaces long edges with chains of virtual vertices.
+//    With `concentrate`, edges that share an endpoint also share their virtual vertices.
+// 4. Ordering (`ordering`), which orders each layer to reduce edge crossings.
+// 5. Coordinate assignment (`brandes_kopf` for x, and the layer sizes for y). Edges between
+//    vertices of the same group are kept vertical, like long edges.
+// 6. Edge routing, which draws each edge as a polyline through its virtual vertices, and then
+//    as a curve in the style that `EdgeRouting` selects (see `splines`).
+//
+// Rank constraints can put both ends of an edge in the same layer. These flat edges are drawn
+// as a straight line if their ends are next to each other, and otherwise as an arc above the
+// layer. Arcs that overlap are stacked, and the space above the layer grows to fit them.
+//
+// Self-loops are removed by cycle removal, so they take no part in layering or ordering. They
+// are drawn as nested rectangular loops on the right side of their vertex, and coordinate
+// assignment reserves space for them there.
+//
+// Edge labels are placed as in dot. If any edge has a label, then the layers are doubled, so
+// that every edge that is not flat has a virtual vertex in a middle layer. The label is given
+// to that vertex, so that ordering and coordinate assignment make room for it, and the edge
+// runs along the label's left side. Labels of flat edges are placed above their arcs, and
+// labels of self-loops are placed to the right of the loops.
+//
+// Edges can attach to ports, which are points within their vertices, like the fields of
+// records in Graphviz. Ordering compares the positions of the ports of edges that share a
+// vertex, so that edges to ports on the left tend to go to vertices on the left. Edges are
+// clipped where they leave the vertex, or start on the side of the vertex that the port names.
+//
+// Clusters are supported by adding border vertices to the graph after layering; see `clusters`.
+// An edge can also connect to a cluster as a whole, like `lhead` and `ltail` in Graphviz. Such
+// an edge is laid out as usual, and then clipped where it crosses the box of the cluster.
+//
+// `layered_incremental` runs the same phases, but replaces cycle removal and layering: the
+// vertices of a previous layout keep their layers, only the new vertices are layered, and
+// edges are reversed if they point up. `LeftOf` constraints keep the old vertices in their
+// previous order. After coordinate assignment, the old vertices are moved back to their
+// previous positions, and edges between them keep their previous routes where they can.
+//
+// `tree` draws rooted trees more compactly than `layered`; see `tree.rs`. `stress` draws
+// undirected graphs, which have no layers, by stress majorization; see `stress.rs`. All of them
+// return a `Layout`.
+//
+// All coordinates use the usual screen convention: x increases to the right and y increases
+// downward.
+
+use crate::brandes_kopf::assign_x_coordinates_with;
+use crate::cycle_removal::{build_dag, remove_cycles, CycleRemoval, CycleRemovalMethod};
+use crate::error::Error;
+use crate::graph::Graph;
+use crate::layering::{
+    concentrate_edges, create_layer_map, create_layer_map_coffman_graham,
+    create_layer_map_network_simplex, create_layer_map_with_constraints,
+    create_proper_graph_from_layers, LayerMap, ProperGraph, RankConstraint,
+};
+use crate::ordering::{
+    minimize_crossings_clustered, ClusterBorder, OrderConstraint, OrderingOptions,
+};
+use crate::ramp_table::RampTable;
+use crate::vec2::Vec2;
+use crate::{E, V};
+use log::debug;
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::iter::once;
+
+mod clusters;
+mod splines;
+mod stress;
+mod tree;
+
+pub use stress::{shortest_path_lengths, stress, StressOptions};
+pub use tree::{find_tree_root, tree, TreeOptions};
+
+/// The direction in which edges point, from the top layer to the bottom layer.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RankDir {
+    /// The top layer is at the top of the drawing. Edges point down.
+    #[default]
+    TopToBottom,
+
+    /// The top layer is at the bottom of the drawing. Edges point up.
+    BottomToTop,
+
+    /// The top layer is at the left of the drawing. Edges point right.
+    LeftToRight,
+
+    /// The top layer is at the right of the drawing. Edges point left.
+    RightToLeft,
+}
+
+impl RankDir {
+    /// Returns true if layers are drawn as columns rather than rows.
+    pub fn is_horizontal(self) -> bool {
+        matches!(self, RankDir::LeftToRight | RankDir::RightToLeft)
+    }
+
+    /// Converts a size in the drawing to a size in the top-to-bottom frame that the layout is
+    /// computed in, or back. For horizontal directions, this swaps width and height.
+    fn swap_size(self, size: Vec2<f64>) -> Vec2<f64> {
+        if self.is_horizontal() {
+            Vec2 {
+                x: size.y,
+                y: size.x,
+            }
+        } else {
+            size
+        }
+    }
+
+    /// Converts a point in the top-to-bottom frame to a point in the drawing. `bounds` is the
+    /// bounding box in the top-to-bottom frame; flipped directions are reflected within it, so
+    /// that the bounding box keeps the same position.
+    fn transform_point(self, p: Vec2<f64>, bounds: &Rect) -> Vec2<f64> {
+        let flipped_y = bounds.min.y + bounds.max.y - p.y;
+        match self {
+            RankDir::TopToBottom => p,
+            RankDir::BottomToTop => Vec2 {
+                x: p.x,
+                y: flipped_y,
+            },
+            RankDir::LeftToRight => Vec2 { x: p.y, y: p.x },
+            RankDir::RightToLeft => Vec2 {
+                x: flipped_y,
+                y: p.x,
+            },
+        }
+    }
+
+    /// Converts a vector (such as an offset or a direction) in the drawing to a vector in the
+    /// top-to-bottom frame. This is the inverse of `transform_point`, without the translation.
+    fn inverse_vector(self, v: Vec2<f64>) -> Vec2<f64> {
+        match self {
+            RankDir::TopToBottom => v,
+            RankDir::BottomToTop => Vec2 { x: v.x, y: -v.y },
+            RankDir::LeftToRight => Vec2 { x: v.y, y: v.x },
+            RankDir::RightToLeft => Vec2 { x: v.y, y: -v.x },
+        }
+    }
+}
+
+/// Selects the algorithm that assigns vertices to layers.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum LayeringMethod {
+    /// Longest-path layering (`create_layer_map`). Every sink is on the bottom layer.
+    #[default]
+    LongestPath,
+
+    /// Network simplex layering (`create_layer_map_network_simplex`), which minimizes the total
+    /// length of the edges.
+    NetworkSimplex,
+
+    /// Coffman-Graham layering (`create_layer_map_coffman_graham`), which puts at most
+    /// `max_width` vertices on each layer. A `max_width` of 0 does not bound the width.
+    CoffmanGraham { max_width: usize },
+}
+
+/// Keeps the edges of a vertex in the order in which they appear in the graph, like the
+/// `ordering` attribute in Graphviz.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum EdgeOrdering {
+    /// The edges that leave the vertex are drawn from left to right in the order of their edge
+    /// numbers.
+    Out,
+
+    /// The edges that enter the vertex are drawn from left to right in the order of their edge
+    /// numbers.
+    In,
+}
+
+/// Selects how edges are drawn, like the `splines` attribute in Graphviz. Every style produces
+/// the same representation, in `Layout::edge_splines`.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum EdgeRouting {
+    /// A straight line between the ends of the edge, which may pass through other vertices.
+    Line,
+
+    /// The polyline through the virtual vertices of the edge, as in `Layout::edge_points`.
+    #[default]
+    Polyline,
+
+    /// Horizontal and vertical segments (for `TopToBottom`). The edge leaves the bottom of its
+    /// `from` vertex and enters the top of its `to` vertex, and turns only in the space between
+    /// layers. Segments of different edges may overlap.
+    Ortho,
+
+    /// A smooth curve that avoids other vertices, as in dot.
+    Spline,
+
+    /// A single curved arc between the ends of the edge, which may pass through other
+    /// vertices.
+    Curved,
+}
+
+/// A side or corner of a vertex, like the compass points of ports in Graphviz. These refer to
+/// the drawing, so `N` is the top of the vertex regardless of the rank direction.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Compass {
+    N,
+    NE,
+    E,
+    SE,
+    S,
+    SW,
+    W,
+    NW,
+}
+
+impl Compass {
+    /// Returns the direction that this compass point faces, in the drawing (where y increases
+    /// downward). The directions of corners are diagonal.
+    pub fn direction(self) -> Vec2<f64> {
+        let (x, y) = match self {
+            Compass::N => (0.0, -1.0),
+            Compass::NE => (1.0, -1.0),
+            Compass::E => (1.0, 0.0),
+            Compass::SE => (1.0, 1.0),
+            Compass::S => (0.0, 1.0),
+            Compass::SW => (-1.0, 1.0),
+            Compass::W => (-1.0, 0.0),
+            Compass::NW => (-1.0, -1.0),
+        };
+        Vec2 { x, y }
+    }
+}
+
+/// A point within a vertex that an edge attaches to.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Port {
+    /// The position of the port relative to the center of the vertex, in the drawing. For a
+    /// record, this is usually the center of a field.
+    pub offset: Vec2<f64>,
+
+    /// The side of the vertex that the edge attaches to. The edge starts at the point on that
+    /// side that is nearest to `offset`, and leaves in the direction of the side. If this is
+    /// `None`, then the edge is aimed at `offset` and starts where it crosses the boundary of
+    /// the vertex.
+    pub compass: Option<Compass>,
+}
+
+/// The ports of an edge. The default attaches both ends to the centers of their vertices.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EdgePorts {
+    /// The port on the edge's `from` vertex.
+    pub tail: Port,
+
+    /// The port on the edge's `to` vertex.
+    pub head: Port,
+}
+
+/// The clusters that an edge connects, like `ltail` and `lhead` in Graphviz. The edge ends at
+/// the box of the cluster rather than at its vertex. A cluster is ignored if it does not
+/// contain the edge's vertex at that end, or if it also contains the vertex at the other end.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct EdgeClusters {
+    /// The index (in `LayeredOptions::clusters`) of the cluster at the edge's `from` end.
+    pub tail: Option<usize>,
+
+    /// The index (in `LayeredOptions::clusters`) of the cluster at the edge's `to` end.
+    pub head: Option<usize>,
+}
+
+/// A set of vertices that is drawn inside a box. Clusters may be nested.
+#[derive(Clone, Debug, Default)]
+pub struct Cluster {
+    /// The index (in `LayeredOptions::clusters`) of the cluster that contains this cluster, or
+    /// `None` for a top-level cluster.
+    pub parent: Option<usize>,
+
+    /// The vertices that are directly in this cluster, rather than in one of its child clusters.
+    /// Each vertex can be directly in at most one cluster.
+    pub verts: Vec<V>,
+
+    /// The size of the cluster's label. Space for the label is reserved inside the top of the
+    /// cluster's box (for `TopToBottom`; for other rank directions, the side that the top layer
+    /// is on).
+    pub label_size: Vec2<f64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct LayeredOptions {
+    /// The size (width and height) of each vertex. Vertices that do not have an entry use
+    /// `default_node_size`.
+    pub node_sizes: Vec<Vec2<f64>>,
+
+    pub default_node_size: Vec2<f64>,
+
+    /// The space between adjacent layers. This is vertical space for `TopToBottom` and
+    /// `BottomToTop`, and horizontal space for `LeftToRight` and `RightToLeft`.
+    pub rank_sep: f64,
+
+    /// The minimum space between adjacent vertices in the same layer.
+    pub node_sep: f64,
+
+    pub rank_dir: RankDir,
+
+    pub cycle_removal: CycleRemovalMethod,
+
+    pub layering: LayeringMethod,
+
+    /// Constraints on the layers of sets of vertices, like the `rank` attribute of subgraphs in
+    /// Graphviz. If there are any, then network simplex layering is used, regardless of
+    /// `layering`.
+    pub rank_constraints: Vec<RankConstraint>,
+
+    /// Options for ordering the vertices within layers. The vertices of `ordering.constraints`
+    /// are the vertices of the input graph.
+    pub ordering: OrderingOptions,
+
+    /// For each vertex, whether the order of its edges is fixed. Vertices that do not have an
+    /// entry are not constrained. The order is enforced among the edges whose next vertices
+    /// are in the same layer; for example, it is not enforced between edges that point down
+    /// and edges that were reversed to break cycles.
+    pub edge_ordering: Vec<Option<EdgeOrdering>>,
+
+    /// The clusters, which are drawn as boxes around their vertices.
+    pub clusters: Vec<Cluster>,
+
+    /// The space between the box of a cluster and the vertices and clusters inside it.
+    pub cluster_padding: f64,
+
+    /// The size (width and height) of the label of each edge. Edges that do not have an entry,
+    /// or whose entry has a zero width or height, have no label.
+    pub edge_label_sizes: Vec<Vec2<f64>>,
+
+    /// The ports of each edge. Edges that do not have an entry attach to the centers of their
+    /// vertices. Ports are ignored for flat edges that are drawn as arcs and for self-loops.
+    pub edge_ports: Vec<EdgePorts>,
+
+    /// The clusters that each edge connects. Edges that do not have an entry connect their
+    /// vertices.
+    pub edge_clusters: Vec<EdgeClusters>,
+
+    /// How edges are drawn. Flat edges and self-loops keep the shape of their polylines in every
+    /// style.
+    pub edge_routing: EdgeRouting,
+
+    /// The group of each vertex, like the `group` attribute of Graphviz. Edges between vertices
+    /// of the same group are kept vertical whenever possible, so that a chain of vertices in a
+    /// group is drawn as a straight line. Vertices that do not have an entry are not in a group.
+    pub node_groups: Vec<Option<u32>>,
+
+    /// Whether to merge edges that share an endpoint and a direction, like `concentrate` in
+    /// Graphviz. Merged edges follow the same path and split apart near the ends where they
+    /// differ. Edges that have a label or ports, edges within a group, and edges in different
+    /// clusters are not merged.
+    pub concentrate: bool,
+}
+
+impl Default for LayeredOptions {
+    fn default() -> Self {
+        Self {
+            node_sizes: Vec::new(),
+            default_node_size: Vec2 { x: 54.0, y: 36.0 },
+            rank_sep: 36.0,
+            node_sep: 18.0,
+            rank_dir: RankDir::default(),
+            cycle_removal: CycleRemovalMethod::default(),
+            layering: LayeringMethod::default(),
+            rank_constraints: Vec::new(),
+            ordering: OrderingOptions::default(),
+            edge_ordering: Vec::new(),
+            clusters: Vec::new(),
+            cluster_padding: 8.0,
+            edge_label_sizes: Vec::new(),
+            edge_ports: Vec::new(),
+            edge_clusters: Vec::new(),
+            edge_routing: EdgeRouting::default(),
+            node_groups: Vec::new(),
+            concentrate: false,
+        }
+    }
+}
+
+impl LayeredOptions {
+    /// Returns the size of vertex `v`.
+    pub fn node_size(&self, v: usize) -> Vec2<f64> {
+        self.node_sizes
+            .get(v)
+            .copied()
+            .unwrap_or(self.default_node_size)
+    }
+
+    /// Returns the group of vertex `v`, if it is in one.
+    pub fn node_group(&self, v: usize) -> Option<u32> {
+        self.node_groups.get(v).copied().flatten()
+    }
+
+    /// Returns the ports of edge `e`.
+    pub fn edge_ports(&self, e: usize) -> EdgePorts {
+        self.edge_ports.get(e).copied().unwrap_or_default()
+    }
+
+    /// Returns the clusters that edge `e` connects.
+    pub fn edge_clusters(&self, e: usize) -> EdgeClusters {
+        self.edge_clusters.get(e).copied().unwrap_or_default()
+    }
+
+    /// Returns the size of the label of edge `e`, if it has one.
+    pub fn edge_label_size(&self, e: usize) -> Option<Vec2<f64>> {
+        self.edge_label_sizes
+            .get(e)
+            .copied()
+            .filter(|s| s.x > 0.0 && s.y > 0.0)
+    }
+}
+
+/// An axis-aligned rectangle.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub min: Vec2<f64>,
+    pub max: Vec2<f64>,
+}
+
+impl Rect {
+    pub fn from_center_size(center: Vec2<f64>, size: Vec2<f64>) -> Self {
+        let half = size / 2.0;
+        Self {
+            min: center - half,
+            max: center + half,
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max.y - self.min.y
+    }
+
+    pub fn contains(&self, p: Vec2<f64>) -> bool {
+        p.x >= self.min.x && p.x <= self.max.x && p.y >= self.min.y && p.y <= self.max.y
+    }
+
+    /// Returns the smallest rectangle that contains `self` and `p`.
+    pub fn union_point(&self, p: Vec2<f64>) -> Self {
+        Self {
+            min: Vec2 {
+                x: self.min.x.min(p.x),
+                y: self.min.y.min(p.y),
+            },
+            max: Vec2 {
+                x: self.max.x.max(p.x),
+                y: self.max.y.max(p.y),
+            },
+        }
+    }
+}
+
+/// The result of a layout.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    /// The center of each vertex.
+    pub node_centers: Vec<Vec2<f64>>,
+
+    /// The size of each vertex.
+    pub node_sizes: Vec<Vec2<f64>>,
+
+    /// The smallest rectangle that contains all vertices and edges.
+    pub bounding_box: Rect,
+
+    /// For each edge of the input graph, the points of the polyline that it follows through the
+    /// layout. The points go from the edge's `from` vertex to its `to` vertex, and the first and
+    /// last points are on the boundaries of those vertices (or of the clusters in
+    /// `LayeredOptions::edge_clusters`). Self-loops leave and return through the right side of
+    /// their vertex (for `TopToBottom`).
+    pub edge_points: RampTable<Vec2<f64>>,
+
+    /// For each edge of the input graph, the control points of the piecewise cubic Bézier curve
+    /// that draws it, in the style of `LayeredOptions::edge_routing`: the first point, and then
+    /// three more for each segment. The curve goes from the edge's `from` vertex to its `to`
+    /// vertex, and its ends are on the boundaries of those vertices (or clusters), like the
+    /// ends of `edge_points`.
+    pub edge_splines: RampTable<Vec2<f64>>,
+
+    /// The box of each cluster. Clusters that contain no vertices have an empty box at the
+    /// origin.
+    pub cluster_boxes: Vec<Rect>,
+
+    /// For each edge of the input graph, the center of its label, or `None` if it has no label.
+    pub edge_label_positions: Vec<Option<Vec2<f64>>>,
+
+    /// The layer of each vertex. Layer 0 is the last layer in the rank direction (the bottom
+    /// layer for `TopToBottom`). These are the layers before they are doubled for edge labels.
+    /// `stress` puts every vertex in layer 0.
+    pub node_layers: Vec<u32>,
+
+    /// The `from` and `to` vertices of each edge of the input graph. `layered_incremental` uses
+    /// these to find the edges of a previous layout in a new graph.
+    pub edge_endpoints: Vec<(V, V)>,
+}
+
+impl Layout {
+    /// Returns the rectangle occupied by vertex `v`.
+    pub fn node_rect(&self, v: usize) -> Rect {
+        Rect::from_center_size(self.node_centers[v], self.node_sizes[v])
+    }
+
+    /// Returns the rectangle occupied by the label of edge `e`, given the size of the label.
+    pub fn edge_label_rect(&self, e: usize, size: Vec2<f64>) -> Option<Rect> {
+        self.edge_label_positions[e].map(|center| Rect::from_center_size(center, size))
+    }
+}
+
+/// Computes a layered drawing of `graph`. The graph may contain cycles.
+///
+/// Returns `Error::ConflictingRankConstraints` if `options.rank_constraints` cannot be
+/// satisfied.
+pub fn layered(graph: &Graph, options: &LayeredOptions) -> Result<Layout, Error> {
+    layered_with(graph, options, None)
+}
+
+/// Computes a layered drawing of `graph` that stays close to `previous`, which is a layout of
+/// an earlier version of the graph. Vertex `v` of `graph` is vertex `v` of `previous`, so new
+/// vertices must be added after the existing ones. `options` should be the options that
+/// `previous` was computed with, except for the entries of new vertices and edges.
+///
+/// Existing vertices keep their layers, and the vertices of each layer keep their relative
+/// order where possible. The new vertices are layered by themselves with `options.layering`,
+/// and each connected group of them is then shifted so that its edges to existing vertices are
+/// short and point down where possible. Edges that point up (from a lower layer to a higher
+/// one) are reversed, like the edges that cycle removal reverses.
+///
+/// Existing vertices also keep their positions within their layers, up to a translation of the
+/// whole drawing, unless new vertices between them need more room. Other vertices keep their
+/// offsets from the nearest existing vertex in a fresh coordinate assignment. Layers can still
+/// move apart, for example to make room for the arcs of new flat edges. An edge between
+/// existing vertices that did not move relative to each other keeps its route and label
+/// position from `previous`; other edges are routed anew.
+///
+/// `options.rank_constraints` are dropped for existing vertices, which keep their layers even
+/// if the constraints would move them. Among the new vertices, the constraints apply as usual,
+/// except that `Min`, `Max`, `Source` and `Sink` only place them relative to the other new
+/// vertices. A new vertex in a `Same` constraint with an existing vertex is put in the layer of
+/// that vertex.
+///
+/// Returns `Error::ConflictingRankConstraints` if the constraints between new vertices cannot be
+/// satisfied.
+pub fn layered_incremental(
+    graph: &Graph,
+    options: &LayeredOptions,
+    previous: &Layout,
+) -> Result<Layout, Error> {
+    layered_with(graph, options, Some(previous))
+}
+
+fn layered_with(
+    graph: &Graph,
+    options: &LayeredOptions,
+    previous: Option<&Layout>,
+) -> Result<Layout, Error> {
+    let (removal, layers) = assign_layers(graph, options, previous)?;
+    let node_layers: Vec<u32> = layers.v_layer.clone();
+    let input = LayeredInput::new(graph, options, removal);
+
+    let (layers, rank_sep) = if input.tb_label_sizes.iter().any(Option::is_some) {
+        (double_layers(&layers), options.rank_sep / 2.0)
+    } else {
+        (layers, options.rank_sep)
+    };
+
+    let (cluster_graph, mut pg) = input.build_proper_graph(&layers);
+    let ordering = input.ordering_options(previous, &layers, &cluster_graph, &pg);
+    let crossings = minimize_crossings_clustered(&mut pg, &cluster_graph.clusters, &ordering);
+    debug!(
+        "layered: {} layers, {} virtual vertices, {} crossings",
+        pg.num_layers(),
+        pg.num_verts() - pg.num_real_verts,
+        crossings
+    );
+
+    let coords = input.assign_coordinates(&cluster_graph, &pg, rank_sep, previous);
+    let routes = input.route_edges(&cluster_graph, &pg, &coords, rank_sep, previous);
+    Ok(input.transform(&cluster_graph, &coords, routes, node_layers))
+}
+
+/// Removes the cycles of `graph` and assigns its vertices to layers. With a `previous` layout,
+/// the layers of its vertices are kept (see `keep_layers`).
+fn assign_layers(
+    graph: &Graph,
+    options: &LayeredOptions,
+    previous: Option<&Layout>,
+) -> Result<(CycleRemoval, LayerMap), Error> {
+    if let Some(previous) = previous {
+        return keep_layers(graph, options, previous);
+    }
+    let removal = remove_cycles(graph, options.cycle_removal);
+    let layers = layer_dag(&removal.dag, options.layering, &options.rank_constraints)?;
+    Ok((removal, layers))
+}
+
+/// The input of `layered_with` after cycle removal. The layout is computed as if the rank
+/// direction were `TopToBottom`, so all sizes are in that frame, and then transformed.
+struct LayeredInput<'a> {
+    graph: &'a Graph,
+    options: &'a LayeredOptions,
+    removal: CycleRemoval,
+
+    /// The size of each vertex of `graph`.
+    tb_sizes: Vec<Vec2<f64>>,
+
+    /// The size of the label of each edge of `graph`, if it has one.
+    tb_label_sizes: Vec<Option<Vec2<f64>>>,
+
+    /// The ports at the `from` and `to` ends of each edge of `removal.dag`, which are swapped
+    /// for reversed edges.
+    dag_ports: Vec<(TbPort, TbPort)>,
+
+    loops: SelfLoops,
+}
+
+/// The self-loops of the vertices of the input graph. Each self-loop extends `step` further to
+/// the right than the one inside it. The labels of the loops are stacked to the right of the
+/// outermost loop, so the vertex needs room for them too.
+struct SelfLoops {
+    step: f64,
+
+    /// The number of self-loops of each vertex.
+    num: Vec<u32>,
+
+    /// The index of each self-loop among the loops of its vertex.
+    index: Vec<u32>,
+
+    /// The offset of the center of the label of each self-loop from the top of its stack.
+    label_offset: Vec<f64>,
+
+    /// The size of the stack of loop labels of each vertex.
+    labels: Vec<Vec2<f64>>,
+}
+
+impl SelfLoops {
+    fn new(graph: &Graph, self_loops: &[E], label_sizes: &[Option<Vec2<f64>>], step: f64) -> Self {
+        let nv = graph.num_verts();
+        let mut loops = SelfLoops {
+            step,
+            num: vec![0; nv],
+            index: vec![0; graph.num_edges()],
+            label_offset: vec![0.0; graph.num_edges()],
+            labels: vec![Vec2::default(); nv],
+        };
+        for &e in self_loops.iter() {
+            let (v, _) = graph.edge_endpoints(e);
+            let (v, e) = (v as usize, e as usize);
+            loops.index[e] = loops.num[v];
+            loops.num[v] += 1;
+            if let Some(size) = label_sizes[e] {
+                let labels = &mut loops.labels[v];
+                loops.label_offset[e] = labels.y + size.y / 2.0;
+                labels.x = labels.x.max(size.x);
+                labels.y += size.y;
+            }
+        }
+        loops
+    }
+
+    /// Returns the space to the right of vertex `v` that its loops and their labels need. This
+    /// is 0 for virtual vertices.
+    fn space(&self, v: V) -> f64 {
+        match self.num.get(v as usize) {
+            Some(&n) => n as f64 * self.step + self.labels[v as usize].x,
+            None => 0.0,
+        }
+    }
+}
+
+/// The coordinates of the vertices of the proper graph, and of its layers and clusters.
+struct Coordinates {
+    /// The center of each vertex of the proper graph.
+    centers: Vec<Vec2<f64>>,
+
+    /// The size of each vertex of the proper graph. Only the input vertices, the cluster label
+    /// vertices and the vertices that carry edge labels have a size.
+    v_size: Vec<Vec2<f64>>,
+
+    /// The vertex that carries the label of each edge, if it has one.
+    label_vert: Vec<Option<V>>,
+
+    /// The center and the height of each layer.
+    layer_y: Vec<f64>,
+    layer_height: Vec<f64>,
+
+    /// The height of the arc of each flat edge above its layer, and the size of its label, by
+    /// its index in `pg.flat_edges`.
+    flat_heights: Vec<f64>,
+    flat_label_sizes: Vec<Option<Vec2<f64>>>,
+
+    /// The box of each cluster, or `Rect::default()` if it has no vertices.
+    cluster_boxes: Vec<Rect>,
+}
+
+/// The drawing of the edges, in the top-to-bottom frame.
+struct Routes {
+    edge_points: RampTable<Vec2<f64>>,
+    edge_splines: RampTable<Vec2<f64>>,
+    label_rects: Vec<Option<Rect>>,
+}
+
+impl<'a> LayeredInput<'a> {
+    fn new(graph: &'a Graph, options: &'a LayeredOptions, removal: CycleRemoval) -> Self {
+        let rank_dir = options.rank_dir;
+        let tb_sizes: Vec<Vec2<f64>> = (0..graph.num_verts())
+            .map(|v| rank_dir.swap_size(options.node_size(v)))
+            .collect();
+        let tb_label_sizes: Vec<Option<Vec2<f64>>> = (0..graph.num_edges())
+            .map(|e| options.edge_label_size(e).map(|s| rank_dir.swap_size(s)))
+            .collect();
+        let dag_ports: Vec<(TbPort, TbPort)> = removal
+            .dag_edge_orig
+            .iter()
+            .map(|&e| {
+                let ports = options.edge_ports(e as usize);
+                let tail = TbPort::new(&ports.tail, rank_dir);
+                let head = TbPort::new(&ports.head, rank_dir);
+                if removal.reversed[e as usize] {
+                    (head, tail)
+                } else {
+                    (tail, head)
+                }
+            })
+            .collect();
+        let loops = SelfLoops::new(
+            graph,
+            &removal.self_loops,
+            &tb_label_sizes,
+            options.node_sep,
+        );
+        Self {
+            graph,
+            options,
+            removal,
+            tb_sizes,
+            tb_label_sizes,
+            dag_ports,
+            loops,
+        }
+    }
+
+    /// Adds the cluster border vertices to the DAG, and then builds the proper graph. The border
+    /// vertices and their edges are numbered after the vertices and edges of the DAG.
+    fn build_proper_graph(&self, layers: &LayerMap) -> (clusters::ClusterGraph, ProperGraph) {
+        let options = self.options;
+        let removal = &self.removal;
+        let dag = &removal.dag;
+        let label_widths: Vec<f64> = options
+            .clusters
+            .iter()
+            .map(|c| options.rank_dir.swap_size(c.label_size).x)
+            .collect();
+        let tree = clusters::cluster_tree(self.graph.num_verts(), &options.clusters);
+        let mut cg = clusters::add_border_verts(dag, layers, tree, &label_widths);
+        let mut pg = create_proper_graph_from_layers(&cg.graph, &cg.layers);
+        if options.concentrate {
+            // Edges are merged only with edges in the same cluster and with the same direction.
+            let groups: Vec<Option<u32>> = (0..cg.graph.num_edges())
+                .map(|dag_e| {
+                    let e = *removal.dag_edge_orig.get(dag_e)? as usize;
+                    if self.tb_label_sizes[e].is_some()
+                        || options.edge_ports(e) != EdgePorts::default()
+                    {
+                        return None;
+                    }
+                    let (from, to) = dag.edge_endpoints(dag_e as E);
+                    let group = options.node_group(from as usize);
+                    if group.is_some() && group == options.node_group(to as usize) {
+                        return None;
+                    }
+                    let c = cg
+                        .clusters
+                        .common_ancestor(cg.clusters.cluster_of(from), cg.clusters.cluster_of(to));
+                    Some(c.map_or(0, |c| c + 1) * 2 + removal.reversed[e] as u32)
+                })
+                .collect();
+            concentrate_edges(&mut pg, &cg.graph, &groups);
+        }
+        if !cg.clusters.is_empty() {
+            let virt_endpoints: Vec<(V, V)> = pg
+                .virt_orig
+                .iter()
+                .map(|&e| cg.graph.edge_endpoints(e))
+                .collect();
+            clusters::add_virtual_verts(&mut cg.clusters, virt_endpoints.into_iter());
+        }
+        (cg, pg)
+    }
+
+    /// Returns the options for ordering `pg`: `options.ordering`, with the offsets of the ports,
+    /// the constraints of `options.edge_ordering`, and the constraints that keep the order of
+    /// `previous`.
+    fn ordering_options(
+        &self,
+        previous: Option<&Layout>,
+        layers: &LayerMap,
+        cg: &clusters::ClusterGraph,
+        pg: &ProperGraph,
+    ) -> OrderingOptions {
+        let options = self.options;
+        let nv = self.graph.num_verts();
+        let mut ordering = options.ordering.clone();
+        if let Some(previous) = previous {
+            ordering
+                .constraints
+                .extend(keep_order(layers, options.rank_dir, previous));
+        }
+        if !options.edge_ports.is_empty() {
+            // Only the first and last proper edges of an edge end at input vertices. The edges
+            // of cluster borders have no ports.
+            let port_offset = |port: &TbPort, v: V| -> f64 {
+                let size = self.tb_sizes[v as usize];
+                if size.x > 0.0 {
+                    port.anchor(Vec2::default(), size).x / size.x
+                } else {
+                    0.0
+                }
+            };
+            ordering.port_offsets = pg
+                .edges
+                .all_values()
+                .iter()
+                .zip(pg.edge_orig.iter())
+                .map(
+                    |(&(from, to), &dag_e)| match self.dag_ports.get(dag_e as usize) {
+                        Some((from_port, to_port)) => (
+                            if (from as usize) < nv {
+                                port_offset(from_port, from)
+                            } else {
+                                0.0
+                            },
+                            if (to as usize) < nv {
+                                port_offset(to_port, to)
+                            } else {
+                                0.0
+                            },
+                        ),
+                        None => (0.0, 0.0),
+                    },
+                )
+                .collect();
+        }
+        ordering.constraints.extend(edge_order_constraints(
+            self.graph,
+            &options.edge_ordering,
+            &self.removal.orig_edge_dag,
+            pg,
+            &cg.graph,
+        ));
+        ordering
+    }
+
+    /// Assigns coordinates to the vertices of the ordered proper graph, and computes the boxes
+    /// of the clusters.
+    fn assign_coordinates(
+        &self,
+        cg: &clusters::ClusterGraph,
+        pg: &ProperGraph,
+        rank_sep: f64,
+        previous: Option<&Layout>,
+    ) -> Coordinates {
+        let options = self.options;
+        let nv = self.graph.num_verts();
+        let loops = &self.loops;
+
+        // Only the input vertices and the cluster label vertices have a size.
+        let mut v_size: Vec<Vec2<f64>> = vec![Vec2::default(); pg.num_verts()];
+        v_size[..nv].copy_from_slice(&self.tb_sizes);
+        for &(v, width) in cg.labels.iter() {
+            v_size[v as usize].x = width;
+        }
+
+        // The label of an edge is carried by the virtual vertex in the middle of its chain.
+        let mut label_vert: Vec<Option<V>> = vec![None; self.graph.num_edges()];
+        for (e, size) in self.tb_label_sizes.iter().enumerate() {
+            if let (Some(size), Some(dag_e)) = (size, self.removal.orig_edge_dag[e]) {
+                let virts = pg.edge_virts.entry_values(dag_e as usize);
+                if !virts.is_empty() {
+                    let v = virts[(virts.len() - 1) / 2];
+                    v_size[v as usize] = *size;
+                    label_vert[e] = Some(v);
+                }
+            }
+        }
+        for (size, labels) in v_size.iter_mut().zip(loops.labels.iter()) {
+            size.y = size.y.max(labels.y);
+        }
+        let v_width: Vec<f64> = v_size.iter().map(|s| s.x).collect();
+
+        // The group of each vertex. A virtual vertex is in a group if both ends of its edge are.
+        let v_group: Vec<Option<u32>> = (0..pg.num_verts() as V)
+            .map(|v| match pg.orig_edge_of_virt(v) {
+                Some(dag_e) => {
+                    let (from, to) = cg.graph.edge_endpoints(dag_e);
+                    let group = options.node_group(from as usize);
+                    group.filter(|&g| options.node_group(to as usize) == Some(g))
+                }
+                None if (v as usize) < nv => options.node_group(v as usize),
+                None => None,
+            })
+            .collect();
+
+        // Everything other than the input vertices is a dummy. The chains of cluster border
+        // vertices take priority over the edges between vertices of a group, which take
+        // priority over the chains of long edges. Vertices are separated by `node_sep`, except
+        // for the space between a border vertex and the inside of its cluster. Space for
+        // self-loops is added to the right of their vertices.
+        let padding = options.cluster_padding;
+        let dummy_level = |v: V| match cg.clusters.border_of(v) {
+            Some(_) => 3,
+            None => (v as usize >= nv) as u32,
+        };
+        let segment_level = |u: V, v: V| {
+            let level = dummy_level(u).min(dummy_level(v));
+            match v_group[u as usize] {
+                Some(g) if v_group[v as usize] == Some(g) => level.max(2),
+                _ => level,
+            }
+        };
+        let separation = |left: V, right: V| {
+            let gap = if cg.clusters.border_of(left) == Some(ClusterBorder::Left)
+                || cg.clusters.border_of(right) == Some(ClusterBorder::Right)
+            {
+                padding
+            } else {
+                options.node_sep
+            };
+            (v_width[left as usize] + v_width[right as usize]) / 2.0 + loops.space(left) + gap
+        };
+        let x = assign_x_coordinates_with(pg, &v_width, &segment_level, &separation);
+        let x = match previous {
+            Some(previous) => {
+                let num_old = previous.node_centers.len().min(nv);
+                let old_x: Vec<f64> = previous.node_centers[..num_old]
+                    .iter()
+                    .map(|&c| options.rank_dir.inverse_vector(c).x)
+                    .collect();
+                keep_x_coordinates(pg, &x, &old_x, &separation)
+            }
+            None => x,
+        };
+
+        let label_heights: Vec<f64> = options
+            .clusters
+            .iter()
+            .map(|c| options.rank_dir.swap_size(c.label_size).y)
+            .collect();
+        let extents = clusters::cluster_extents(&cg.clusters, &cg.span, &label_heights, padding);
+        let mut margins = clusters::layer_margins(pg.num_layers(), &cg.span, &extents);
+        let flat_label_sizes: Vec<Option<Vec2<f64>>> = pg
+            .flat_edge_orig
+            .iter()
+            .map(|&dag_e| self.tb_label_sizes[self.removal.dag_edge_orig[dag_e as usize] as usize])
+            .collect();
+        let flat_label_heights: Vec<f64> = flat_label_sizes
+            .iter()
+            .map(|s| s.map_or(0.0, |s| s.y))
+            .collect();
+        let flat_heights = flat_edge_heights(pg, &flat_label_heights, options.rank_sep / 2.0);
+        for (layer, range) in (0..pg.num_layers()).map(|l| (l, pg.flat_edges.entry_values_range(l)))
+        {
+            let above = range
+                .map(|i| flat_heights[i] + flat_label_heights[i])
+                .fold(0.0, f64::max);
+            margins[layer].0 += above;
+        }
+        let layer_height = layer_heights(pg, &v_size);
+        let layer_y = layer_y_coordinates(&layer_height, &margins, rank_sep);
+        let centers: Vec<Vec2<f64>> = (0..pg.num_verts())
+            .map(|v| Vec2 {
+                x: x[v],
+                y: layer_y[pg.v_layer[v] as usize],
+            })
+            .collect();
+
+        // Each cluster's box extends from its left border vertices to its right border vertices.
+        let cluster_boxes: Vec<Rect> = (0..options.clusters.len())
+            .map(|c| match cg.span[c] {
+                Some((lo, hi)) => {
+                    let (lo, hi) = (lo as usize, hi as usize);
+                    let left_x = cg.left.entry_values(c).iter().map(|&v| x[v as usize]);
+                    let right_x = cg.right.entry_values(c).iter().map(|&v| x[v as usize]);
+                    Rect {
+                        min: Vec2 {
+                            x: left_x.fold(f64::INFINITY, f64::min),
+                            y: layer_y[hi] - layer_height[hi] / 2.0 - extents[c].0,
+                        },
+                        max: Vec2 {
+                            x: right_x.fold(f64::NEG_INFINITY, f64::max),
+                            y: layer_y[lo] + layer_height[lo] / 2.0 + extents[c].1,
+                        },
+                    }
+                }
+                None => Rect::default(),
+            })
+            .collect();
+
+        Coordinates {
+            centers,
+            v_size,
+            label_vert,
+            layer_y,
+            layer_height,
+            flat_heights,
+            flat_label_sizes,
+            cluster_boxes,
+        }
+    }
+
+    /// Draws each edge of the input graph, through the vertices of its chain in the proper
+    /// graph, and places the edge labels.
+    fn route_edges(
+        &self,
+        cg: &clusters::ClusterGraph,
+        pg: &ProperGraph,
+        coords: &Coordinates,
+        rank_sep: f64,
+        previous: Option<&Layout>,
+    ) -> Routes {
+        let graph = self.graph;
+        let options = self.options;
+        let removal = &self.removal;
+        let tb_sizes = &self.tb_sizes;
+        let loops = &self.loops;
+        let Coordinates {
+            centers,
+            v_size,
+            label_vert,
+            layer_y,
+            layer_height,
+            flat_heights,
+            flat_label_sizes,
+            cluster_boxes,
+        } = coords;
+
+        // The index of each flat edge in `pg.flat_edges`, by its index in `cg.graph`.
+        let mut edge_flat_index: Vec<Option<usize>> = vec![None; cg.graph.num_edges()];
+        for (i, &e) in pg.flat_edge_orig.iter().enumerate() {
+            edge_flat_index[e as usize] = Some(i);
+        }
+
+        // The space that spline corridors may use.
+        let v_extent: Vec<(f64, f64)> = (0..pg.num_verts())
+            .map(|v| {
+                let (x, half) = (centers[v].x, v_size[v].x / 2.0);
+                (x - half, x + half + loops.space(v as V))
+            })
+            .collect();
+        let layer_extent: Vec<(f64, f64)> = layer_y
+            .iter()
+            .zip(layer_height.iter())
+            .map(|(&y, &h)| (y - h / 2.0, y + h / 2.0))
+            .collect();
+        let corridors = splines::Corridors {
+            pg,
+            v_extent: &v_extent,
+            layer_extent: &layer_extent,
+            left: v_extent.iter().map(|e| e.0).fold(f64::INFINITY, f64::min),
+            right: v_extent
+                .iter()
+                .map(|e| e.1)
+                .fold(f64::NEG_INFINITY, f64::max),
+        };
+
+        // Edges that leave from a side of a vertex go this far in the direction of the side.
+        let port_stub = options.node_sep.min(rank_sep) / 2.0;
+
+        // The labels are computed in the same frame as the vertices, as rectangles.
+        let mut label_rects: Vec<Option<Rect>> = vec![None; graph.num_edges()];
+
+        let mut edge_points: RampTable<Vec2<f64>> =
+            RampTable::with_capacity(graph.num_edges(), pg.edges.num_values() + graph.num_edges());
+        // The box of the cluster that an edge connects at its `inner` end, if the cluster
+        // contains the vertex at that end and not the vertex at the `outer` end.
+        let compound_box = |c: Option<usize>, inner: V, outer: V| -> Option<&Rect> {
+            let c = c?;
+            assert!(c < options.clusters.len(), "edge cluster is out of range");
+            let contains = |v: V| {
+                let path = cg.clusters.path(cg.clusters.cluster_of(v));
+                path.contains(&(c as u32))
+            };
+            if cg.span[c].is_some() && contains(inner) && !contains(outer) {
+                Some(&cluster_boxes[c])
+            } else {
+                None
+            }
+        };
+
+        let mut edge_splines: RampTable<Vec2<f64>> =
+            RampTable::with_capacity(graph.num_edges(), 3 * edge_points.num_values());
+        let kept = match previous {
+            Some(previous) => self.kept_routes(previous, centers),
+            None => Vec::new(),
+        };
+        for (e, &dag_e) in removal.orig_edge_dag.iter().enumerate() {
+            if let (Some(previous), Some(&Some(old_e))) = (previous, kept.get(e)) {
+                // The route of `previous` moves with the `from` vertex of the edge.
+                let (from, _) = graph.edge_endpoints(e as E);
+                let (old_center, center) =
+                    (previous.node_centers[from as usize], centers[from as usize]);
+                let to_tb =
+                    |&p: &Vec2<f64>| center + options.rank_dir.inverse_vector(p - old_center);
+                edge_points
+                    .push_entry_extend(previous.edge_points.entry_values(old_e).iter().map(to_tb));
+                edge_splines
+                    .push_entry_extend(previous.edge_splines.entry_values(old_e).iter().map(to_tb));
+                if let (Some(p), Some(size)) =
+                    (previous.edge_label_positions[old_e], self.tb_label_sizes[e])
+                {
+                    label_rects[e] = Some(Rect::from_center_size(to_tb(&p), size));
+                }
+                continue;
+            }
+            let mut curve: Option<Vec<Vec2<f64>>> = None;
+            if let Some(dag_e) = dag_e {
+                let chain = pg.edge_chain(&cg.graph, dag_e);
+                let (first, last) = (chain[0] as usize, chain[chain.len() - 1] as usize);
+                let flat_index = edge_flat_index[dag_e as usize];
+                let mut points: Vec<Vec2<f64>> = match flat_index {
+                    Some(i) if flat_heights[i] > 0.0 => {
+                        let layer = pg.v_layer[first] as usize;
+                        let arc_y = layer_y[layer] - layer_height[layer] / 2.0 - flat_heights[i];
+                        let (a, b) = (centers[first], centers[last]);
+                        if let Some(size) = flat_label_sizes[i] {
+                            let center = Vec2 {
+                                x: (a.x + b.x) / 2.0,
+                                y: arc_y - size.y / 2.0,
+                            };
+                            label_rects[e] = Some(Rect::from_center_size(center, size));
+                        }
+                        vec![
+                            Vec2 {
+                                x: a.x,
+                                y: a.y - tb_sizes[first].y / 2.0,
+                            },
+                            Vec2 { x: a.x, y: arc_y },
+                            Vec2 { x: b.x, y: arc_y },
+                            Vec2 {
+                                x: b.x,
+                                y: b.y - tb_sizes[last].y / 2.0,
+                            },
+                        ]
+                    }
+                    _ => {
+                        // The edge passes along the left side of its label.
+                        let mut points: Vec<Vec2<f64>> = Vec::with_capacity(chain.len() + 1);
+                        for &v in chain.iter() {
+                            let c = centers[v as usize];
+                            if Some(v) == label_vert[e] {
+                                let size = v_size[v as usize];
+                                label_rects[e] = Some(Rect::from_center_size(c, size));
+                                let x = c.x - size.x / 2.0;
+                                points.push(Vec2 {
+                                    x,
+                                    y: c.y - size.y / 2.0,
+                                });
+                                points.push(Vec2 {
+                                    x,
+                                    y: c.y + size.y / 2.0,
+                                });
+                            } else {
+                                points.push(c);
+                            }
+                        }
+                        // Each end of the edge starts at its port, and is either clipped to the
+                        // boundary of the vertex or leaves through a side.
+                        let n = points.len();
+                        let (from_port, to_port) = self.dag_ports[dag_e as usize];
+                        points[0] = from_port.anchor(centers[first], tb_sizes[first]);
+                        points[n - 1] = to_port.anchor(centers[last], tb_sizes[last]);
+                        let (start, start_stub) =
+                            from_port.attach(centers[first], tb_sizes[first], points[1], port_stub);
+                        let (end, end_stub) =
+                            to_port.attach(centers[last], tb_sizes[last], points[n - 2], port_stub);
+                        let mut routed: Vec<Vec2<f64>> = Vec::with_capacity(n + 2);
+                        routed.push(start);
+                        routed.extend(start_stub);
+                        routed.extend_from_slice(&points[1..n - 1]);
+                        routed.extend(end_stub);
+                        routed.push(end);
+
+                        // The curve leaves and enters through the sides of ports, like the
+                        // stubs. Flat edges that are drawn as straight lines stay straight.
+                        let start_dir = start_stub.map_or(Vec2::default(), |p| p - start);
+                        let end_dir = end_stub.map_or(Vec2::default(), |p| end - p);
+                        curve = match options.edge_routing {
+                            _ if flat_index.is_some() => None,
+                            EdgeRouting::Polyline => None,
+                            EdgeRouting::Spline => {
+                                let boxes = corridors.boxes(&chain, &routed, label_vert[e]);
+                                splines::route_spline(&boxes, start, end, start_dir, end_dir)
+                            }
+                            EdgeRouting::Line | EdgeRouting::Curved => {
+                                let (start, start_stub) = from_port.attach(
+                                    centers[first],
+                                    tb_sizes[first],
+                                    points[n - 1],
+                                    port_stub,
+                                );
+                                let (end, end_stub) = to_port.attach(
+                                    centers[last],
+                                    tb_sizes[last],
+                                    points[0],
+                                    port_stub,
+                                );
+                                if options.edge_routing == EdgeRouting::Line {
+                                    let mut line: Vec<Vec2<f64>> = vec![start];
+                                    line.extend(start_stub);
+                                    line.extend(end_stub);
+                                    line.push(end);
+                                    Some(splines::polyline_to_bezier(&line))
+                                } else {
+                                    Some(splines::curved_arc(start, end, start_dir, end_dir))
+                                }
+                            }
+                            EdgeRouting::Ortho => {
+                                // Ports that are not on a side are left vertically.
+                                let below = Vec2 {
+                                    x: 0.0,
+                                    y: tb_sizes[first].y,
+                                };
+                                let above = Vec2 {
+                                    x: 0.0,
+                                    y: tb_sizes[last].y,
+                                };
+                                let (start, start_stub) = from_port.attach(
+                                    centers[first],
+                                    tb_sizes[first],
+                                    points[0] + below,
+                                    port_stub,
+                                );
+                                let (end, end_stub) = to_port.attach(
+                                    centers[last],
+                                    tb_sizes[last],
+                                    points[n - 1] - above,
+                                    port_stub,
+                                );
+                                let mut route: Vec<Vec2<f64>> = vec![start];
+                                route.extend(start_stub);
+                                route.extend_from_slice(&points[1..n - 1]);
+                                route.extend(end_stub);
+                                route.push(end);
+                                let ortho = splines::ortho_polyline(&route, &layer_extent);
+                                Some(splines::polyline_to_bezier(&ortho))
+                            }
+                        };
+                        routed
+                    }
+                };
+                let mut curve = curve.unwrap_or_else(|| splines::polyline_to_bezier(&points));
+                if removal.reversed[e] {
+                    curve.reverse();
+                    points.reverse();
+                }
+
+                // Clip the ends of edges that connect clusters. The head is clipped by reversing
+                // the edge, so that the part to remove is at the start.
+                let (from, to) = graph.edge_endpoints(e as E);
+                let ends = options.edge_clusters(e);
+                for &(c, inner, outer) in [(ends.tail, from, to), (ends.head, to, from)].iter() {
+                    if let Some(r) = compound_box(c, inner, outer) {
+                        if let (Some(p), Some(b)) = (
+                            clip_polyline_start(&points, r),
+                            splines::clip_curve_start(&curve, r),
+                        ) {
+                            points = p;
+                            curve = b;
+                        }
+                    }
+                    points.reverse();
+                    curve.reverse();
+                }
+                edge_splines.push_entry_copy(&curve);
+                edge_points.push_entry_copy(&points);
+            } else {
+                let (v, _) = graph.edge_endpoints(e as E);
+                let v = v as usize;
+                let points = self_loop_points(
+                    centers[v],
+                    tb_sizes[v],
+                    loops.index[e],
+                    loops.num[v],
+                    loops.step,
+                );
+                edge_splines.push_entry_copy(&splines::polyline_to_bezier(&points));
+                edge_points.push_entry_copy(&points);
+                if let Some(size) = self.tb_label_sizes[e] {
+                    let center = Vec2 {
+                        x: centers[v].x
+                            + tb_sizes[v].x / 2.0
+                            + loops.num[v] as f64 * loops.step
+                            + size.x / 2.0,
+                        y: centers[v].y - loops.labels[v].y / 2.0 + loops.label_offset[e],
+                    };
+                    label_rects[e] = Some(Rect::from_center_size(center, size));
+                }
+            }
+        }
+
+        Routes {
+            edge_points,
+            edge_splines,
+            label_rects,
+        }
+    }
+
+    /// Returns the edge of `previous` whose route each edge keeps, if any. An edge is matched
+    /// with the edge of `previous` that has the same endpoints and the same index among the
+    /// edges between them. It keeps the route if it is not a self-loop, if it has a label
+    /// exactly when the old edge had one, and if its endpoints are in the same place relative to
+    /// each other as in `previous`.
+    fn kept_routes(&self, previous: &Layout, centers: &[Vec2<f64>]) -> Vec<Option<usize>> {
+        let rank_dir = self.options.rank_dir;
+        let num_old = previous.node_centers.len();
+        let mut old_edges: HashMap<(V, V), Vec<usize>> = HashMap::new();
+        for (old_e, &ends) in previous.edge_endpoints.iter().enumerate().rev() {
+            old_edges.entry(ends).or_default().push(old_e);
+        }
+        self.graph
+            .iter_edges_flattened()
+            .enumerate()
+            .map(|(e, (from, to))| {
+                let old_e = old_edges.get_mut(&(from, to)).and_then(Vec::pop)?;
+                let (from, to) = (from as usize, to as usize);
+                if from == to || from >= num_old || to >= num_old {
+                    return None;
+                }
+                let old_label = previous.edge_label_positions.get(old_e).copied().flatten();
+                if old_label.is_some() != self.tb_label_sizes[e].is_some() {
+                    return None;
+                }
+                let old = rank_dir
+                    .inverse_vector(previous.node_centers[to] - previous.node_centers[from]);
+                let moved = old - (centers[to] - centers[from]);
+                if moved.x.abs() > MOVE_TOLERANCE || moved.y.abs() > MOVE_TOLERANCE {
+                    return None;
+                }
+                Some(old_e)
+            })
+            .collect()
+    }
+
+    /// Transforms the drawing from the top-to-bottom frame to `options.rank_dir`, and returns
+    /// it as a `Layout`.
+    fn transform(
+        &self,
+        cg: &clusters::ClusterGraph,
+        coords: &Coordinates,
+        routes: Routes,
+        node_layers: Vec<u32>,
+    ) -> Layout {
+        let rank_dir = self.options.rank_dir;
+        let nv = self.graph.num_verts();
+        let Routes {
+            mut edge_points,
+            mut edge_splines,
+            label_rects,
+        } = routes;
+        let tb_bounds = compute_bounding_box(
+            &coords.centers[..nv],
+            &self.tb_sizes,
+            &edge_points,
+            coords
+                .cluster_boxes
+                .iter()
+                .zip(cg.span.iter())
+                .filter(|(_, span)| span.is_some())
+                .map(|(r, _)| r)
+                .chain(label_rects.iter().flatten()),
+        );
+        // Curves lie within their control points.
+        let tb_bounds = edge_splines
+            .all_values()
+            .iter()
+            .fold(tb_bounds, |r, &p| r.union_point(p));
+        let node_centers: Vec<Vec2<f64>> = coords.centers[..nv]
+            .iter()
+            .map(|&p| rank_dir.transform_point(p, &tb_bounds))
+            .collect();
+        for p in edge_points
+            .all_values_mut()
+            .iter_mut()
+            .chain(edge_splines.all_values_mut().iter_mut())
+        {
+            *p = rank_dir.transform_point(*p, &tb_bounds);
+        }
+        let transform_rect = |r: &Rect| {
+            let corner = rank_dir.transform_point(r.min, &tb_bounds);
+            Rect {
+                min: corner,
+                max: corner,
+            }
+            .union_point(rank_dir.transform_point(r.max, &tb_bounds))
+        };
+        let bounding_box = transform_rect(&tb_bounds);
+        let cluster_boxes: Vec<Rect> = coords
+            .cluster_boxes
+            .iter()
+            .zip(cg.span.iter())
+            .map(|(r, span)| match span {
+                Some(_) => transform_rect(r),
+                None => Rect::default(),
+            })
+            .collect();
+        let edge_label_positions: Vec<Option<Vec2<f64>>> = label_rects
+            .iter()
+            .map(|r| r.map(|r| rank_dir.transform_point((r.min + r.max) / 2.0, &tb_bounds)))
+            .collect();
+        Layout {
+            node_centers,
+            node_sizes: (0..nv).map(|v| self.options.node_size(v)).collect(),
+            bounding_box,
+            edge_points,
+            edge_splines,
+            cluster_boxes,
+            edge_label_positions,
+            node_layers,
+            edge_endpoints: self.graph.iter_edges_flattened().collect(),
+        }
+    }
+}
+
+/// Assigns the vertices of `dag` to layers, with network simplex if there are any
+/// `rank_constraints`, and otherwise with `layering`.
+fn layer_dag(
+    dag: &Graph,
+    layering: LayeringMethod,
+    rank_constraints: &[RankConstraint],
+) -> Result<LayerMap, Error> {
+    let ones = vec![1; dag.num_edges()];
+    if !rank_constraints.is_empty() {
+        return create_layer_map_with_constraints(dag, &ones, &ones, rank_constraints);
+    }
+    match layering {
+        LayeringMethod::LongestPath => create_layer_map(dag),
+        LayeringMethod::NetworkSimplex => create_layer_map_network_simplex(dag, &ones, &ones),
+        LayeringMethod::CoffmanGraham { max_width } => {
+            create_layer_map_coffman_graham(dag, max_width)
+        }
+    }
+}
+
+/// Assigns the vertices that are in `previous` to the same layers as before, and the new
+/// vertices to layers near their neighbors. The new vertices are layered by themselves, and
+/// then each connected group of new vertices is shifted up or down as a whole, so that its edges
+/// to old vertices are as short as possible while pointing down. A group that has a vertex in a
+/// `Same` constraint with an old vertex is shifted to the layer of that vertex instead. Returns
+/// the edges, oriented so that they point down, and the layers. Flat edges point to the right,
+/// in the order of the vertices in `previous`; new vertices are ordered after existing ones.
+fn keep_layers(
+    graph: &Graph,
+    options: &LayeredOptions,
+    previous: &Layout,
+) -> Result<(CycleRemoval, LayerMap), Error> {
+    let nv = graph.num_verts();
+    let num_old = previous.node_layers.len().min(nv);
+    let is_new = |v: V| v as usize >= num_old;
+
+    // Layer the subgraph of the new vertices, in which vertex `v` is `v - num_old`. The rank
+    // constraints are restricted to the new vertices.
+    let pairs: Vec<(V, V)> = graph
+        .iter_edges_flattened()
+        .filter(|&(from, to)| is_new(from) && is_new(to))
+        .map(|(from, to)| (from - num_old as V, to - num_old as V))
+        .collect();
+    let (new_graph, _) = Graph::from_edge_list(nv - num_old, &pairs);
+    let new_constraints: Vec<RankConstraint> = options
+        .rank_constraints
+        .iter()
+        .filter_map(|c| {
+            let verts: Vec<V> = c
+                .verts()
+                .iter()
+                .filter(|&&v| is_new(v))
+                .map(|&v| v - num_old as V)
+                .collect();
+            if verts.is_empty() {
+                return None;
+            }
+            Some(match c {
+                RankConstraint::Same(_) => RankConstraint::Same(verts),
+                RankConstraint::Min(_) => RankConstraint::Min(verts),
+                RankConstraint::Source(_) => RankConstraint::Source(verts),
+                RankConstraint::Max(_) => RankConstraint::Max(verts),
+                RankConstraint::Sink(_) => RankConstraint::Sink(verts),
+            })
+        })
+        .collect();
+    let new_dag = remove_cycles(&new_graph, options.cycle_removal).dag;
+    let fresh = layer_dag(&new_dag, options.layering, &new_constraints).map_err(|e| match e {
+        Error::ConflictingRankConstraints(verts) => {
+            Error::ConflictingRankConstraints(verts.iter().map(|&v| v + num_old as V).collect())
+        }
+        e => e,
+    })?;
+    let fresh_layer = |v: V| fresh.v_layer[v as usize - num_old] as i64;
+
+    // The layer of the first old vertex in the `Same` constraint of each new vertex, if any.
+    let mut same_layer: Vec<Option<i64>> = vec![None; nv];
+    for c in options.rank_constraints.iter() {
+        if let RankConstraint::Same(verts) = c {
+            if let Some(&old) = verts.iter().find(|&&v| !is_new(v)) {
+                for &v in verts.iter().filter(|&&v| is_new(v)) {
+                    same_layer[v as usize] = Some(previous.node_layers[old as usize] as i64);
+                }
+            }
+        }
+    }
+
+    let mut v_layer: Vec<Option<i64>> = vec![None; nv];
+    for (layer, &old) in v_layer.iter_mut().zip(previous.node_layers.iter()) {
+        *layer = Some(old as i64);
+    }
+    let t_graph = graph.transpose();
+    let mut group: Vec<V> = Vec::new();
+    let mut shifts: Vec<i64> = Vec::new();
+    for seed in num_old as V..nv as V {
+        if v_layer[seed as usize].is_some() {
+            continue;
+        }
+
+        // Find the group of `seed`, and the shifts that would make each of its edges to old
+        // vertices point down by one layer. Vertices of the group are marked with a layer
+        // until the group is shifted.
+        group.clear();
+        shifts.clear();
+        group.push(seed);
+        v_layer[seed as usize] = Some(0);
+        let (mut lowest, mut highest) = (i64::MIN, i64::MAX);
+        let mut pinned: Option<i64> = None;
+        let mut i = 0;
+        while i < group.len() {
+            let v = group[i];
+            i += 1;
+            let layer = fresh_layer(v);
+            if let Some(same) = same_layer[v as usize] {
+                pinned = pinned.or(Some(same - layer));
+            }
+            let down = graph.edges_from(v).iter().map(|&w| (w, true));
+            let up = t_graph.edges_from(v).iter().map(|&w| (w, false));
+            for (w, is_down) in down.chain(up) {
+                if is_new(w) {
+                    if v_layer[w as usize].is_none() {
+                        v_layer[w as usize] = Some(0);
+                        group.push(w);
+                    }
+                } else if is_down {
+                    let shift = previous.node_layers[w as usize] as i64 + 1 - layer;
+                    lowest = lowest.max(shift);
+                    shifts.push(shift);
+                } else {
+                    let shift = previous.node_layers[w as usize] as i64 - 1 - layer;
+                    highest = highest.min(shift);
+                    shifts.push(shift);
+                }
+            }
+        }
+
+        // The median of the shifts minimizes the total length of the edges to old vertices.
+        // If the edges cannot all point down, some of them are reversed.
+        shifts.sort_unstable();
+        let shift =
+            pinned.unwrap_or_else(|| match shifts.get(shifts.len().saturating_sub(1) / 2) {
+                Some(&median) if lowest <= highest => median.max(lowest).min(highest),
+                Some(&median) => median,
+                None => 0,
+            });
+        for &v in group.iter() {
+            v_layer[v as usize] = Some(fresh_layer(v) + shift);
+        }
+    }
+    let v_layer: Vec<i64> = v_layer.into_iter().map(Option::unwrap).collect();
+    let lowest = v_layer.iter().copied().min().unwrap_or(0);
+    let highest = v_layer.iter().copied().max().unwrap_or(0);
+    let layers = LayerMap {
+        num_layers: (highest - lowest) as usize + 1,
+        v_layer: v_layer.iter().map(|&l| (l - lowest) as u32).collect(),
+    };
+
+    let rank_dir = options.rank_dir;
+    let order_key = |v: V| match previous.node_centers.get(v as usize) {
+        Some(&c) if !is_new(v) => (0, rank_dir.inverse_vector(c).x, v),
+        _ => (1, 0.0, v),
+    };
+    let reversed: Vec<bool> = graph
+        .iter_edges_flattened()
+        .map(|(from, to)| {
+            let (from_layer, to_layer) = (v_layer[from as usize], v_layer[to as usize]);
+            from_layer < to_layer
+                || from_layer == to_layer
+                    && order_key(from).partial_cmp(&order_key(to)) == Some(Ordering::Greater)
+        })
+        .collect();
+    Ok((build_dag(graph, reversed), layers))
+}
+
+/// Vertices that move less than this, relative to each other, have not moved.
+const MOVE_TOLERANCE: f64 = 1e-6;
+
+/// Moves the vertices of `pg` from their coordinates `x` toward the coordinates `old_x` that
+/// vertices `0..old_x.len()` had in a previous layout. Each of these old vertices has its old x
+/// as its target. Every other vertex has a target that keeps its offset from the nearest old
+/// vertex (along the edges of `pg`), or from all of them if it is not connected to any. The
+/// vertices of each layer are then placed as close to their targets as `separation` allows, by
+/// least squares, keeping their order. The old vertices take priority, so they only move if
+/// they no longer fit between each other.
+fn keep_x_coordinates(
+    pg: &ProperGraph,
+    x: &[f64],
+    old_x: &[f64],
+    separation: &dyn Fn(V, V) -> f64,
+) -> Vec<f64> {
+    let nv = pg.num_verts();
+    let num_old = old_x.len();
+
+    // Spread the shift of each old vertex to the vertices around it, in breadth-first order.
+    let pairs: Vec<(V, V)> = pg
+        .edges
+        .all_values()
+        .iter()
+        .flat_map(|&(from, to)| once((from, to)).chain(once((to, from))))
+        .collect();
+    let (neighbors, _) = Graph::from_edge_list(nv, &pairs);
+    let mut shift: Vec<Option<f64>> = vec![None; nv];
+    let mut queue: VecDeque<V> = VecDeque::new();
+    for v in 0..num_old {
+        shift[v] = Some(old_x[v] - x[v]);
+        queue.push_back(v as V);
+    }
+    while let Some(v) = queue.pop_front() {
+        for &w in neighbors.edges_from(v).iter() {
+            if shift[w as usize].is_none() {
+                shift[w as usize] = shift[v as usize];
+                queue.push_back(w);
+            }
+        }
+    }
+    let mut old_shifts: Vec<f64> = shift[..num_old].iter().map(|s| s.unwrap()).collect();
+    old_shifts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median_shift = old_shifts.get(num_old / 2).copied().unwrap_or(0.0);
+
+    // With `offset` the least distance from the first vertex of a layer to each vertex, the
+    // vertices are separated if their positions minus their offsets are nondecreasing. The
+    // closest such positions are found by pooling adjacent blocks of vertices that violate the
+    // order, and putting each block at the mean of the targets of its old vertices, or of all
+    // of its vertices if it has no old ones.
+    let mut new_x: Vec<f64> = vec![0.0; nv];
+    let mut offsets: Vec<f64> = Vec::new();
+    let mut blocks: Vec<Block> = Vec::new();
+    for layer in 0..pg.num_layers() {
+        let verts = pg.verts.entry_values(layer);
+        offsets.clear();
+        blocks.clear();
+        for (i, &v) in verts.iter().enumerate() {
+            let offset = match i {
+                0 => 0.0,
+                _ => offsets[i - 1] + separation(verts[i - 1], v),
+            };
+            offsets.push(offset);
+            let target = x[v as usize] - offset + shift[v as usize].unwrap_or(median_shift);
+            let is_old = (v as usize) < num_old;
+            let mut block = Block {
+                len: 1,
+                sum: target,
+                num_old: is_old as usize,
+                old_sum: if is_old { target } else { 0.0 },
+            };
+            while let Some(last) = blocks.last() {
+                if last.position() <= block.position() {
+                    break;
+                }
+                block = Block {
+                    len: last.len + block.len,
+                    sum: last.sum + block.sum,
+                    num_old: last.num_old + block.num_old,
+                    old_sum: last.old_sum + block.old_sum,
+                };
+                blocks.pop();
+            }
+            blocks.push(block);
+        }
+        let mut i = 0;
+        for block in blocks.iter() {
+            for _ in 0..block.len {
+                new_x[verts[i] as usize] = offsets[i] + block.position();
+                i += 1;
+            }
+        }
+    }
+    new_x
+}
+
+/// A run of adjacent vertices of a layer that `keep_x_coordinates` moves together. The targets
+/// are those of the vertices minus their offsets.
+struct Block {
+    len: usize,
+    sum: f64,
+    num_old: usize,
+    old_sum: f64,
+}
+
+impl Block {
+    fn position(&self) -> f64 {
+        if self.num_old > 0 {
+            self.old_sum / self.num_old as f64
+        } else {
+            self.sum / self.len as f64
+        }
+    }
+}
+
+/// Returns `LeftOf` constraints that keep the vertices of each layer that are in `previous` in
+/// the order in which they were drawn.
+fn keep_order(layers: &LayerMap, rank_dir: RankDir, previous: &Layout) -> Vec<OrderConstraint> {
+    let num_old = previous.node_centers.len().min(layers.v_layer.len());
+    let mut old: Vec<(u32, f64, V)> = (0..num_old)
+        .map(|v| {
+            let x = rank_dir.inverse_vector(previous.node_centers[v]).x;
+            (layers.v_layer[v], x, v as V)
+        })
+        .collect();
+    old.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    old.windows(2)
+        .filter(|w| w[0].0 == w[1].0)
+        .map(|w| OrderConstraint::LeftOf(w[0].2, w[1].2))
+        .collect()
+}
+
+/// A port, in the top-to-bottom frame.
+#[derive(Copy, Clone, Debug, Default)]
+struct TbPort {
+    offset: Vec2<f64>,
+
+    /// The direction of the side of the vertex that the edge leaves through, if any.
+    side: Option<Vec2<f64>>,
+}
+
+impl TbPort {
+    fn new(port: &Port, rank_dir: RankDir) -> Self {
+        Self {
+            offset: rank_dir.inverse_vector(port.offset),
+            side: port.compass.map(|c| rank_dir.inverse_vector(c.direction())),
+        }
+    }
+
+    /// Returns the point of the port, for a vertex at `center` with size `size`. The offset is
+    /// clamped to the vertex, and for a port on a side, moved to that side.
+    fn anchor(&self, center: Vec2<f64>, size: Vec2<f64>) -> Vec2<f64> {
+        let half = size / 2.0;
+        let mut p = Vec2 {
+            x: self.offset.x.max(-half.x).min(half.x),
+            y: self.offset.y.max(-half.y).min(half.y),
+        };
+        if let Some(side) = self.side {
+            if side.x != 0.0 {
+                p.x = half.x * side.x.signum();
+            }
+            if side.y != 0.0 {
+                p.y = half.y * side.y.signum();
+            }
+        }
+        center + p
+    }
+
+    /// Returns the point where an edge that goes toward `toward` attaches to the vertex. For a
+    /// port on a side, this also returns a point `stub` away from the side, so that the edge
+    /// leaves in the direction of the side.
+    fn attach(
+        &self,
+        center: Vec2<f64>,
+        size: Vec2<f64>,
+        toward: Vec2<f64>,
+        stub: f64,
+    ) -> (Vec2<f64>, Option<Vec2<f64>>) {
+        let anchor = self.anchor(center, size);
+        match self.side {
+            Some(side) => {
+                let len = (side.x * side.x + side.y * side.y).sqrt();
+                (anchor, Some(anchor + side * (stub / len)))
+            }
+            None => (clip_to_box(center, size, anchor, toward), None),
+        }
+    }
+}
+
+/// Converts `edge_ordering` to constraints on the order of the proper graph. For each
+/// constrained vertex, the next vertex along each of its edges (which is virtual, for a long
+/// edge) is constrained to be left of the next vertex along the following edge in the same
+/// layer. Self-loops and flat edges are skipped.
+fn edge_order_constraints(
+    graph: &Graph,
+    edge_ordering: &[Option<EdgeOrdering>],
+    orig_edge_dag: &[Option<E>],
+    pg: &ProperGraph,
+    cluster_graph: &Graph,
+) -> Vec<OrderConstraint> {
+    let mut constraints: Vec<OrderConstraint> = Vec::new();
+    let mut last_in_layer: HashMap<u32, V> = HashMap::new();
+    for (v, ordering) in edge_ordering.iter().enumerate() {
+        let v = v as V;
+        let edges: Vec<E> = match ordering {
+            Some(EdgeOrdering::Out) => {
+                let range = graph.edges().entry_values_range(v as usize);
+                (range.start as E..range.end as E).collect()
+            }
+            Some(EdgeOrdering::In) => graph
+                .iter_edges_flattened()
+                .enumerate()
+                .filter(|&(_, (_, to))| to == v)
+                .map(|(e, _)| e as E)
+                .collect(),
+            None => continue,
+        };
+        last_in_layer.clear();
+        for e in edges {
+            let dag_e = match orig_edge_dag[e as usize] {
+                Some(dag_e) => dag_e,
+                None => continue,
+            };
+            let chain = pg.edge_chain(cluster_graph, dag_e);
+            let next = if chain[0] == v {
+                chain[1]
+            } else {
+                chain[chain.len() - 2]
+            };
+            let layer = pg.v_layer[next as usize];
+            if layer == pg.v_layer[v as usize] {
+                continue;
+            }
+            match last_in_layer.insert(layer, next) {
+                Some(last) if last != next => {
+                    constraints.push(OrderConstraint::LeftOf(last, next));
+                }
+                _ => {}
+            }
+        }
+    }
+    constraints
+}
+
+/// Doubles the layer of every vertex, so that every edge that is not flat spans at least two
+/// layers.
+fn double_layers(layers: &LayerMap) -> LayerMap {
+    LayerMap {
+        num_layers: (2 * layers.num_layers).saturating_sub(1),
+        v_layer: layers.v_layer.iter().map(|&layer| 2 * layer).collect(),
+    }
+}
+
+/// Computes the polyline for self-loop `index` of a vertex that has `count` self-loops. The
+/// loop leaves the right side of the vertex above its center, goes `(index + 1) * step` to the
+/// right, and returns below the center. Loops with higher indices are taller, so the loops of
+/// a vertex are nested and do not cross.
+fn self_loop_points(
+    center: Vec2<f64>,
+    size: Vec2<f64>,
+    index: u32,
+    count: u32,
+    step: f64,
+) -> Vec<Vec2<f64>> {
+    let right = center.x + size.x / 2.0;
+    let outer_x = right + (index + 1) as f64 * step;
+    let dy = size.y / 2.0 * (index + 1) as f64 / (count + 1) as f64;
+    vec![
+        Vec2 {
+            x: right,
+            y: center.y - dy,
+        },
+        Vec2 {
+            x: outer_x,
+            y: center.y - dy,
+        },
+        Vec2 {
+            x: outer_x,
+            y: center.y + dy,
+        },
+        Vec2 {
+            x: right,
+            y: center.y + dy,
+        },
+    ]
+}
+
+/// Chooses how each flat edge is drawn. `label_height` and the result are parallel to
+/// `pg.flat_edges.all_values()`. A height of 0 means that the edge is drawn as a straight line,
+/// which is possible for the first unlabeled edge between two vertices that are next to each
+/// other. Otherwise, the edge is drawn as an arc, and the result is the height of the arc above
+/// the top of the layer. Arcs are stacked so that the horizontal parts of arcs that overlap
+/// (and their labels) are at different heights; shorter arcs are placed lower, and each arc
+/// is at least `step` above the arcs and labels below it.
+fn flat_edge_heights(pg: &ProperGraph, label_height: &[f64], step: f64) -> Vec<f64> {
+    let mut heights: Vec<f64> = vec![0.0; pg.flat_edges.num_values()];
+    for layer in 0..pg.num_layers() {
+        let range = pg.flat_edges.entry_values_range(layer);
+
+        // The span of each edge, as (left position, right position).
+        let spans: Vec<(u32, u32)> = pg.flat_edges.values[range.clone()]
+            .iter()
+            .map(|&(from, to)| {
+                let (a, b) = (pg.v_pos[from as usize], pg.v_pos[to as usize]);
+                (a.min(b), a.max(b))
+            })
+            .collect();
+        let mut by_width: Vec<usize> = (0..spans.len()).collect();
+        by_width.sort_by_key(|&i| (spans[i].1 - spans[i].0, spans[i].0));
+
+        // The span of each edge that has been placed, and the top of its arc and label.
+        let mut placed: Vec<(u32, u32, f64)> = Vec::new();
+        let mut has_straight: Vec<(u32, u32)> = Vec::new();
+        for &i in by_width.iter() {
+            let (left, right) = spans[i];
+            let label = label_height[range.start + i];
+            if right - left == 1 && label == 0.0 && !has_straight.contains(&(left, right)) {
+                has_straight.push((left, right));
+                continue;
+            }
+            let height = step
+                + placed
+                    .iter()
+                    .filter(|&&(l, r, _)| l <= right && left <= r)
+                    .map(|&(_, _, top)| top)
+                    .fold(0.0, f64::max);
+            heights[range.start + i] = height;
+            placed.push((left, right, height + label));
+        }
+    }
+    heights
+}
+
+/// Computes the height of each layer, which is the height of its tallest vertex.
+fn layer_heights(pg: &ProperGraph, v_size: &[Vec2<f64>]) -> Vec<f64> {
+    pg.verts
+        .iter()
+        .map(|layer| {
+            layer
+                .iter()
+                .map(|&v| v_size[v as usize].y)
+                .fold(0.0, f64::max)
+        })
+        .collect()
+}
+
+/// Computes the y coordinate of the center of each layer. Vertices are centered vertically
+/// within their layer. Adjacent layers are separated by `rank_sep`, plus the margins (the space
+/// above and below each layer) that are needed for clusters.
+fn layer_y_coordinates(layer_height: &[f64], margins: &[(f64, f64)], rank_sep: f64) -> Vec<f64> {
+    let num_layers = layer_height.len();
+    let mut layer_y: Vec<f64> = vec![0.0; num_layers];
+    let mut top = 0.0;
+    for layer in (0..num_layers).rev() {
+        let (above, below) = margins[layer];
+        top += above;
+        layer_y[layer] = top + layer_height[layer] / 2.0;
+        top += layer_height[layer] + below + rank_sep;
+    }
+    layer_y
+}
+
+/// Moves `from`, which is inside a box of size `size` that is centered on `center`, toward
+/// `toward` until it reaches the boundary of the box. If `toward` is inside the box, returns
+/// `toward`.
+fn clip_to_box(
+    center: Vec2<f64>,
+    size: Vec2<f64>,
+    from: Vec2<f64>,
+    toward: Vec2<f64>,
+) -> Vec2<f64> {
+    let d = toward - from;
+    let half = size / 2.0;
+    let mut t: f64 = 1.0;
+    if d.x != 0.0 {
+        t = t.min((center.x + half.x * d.x.signum() - from.x) / d.x);
+    }
+    if d.y != 0.0 {
+        t = t.min((center.y + half.y * d.y.signum() - from.y) / d.y);
+    }
+    from + d * t
+}
+
+/// Removes the start of a polyline, up to the point where it leaves `rect`. The first point
+/// must be inside `rect`. Returns `None` if the polyline does not leave `rect`.
+fn clip_polyline_start(points: &[Vec2<f64>], rect: &Rect) -> Option<Vec<Vec2<f64>>> {
+    let i = points.iter().position(|&p| !rect.contains(p))?;
+    let center = (rect.min + rect.max) / 2.0;
+    let size = rect.max - rect.min;
+    let mut clipped: Vec<Vec2<f64>> = Vec::with_capacity(points.len() - i + 1);
+    clipped.push(clip_to_box(center, size, points[i - 1], points[i]));
+    clipped.extend_from_slice(&points[i..]);
+    Some(clipped)
+}
+
+fn compute_bounding_box<'a>(
+    node_centers: &[Vec2<f64>],
+    node_sizes: &[Vec2<f64>],
+    edge_points: &RampTable<Vec2<f64>>,
+    rects: impl Iterator<Item = &'a Rect>,
+) -> Rect {
+    let mut corners = node_centers
+        .iter()
+        .zip(node_sizes.iter())
+        .map(|(&c, &s)| Rect::from_center_size(c, s))
+        .chain(rects.copied())
+        .flat_map(|r| vec![r.min, r.max])
+        .chain(edge_points.all_values().iter().copied());
+    let first = match corners.next() {
+        Some(p) => p,
+        None => return Rect::default(),
+    };
+    corners.fold(
+        Rect {
+            min: first,
+            max: first,
+        },
+        |r, p| r.union_point(p),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    pub(super) const ALL_RANK_DIRS: [RankDir; 4] = [
+        RankDir::TopToBottom,
+        RankDir::BottomToTop,
+        RankDir::LeftToRight,
+        RankDir::RightToLeft,
+    ];
+
+    fn on_boundary(r: &Rect, p: Vec2<f64>) -> bool {
+        r.contains(p)
+            && ((p.x - r.min.x).abs() < EPSILON
+                || (p.x - r.max.x).abs() < EPSILON
+                || (p.y - r.min.y).abs() < EPSILON
+                || (p.y - r.max.y).abs() < EPSILON)
+    }
+
+    /// Checks the properties that every layout should have.
+    pub(super) fn check_layout(graph: &Graph, layout: &Layout) {
+        assert_eq!(layout.node_centers.len(), graph.num_verts());
+        assert_eq!(layout.edge_points.len(), graph.num_edges());
+        assert_eq!(layout.edge_splines.len(), graph.num_edges());
+
+        for v in 0..graph.num_verts() {
+            let r = layout.node_rect(v);
+            assert!(layout.bounding_box.contains(r.min) && layout.bounding_box.contains(r.max));
+            for w in v + 1..graph.num_verts() {
+                let s = layout.node_rect(w);
+                let overlap = r.min.x < s.max.x
+                    && s.min.x < r.max.x
+                    && r.min.y < s.max.y
+                    && s.min.y < r.max.y;
+                assert!(!overlap, "v{} and v{} overlap", v, w);
+            }
+        }
+
+        for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+            let points = layout.edge_points.entry_values(e);
+            assert!(points.len() >= 2);
+            assert!(on_boundary(&layout.node_rect(from as usize), points[0]));
+            assert!(on_boundary(
+                &layout.node_rect(to as usize),
+                points[points.len() - 1]
+            ));
+
+            let curve = layout.edge_splines.entry_values(e);
+            assert!(curve.len() >= 4 && curve.len() % 3 == 1);
+            assert!(on_boundary(&layout.node_rect(from as usize), curve[0]));
+            assert!(on_boundary(
+                &layout.node_rect(to as usize),
+                curve[curve.len() - 1]
+            ));
+        }
+    }
+
+    #[test]
+    fn layered_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph) {
+            for &layering in [
+                LayeringMethod::LongestPath,
+                LayeringMethod::NetworkSimplex,
+                LayeringMethod::CoffmanGraham { max_width: 2 },
+            ]
+            .iter()
+            {
+                for &rank_dir in ALL_RANK_DIRS.iter() {
+                    let options = LayeredOptions {
+                        layering,
+                        rank_dir,
+                        ..LayeredOptions::default()
+                    };
+                    let layout = layered(graph, &options).unwrap();
+                    println!(
+                        "--- {} ({:?}, {:?})\n{:#?}",
+                        description, layering, rank_dir, layout
+                    );
+                    check_layout(graph, &layout);
+                }
+            }
+        }
+
+        case("empty", &Graph::new());
+        case("one edge", &graph_from_paths(&[&[0, 1]]));
+        case("long edge", &graph_from_paths(&[&[0, 1, 2, 3], &[0, 3]]));
+        case("cycle", &graph_from_paths(&[&[0, 1, 2, 0]]));
+        case("self-loop", &graph_from_paths(&[&[0, 0, 1]]));
+        case(
+            "diamond",
+            &graph_from_paths(&[&[0, 1, 3], &[0, 2, 3], &[4, 2]]),
+        );
+    }
+
+    #[test]
+    fn rank_dir_test() {
+        init_test();
+        let graph = graph_from_paths(&[&[0, 1, 2]]);
+        let layout_with = |rank_dir: RankDir| {
+            let options = LayeredOptions {
+                rank_dir,
+                ..LayeredOptions::default()
+            };
+            layered(&graph, &options).unwrap()
+        };
+
+        let down = layout_with(RankDir::TopToBottom);
+        assert!(down.node_centers[0].y < down.node_centers[1].y);
+        assert!(down.node_centers[1].y < down.node_centers[2].y);
+        // A chain is drawn as a vertical line.
+        assert_eq!(down.node_centers[0].x, down.node_centers[2].x);
+
+        let up = layout_with(RankDir::BottomToTop);
+        assert!(up.node_centers[0].y > up.node_centers[1].y);
+        assert!(up.node_centers[1].y > up.node_centers[2].y);
+        assert_eq!(down.bounding_box, up.bounding_box);
+
+        let right = layout_with(RankDir::LeftToRight);
+        assert!(right.node_centers[0].x < right.node_centers[1].x);
+        assert!(right.node_centers[1].x < right.node_centers[2].x);
+        assert_eq!(right.node_centers[0].y, right.node_centers[2].y);
+
+        let left = layout_with(RankDir::RightToLeft);
+        assert!(left.node_centers[0].x > left.node_centers[1].x);
+        assert!(left.node_centers[1].x > left.node_centers[2].x);
+        assert_eq!(right.bounding_box, left.bounding_box);
+
+        // Horizontal layouts are not rotated; the vertices keep their sizes. Each layer is as
+        // wide as its vertices, and the layers are separated by rank_sep.
+        let options = LayeredOptions::default();
+        let size = options.default_node_size;
+        assert_eq!(right.node_sizes[0], size);
+        assert_eq!(
+            right.node_centers[1].x - right.node_centers[0].x,
+            size.x + options.rank_sep
+        );
+        assert_eq!(
+            right.bounding_box.width(),
+            3.0 * size.x + 2.0 * options.rank_sep
+        );
+        assert_eq!(right.bounding_box.height(), size.y);
+    }
+
+    #[test]
+    fn horizontal_edges_test() {
+        init_test();
+        let graph = graph_from_paths(&[&[0, 1], &[0, 2]]);
+        let options = LayeredOptions {
+            rank_dir: RankDir::RightToLeft,
+            node_sizes: vec![Vec2 { x: 10.0, y: 60.0 }],
+            ..LayeredOptions::default()
+        };
+        let layout = layered(&graph, &options).unwrap();
+        check_layout(&graph, &layout);
+
+        // Edges leave v0 through its left side.
+        let r0 = layout.node_rect(0);
+        for e in 0..2 {
+            let points = layout.edge_points.entry_values(e);
+            assert!((points[0].x - r0.min.x).abs() < EPSILON);
+            assert!(points[points.len() - 1].x < r0.min.x);
+        }
+    }
+
+    #[test]
+    fn node_sizes_test() {
+        init_test();
+        let graph = graph_from_paths(&[&[0, 1], &[0, 2]]);
+        let options = LayeredOptions {
+            node_sizes: vec![Vec2 { x: 10.0, y: 20.0 }, Vec2 { x: 30.0, y: 40.0 }],
+            default_node_size: Vec2 { x: 50.0, y: 10.0 },
+            rank_sep: 5.0,
+            node_sep: 7.0,
+            ..LayeredOptions::default()
+        };
+        let layout = layered(&graph, &options).unwrap();
+        check_layout(&graph, &layout);
+        assert_eq!(layout.node_sizes[2], Vec2 { x: 50.0, y: 10.0 });
+
+        // Layer 1 holds v0 (height 20), layer 0 holds v1 and v2 (height 40).
+        assert_eq!(layout.node_centers[0].y, 10.0);
+        assert_eq!(layout.node_centers[1].y, 20.0 + 5.0 + 20.0);
+        assert_eq!(layout.node_centers[2].y, 20.0 + 5.0 + 20.0);
+        assert_eq!(layout.bounding_box.height(), 20.0 + 5.0 + 40.0);
+        assert_eq!(layout.bounding_box.width(), 30.0 + 7.0 + 50.0);
+    }
+
+    #[test]
+    fn reversed_edges_keep_direction_test() {
+        init_test();
+        let graph = graph_from_paths(&[&[0, 1, 2, 0]]);
+        let layout = layered(&graph, &LayeredOptions::default()).unwrap();
+        for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+            let points = layout.edge_points.entry_values(e);
+            let from_rect = layout.node_rect(from as usize);
+            let to_rect = layout.node_rect(to as usize);
+            assert!(from_rect.contains(points[0]), "edge {}", e);
+            assert!(to_rect.contains(points[points.len() - 1]), "edge {}", e);
+        }
+    }
+
+    fn overlaps(a: &Rect, b: &Rect) -> bool {
+        a.min.x < b.max.x - EPSILON
+            && b.min.x < a.max.x - EPSILON
+            && a.min.y < b.max.y - EPSILON
+            && b.min.y < a.max.y - EPSILON
+    }
+
+    fn inside(inner: &Rect, outer: &Rect, margin: f64) -> bool {
+        inner.min.x >= outer.min.x + margin - EPSILON
+            && inner.min.y >= outer.min.y + margin - EPSILON
+            && inner.max.x <= outer.max.x - margin + EPSILON
+            && inner.max.y <= outer.max.y - margin + EPSILON
+    }
+
+    /// Checks that each cluster's box contains its vertices and child clusters, with padding,
+    /// and does not overlap anything else.
+    fn check_clusters(graph: &Graph, options: &LayeredOptions, layout: &Layout) {
+        let clusters = &options.clusters;
+        let padding = options.cluster_padding;
+        let is_within = |mut c: Option<usize>, ancestor: usize| {
+            while let Some(cc) = c {
+                if cc == ancestor {
+                    return true;
+                }
+                c = clusters[cc].parent;
+            }
+            false
+        };
+        let mut v_cluster: Vec<Option<usize>> = vec![None; graph.num_verts()];
+        for (c, cluster) in clusters.iter().enumerate() {
+            for &v in cluster.verts.iter() {
+                v_cluster[v as usize] = Some(c);
+            }
+        }
+
+        // Clusters that have no vertices are not drawn.
+        let is_empty = |c: usize| !(0..graph.num_verts()).any(|v| is_within(v_cluster[v], c));
+
+        for (c, cluster) in clusters.iter().enumerate() {
+            if is_empty(c) {
+                assert_eq!(layout.cluster_boxes[c], Rect::default());
+                continue;
+            }
+            let r = &layout.cluster_boxes[c];
+            assert!(inside(r, &layout.bounding_box, 0.0));
+            for (v, &in_cluster) in v_cluster.iter().enumerate() {
+                let node = layout.node_rect(v);
+                if is_within(in_cluster, c) {
+                    assert!(
+                        inside(&node, r, padding),
+                        "v{} is not inside cluster {}",
+                        v,
+                        c
+                    );
+                } else {
+                    assert!(!overlaps(&node, r), "v{} overlaps cluster {}", v, c);
+                }
+            }
+            for (d, other) in layout.cluster_boxes.iter().enumerate() {
+                if d == c || is_empty(d) {
+                    continue;
+                }
+                if is_within(Some(d), c) {
+                    assert!(inside(other, r, padding), "{} is not inside {}", d, c);
+                } else if !is_within(Some(c), d) {
+                    assert!(!overlaps(other, r), "clusters {} and {} overlap", c, d);
+                }
+            }
+
+            // The label fits inside the box, along the top edge.
+            let label = options.rank_dir.swap_size(cluster.label_size);
+            let extent = options.rank_dir.swap_size(Vec2 {
+                x: r.width(),
+                y: r.height(),
+            });
+            assert!(extent.x >= label.x + 2.0 * padding - EPSILON);
+            assert!(extent.y >= label.y + 2.0 * padding - EPSILON);
+        }
+    }
+
+    #[test]
+    fn clusters_test() {
+        init_test();
+
+        let graph = graph_from_paths(&[
+            &[0, 1, 2],
+            &[0, 3, 2],
+            &[4, 1],
+            &[4, 5],
+            &[0, 5],
+            &[6, 7, 8],
+            &[0, 8],
+        ]);
+        let clusters = vec![
+            Cluster {
+                parent: None,
+                verts: vec![1, 2],
+                label_size: Vec2 { x: 200.0, y: 12.0 },
+            },
+            Cluster {
+                parent: Some(0),
+                verts: vec![3],
+                label_size: Vec2 { x: 20.0, y: 10.0 },
+            },
+            Cluster {
+                parent: None,
+                verts: vec![4, 5],
+                label_size: Vec2::default(),
+            },
+            Cluster {
+                parent: Some(2),
+                verts: vec![],
+                label_size: Vec2::default(),
+            },
+            Cluster {
+                parent: None,
+                verts: vec![6, 7, 8],
+                label_size: Vec2::default(),
+            },
+        ];
+
+        for &layering in [LayeringMethod::LongestPath, LayeringMethod::NetworkSimplex].iter() {
+            for &rank_dir in ALL_RANK_DIRS.iter() {
+                let options = LayeredOptions {
+                    layering,
+                    rank_dir,
+                    clusters: clusters.clone(),
+                    ..LayeredOptions::default()
+                };
+                let layout = layered(&graph, &options).unwrap();
+                println!(
+                    "--- {:?}, {:?}\n{:#?}",
+                    layering, rank_dir, layout.cluster_boxes
+                );
+                check_layout(&graph, &layout);
+                check_clusters(&graph, &options, &layout);
+            }
+        }
+    }
+
+    #[test]
+    fn self_loops_test() {
+        init_test();
+
+        // v0 has two loops and v1 has one. v2 is next to v1 on the right.
+        let graph = graph_from_paths(&[&[0, 0, 0, 1, 1], &[0, 2]]);
+        for &rank_dir in ALL_RANK_DIRS.iter() {
+            let options = LayeredOptions {
+                rank_dir,
+                ..LayeredOptions::default()
+            };
+            let layout = layered(&graph, &options).unwrap();
+            println!("--- {:?}\n{:#?}", rank_dir, layout);
+            check_layout(&graph, &layout);
+
+            let mut loop_rects: Vec<(V, Rect)> = Vec::new();
+            for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+                if from != to {
+                    continue;
+                }
+                let points = layout.edge_points.entry_values(e);
+                assert_eq!(points.len(), 4);
+                let r = points[1..].iter().fold(
+                    Rect {
+                        min: points[0],
+                        max: points[0],
+                    },
+                    |r, &p| r.union_point(p),
+                );
+                assert!(inside(&r, &layout.bounding_box, 0.0));
+                for v in 0..graph.num_verts() {
+                    if v != from as usize {
+                        assert!(
+                            !overlaps(&r, &layout.node_rect(v)),
+                            "loop {} overlaps v{}",
+                            e,
+                            v
+                        );
+                    }
+                }
+                loop_rects.push((from, r));
+            }
+
+            // The loops of a vertex are nested.
+            assert_eq!(loop_rects.len(), 3);
+            let (a, b) = (&loop_rects[0].1, &loop_rects[1].1);
+            assert!(inside(a, b, 0.0) || inside(b, a, 0.0));
+            assert_ne!(a, b);
+        }
+    }
+
+    #[test]
+    fn flat_edges_test() {
+        init_test();
+
+        // 1, 2 and 3 are on the same layer. There are two edges from 1 to 2, so at most one of
+        // them can be a straight line.
+        let graph = graph_from_paths(&[
+            &[0, 1],
+            &[0, 2],
+            &[0, 3],
+            &[1, 2],
+            &[1, 2],
+            &[1, 3],
+            &[3, 4],
+        ]);
+        let flat = [(1, 2), (1, 3)];
+        for &rank_dir in ALL_RANK_DIRS.iter() {
+            let options = LayeredOptions {
+                rank_dir,
+                rank_constraints: vec![RankConstraint::Same(vec![1, 2, 3])],
+                ..LayeredOptions::default()
+            };
+            let layout = layered(&graph, &options).unwrap();
+            println!("--- {:?}\n{:#?}", rank_dir, layout);
+            check_layout(&graph, &layout);
+            for p in layout.edge_points.all_values().iter() {
+                assert!(layout.bounding_box.contains(*p));
+            }
+        }
+
+        let layout = layered(
+            &graph,
+            &LayeredOptions {
+                rank_constraints: vec![RankConstraint::Same(vec![1, 2, 3])],
+                ..LayeredOptions::default()
+            },
+        )
+        .unwrap();
+        let top = (1..4)
+            .map(|v| layout.node_rect(v).min.y)
+            .fold(f64::INFINITY, f64::min);
+        let mut num_arcs = 0;
+        for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+            if !flat.contains(&(from, to)) {
+                continue;
+            }
+            let (a, b) = (
+                layout.node_centers[from as usize],
+                layout.node_centers[to as usize],
+            );
+            assert_eq!(a.y, b.y);
+            assert!(a.x < b.x, "flat edge {} points left", e);
+            let points = layout.edge_points.entry_values(e);
+            if points.len() == 4 {
+                // The arc goes above the layer.
+                num_arcs += 1;
+                assert!(points[1].y < top && points[2].y < top);
+            } else {
+                assert_eq!(points.len(), 2);
+            }
+        }
+        assert!(num_arcs >= 2);
+
+        // 0 and 2 cannot be on the same layer, since there is a path between them.
+        let result = layered(
+            &graph_from_paths(&[&[0, 1, 2]]),
+            &LayeredOptions {
+                rank_constraints: vec![RankConstraint::Same(vec![0, 2])],
+                ..LayeredOptions::default()
+            },
+        );
+        assert!(matches!(result, Err(Error::ConflictingRankConstraints(_))));
+    }
+
+    #[test]
+    fn edge_labels_test() {
+        init_test();
+
+        // 0 -> 1 is labeled, and so is the flat edge 1 -> 2 and the self-loops of 3.
+        let graph = graph_from_paths(&[&[0, 1, 2], &[0, 2], &[0, 3, 3, 3], &[1, 4], &[0, 4]]);
+        let label = Vec2 { x: 40.0, y: 14.0 };
+        let mut edge_label_sizes = vec![Vec2::default(); graph.num_edges()];
+        let mut labeled: Vec<usize> = Vec::new();
+        for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+            if (from, to) == (0, 1) || (from, to) == (1, 2) || from == 3 {
+                edge_label_sizes[e] = label;
+                labeled.push(e);
+            }
+        }
+        assert_eq!(labeled.len(), 4);
+
+        for &rank_dir in ALL_RANK_DIRS.iter() {
+            let options = LayeredOptions {
+                rank_dir,
+                rank_constraints: vec![RankConstraint::Same(vec![1, 2])],
+                edge_label_sizes: edge_label_sizes.clone(),
+                ..LayeredOptions::default()
+            };
+            let layout = layered(&graph, &options).unwrap();
+            println!("--- {:?}\n{:#?}", rank_dir, layout);
+            check_layout(&graph, &layout);
+
+            let rects: Vec<Rect> = labeled
+                .iter()
+                .map(|&e| layout.edge_label_rect(e, label).unwrap())
+                .collect();
+            for (i, r) in rects.iter().enumerate() {
+                assert!(inside(r, &layout.bounding_box, 0.0));
+                for v in 0..graph.num_verts() {
+                    assert!(
+                        !overlaps(r, &layout.node_rect(v)),
+                        "label {} overlaps v{}",
+                        i,
+                        v
+                    );
+                }
+                for other in rects[i + 1..].iter() {
+                    assert!(!overlaps(r, other), "labels overlap");
+                }
+            }
+            for e in 0..graph.num_edges() {
+                assert_eq!(
+                    layout.edge_label_positions[e].is_some(),
+                    labeled.contains(&e)
+                );
+            }
+        }
+
+        // With a label, the layers are doubled but the distance between them is halved, so
+        // only the height of the label (on 0 -> 1) is added.
+        let unlabeled = layered(&graph, &LayeredOptions::default()).unwrap();
+        let options = LayeredOptions {
+            edge_label_sizes: vec![Vec2 { x: 10.0, y: 1.0 }],
+            ..LayeredOptions::default()
+        };
+        let labeled = layered(&graph, &options).unwrap();
+        let height = |layout: &Layout| layout.node_centers[4].y - layout.node_centers[0].y;
+        assert_eq!(height(&labeled), height(&unlabeled) + 1.0);
+    }
+
+    #[test]
+    fn edge_routing_test() {
+        init_test();
+
+        // A long edge that must pass between 2 and 3, a reversed edge, a labeled edge, a flat
+        // edge and a self-loop.
+        let graph = graph_from_paths(&[
+            &[0, 1, 2, 4],
+            &[0, 3, 4],
+            &[0, 5, 6, 4],
+            &[4, 0],
+            &[7, 7],
+            &[1, 3],
+        ]);
+        let mut edge_label_sizes = vec![Vec2::default(); graph.num_edges()];
+        edge_label_sizes[1] = Vec2 { x: 30.0, y: 12.0 };
+        for &edge_routing in [
+            EdgeRouting::Line,
+            EdgeRouting::Polyline,
+            EdgeRouting::Ortho,
+            EdgeRouting::Spline,
+            EdgeRouting::Curved,
+        ]
+        .iter()
+        {
+            for &rank_dir in ALL_RANK_DIRS.iter() {
+                let options = LayeredOptions {
+                    rank_dir,
+                    edge_routing,
+                    edge_label_sizes: edge_label_sizes.clone(),
+                    rank_constraints: vec![RankConstraint::Same(vec![1, 3])],
+                    ..LayeredOptions::default()
+                };
+                let layout = layered(&graph, &options).unwrap();
+                println!("--- {:?} ({:?})\n{:#?}", edge_routing, rank_dir, layout);
+                check_layout(&graph, &layout);
+                assert!(layout
+                    .edge_splines
+                    .all_values()
+                    .iter()
+                    .all(|&p| layout.bounding_box.contains(p)));
+
+                let label = layout.edge_label_rect(1, edge_label_sizes[1]).unwrap();
+                for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+                    let curve = layout.edge_splines.entry_values(e);
+                    let segments = curve.chunks(3).skip(1).len();
+                    match edge_routing {
+                        EdgeRouting::Line | EdgeRouting::Curved
+                            if from != to && (from, to) != (1, 3) =>
+                        {
+                            assert_eq!(segments, 1, "{:?}: edge {}", edge_routing, e);
+                        }
+                        EdgeRouting::Ortho => {
+                            for seg in curve.windows(4).step_by(3) {
+                                assert!(seg[0].x == seg[3].x || seg[0].y == seg[3].y);
+                            }
+                        }
+                        _ => {}
+                    }
+                    if let EdgeRouting::Line | EdgeRouting::Curved = edge_routing {
+                        continue;
+                    }
+
+                    // The curve does not pass through other vertices, or through the label.
+                    let samples = splines::tests::sample_curve(curve, 16);
+                    let shrink = |r: Rect| Rect {
+                        min: r.min + Vec2 { x: 0.5, y: 0.5 },
+                        max: r.max - Vec2 { x: 0.5, y: 0.5 },
+                    };
+                    for v in
+                        (0..graph.num_verts()).filter(|&v| v != from as usize && v != to as usize)
+                    {
+                        let r = shrink(layout.node_rect(v));
+                        assert!(
+                            !samples.iter().any(|&p| r.contains(p)),
+                            "{:?} ({:?}): edge {} passes through v{}",
+                            edge_routing,
+                            rank_dir,
+                            e,
+                            v
+                        );
+                    }
+                    assert!(!samples.iter().any(|&p| shrink(label).contains(p)));
+                }
+            }
+        }
+
+        // By default, the curves are the polylines.
+        let layout = layered(&graph, &LayeredOptions::default()).unwrap();
+        for e in 0..graph.num_edges() {
+            let points = layout.edge_points.entry_values(e);
+            assert!(
+                layout.edge_splines.entry_values(e) == &splines::polyline_to_bezier(points)[..]
+            );
+        }
+    }
+
+    #[test]
+    fn compound_edges_test() {
+        init_test();
+
+        // A = {0, 1} and B = {2, 3}. The edge 1 -> 2 connects A to B, 0 -> 1 names A as its
+        // head but is inside A, and 3 -> 4 names B as its head but does not end in B.
+        let graph = graph_from_paths(&[&[0, 1, 2, 3, 4], &[0, 3]]);
+        let clusters = vec![
+            Cluster {
+                parent: None,
+                verts: vec![0, 1],
+                label_size: Vec2::default(),
+            },
+            Cluster {
+                parent: None,
+                verts: vec![2, 3],
+                label_size: Vec2::default(),
+            },
+        ];
+        let edge_index = |from: V, to: V| {
+            graph
+                .iter_edges_flattened()
+                .position(|e| e == (from, to))
+                .unwrap()
+        };
+        let mut edge_clusters = vec![EdgeClusters::default(); graph.num_edges()];
+        edge_clusters[edge_index(1, 2)] = EdgeClusters {
+            tail: Some(0),
+            head: Some(1),
+        };
+        edge_clusters[edge_index(0, 1)].head = Some(0);
+        edge_clusters[edge_index(3, 4)].head = Some(1);
+        let margin = Vec2 {
+            x: EPSILON / 2.0,
+            y: EPSILON / 2.0,
+        };
+
+        for &edge_routing in [
+            EdgeRouting::Line,
+            EdgeRouting::Polyline,
+            EdgeRouting::Ortho,
+            EdgeRouting::Spline,
+            EdgeRouting::Curved,
+        ]
+        .iter()
+        {
+            for &rank_dir in ALL_RANK_DIRS.iter() {
+                let options = LayeredOptions {
+                    rank_dir,
+                    edge_routing,
+                    clusters: clusters.clone(),
+                    edge_clusters: edge_clusters.clone(),
+                    ..LayeredOptions::default()
+                };
+                let layout = layered(&graph, &options).unwrap();
+                println!("--- {:?} ({:?})\n{:#?}", edge_routing, rank_dir, layout);
+                check_clusters(&graph, &options, &layout);
+
+                for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+                    // The clipped ends are only on the cluster boxes up to rounding.
+                    let grow = |r: Rect| Rect {
+                        min: r.min - margin,
+                        max: r.max + margin,
+                    };
+                    let (from_rect, to_rect) = if (from, to) == (1, 2) {
+                        (grow(layout.cluster_boxes[0]), grow(layout.cluster_boxes[1]))
+                    } else {
+                        (
+                            layout.node_rect(from as usize),
+                            layout.node_rect(to as usize),
+                        )
+                    };
+                    let points = layout.edge_points.entry_values(e);
+                    let curve = layout.edge_splines.entry_values(e);
+                    assert!(curve.len() >= 4 && curve.len() % 3 == 1);
+                    for ends in [points, curve].iter() {
+                        assert!(
+                            on_boundary(&from_rect, ends[0])
+                                && on_boundary(&to_rect, ends[ends.len() - 1]),
+                            "{:?} ({:?}): edge {} -> {} has ends {:?}",
+                            edge_routing,
+                            rank_dir,
+                            from,
+                            to,
+                            ends
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn concentrate_test() {
+        init_test();
+
+        // 0 fans out to 1, 2 and 3, which are four layers below it. The edge 0 -> 3 has a
+        // label, so it is not merged.
+        let graph = graph_from_paths(&[
+            &[0, 4, 6, 7, 1],
+            &[0, 1],
+            &[0, 2],
+            &[0, 3],
+            &[7, 2],
+            &[7, 3],
+        ]);
+        let edge_index = |from: V, to: V| {
+            graph
+                .iter_edges_flattened()
+                .position(|e| e == (from, to))
+                .unwrap()
+        };
+        let mut edge_label_sizes = vec![Vec2::default(); graph.num_edges()];
+        edge_label_sizes[edge_index(0, 3)] = Vec2 { x: 20.0, y: 10.0 };
+
+        for &concentrate in [false, true].iter() {
+            for &rank_dir in ALL_RANK_DIRS.iter() {
+                let options = LayeredOptions {
+                    rank_dir,
+                    concentrate,
+                    edge_routing: EdgeRouting::Spline,
+                    edge_label_sizes: edge_label_sizes.clone(),
+                    ..LayeredOptions::default()
+                };
+                let layout = layered(&graph, &options).unwrap();
+                println!("--- {} ({:?})\n{:#?}", concentrate, rank_dir, layout);
+                check_layout(&graph, &layout);
+
+                // With the label, the layers are doubled, so every edge has a virtual vertex in
+                // each of the seven layers between 0 and its other end. Merged edges share
+                // those points.
+                let points = |from: V, to: V| layout.edge_points.entry_values(edge_index(from, to));
+                assert_eq!(points(0, 1).len(), 9);
+                assert_eq!(points(0, 1)[1..8] == points(0, 2)[1..8], concentrate);
+                assert!(points(0, 1)[1..8] != points(0, 3)[1..8]);
+            }
+        }
+    }
+
+    #[test]
+    fn node_groups_test() {
+        init_test();
+
+        // The main path 0 -> 1 -> 2 -> 3 -> 4, with edges that pull it to the side.
+        let graph = graph_from_paths(&[
+            &[0, 1, 2, 3, 4],
+            &[5, 1],
+            &[6, 1],
+            &[1, 7],
+            &[1, 8],
+            &[8, 3],
+            &[2, 9],
+            &[2, 10],
+            &[10, 4],
+            &[11, 3],
+        ]);
+        let mut node_groups = vec![None; graph.num_verts()];
+        node_groups[..5].fill(Some(0));
+        for &grouped in [false, true].iter() {
+            for &rank_dir in ALL_RANK_DIRS.iter() {
+                let options = LayeredOptions {
+                    rank_dir,
+                    node_groups: if grouped {
+                        node_groups.clone()
+                    } else {
+                        Vec::new()
+                    },
+                    ..LayeredOptions::default()
+                };
+                let layout = layered(&graph, &options).unwrap();
+                check_layout(&graph, &layout);
+                let across = |v: usize| {
+                    let c = layout.node_centers[v];
+                    match rank_dir {
+                        RankDir::TopToBottom | RankDir::BottomToTop => c.x,
+                        RankDir::LeftToRight | RankDir::RightToLeft => c.y,
+                    }
+                };
+                let path: Vec<f64> = (0..5).map(across).collect();
+                println!("--- {} ({:?}): {:?}", grouped, rank_dir, path);
+                let straight = path.iter().all(|&c| (c - path[0]).abs() < EPSILON);
+                assert_eq!(straight, grouped);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_test() {
+        init_test();
+
+        let before = graph_from_paths(&[&[0, 1, 2], &[0, 3, 2], &[4, 2], &[5, 6]]);
+        // The edge 4 -> 1 would move 4 up a layer, 2 -> 7 and 1 -> 7 add a vertex, 3 -> 0
+        // points up, and 8 -> 9 is a new component. The edge 0 -> 3 is gone.
+        let after = graph_from_paths(&[
+            &[0, 1, 2, 7],
+            &[3, 2],
+            &[4, 2],
+            &[5, 6],
+            &[4, 1, 7],
+            &[3, 0],
+            &[8, 9],
+        ]);
+        for &rank_dir in ALL_RANK_DIRS.iter() {
+            let options = LayeredOptions {
+                rank_dir,
+                ..LayeredOptions::default()
+            };
+            let previous = layered(&before, &options).unwrap();
+            let fresh = layered(&after, &options).unwrap();
+            let layout = layered_incremental(&after, &options, &previous).unwrap();
+            println!("--- {:?}\n{:#?}", rank_dir, layout);
+            check_layout(&after, &layout);
+
+            // The old vertices keep their layers, up to a shift, which a fresh layout does not.
+            let shift =
+                |layout: &Layout| layout.node_layers[0] as i64 - previous.node_layers[0] as i64;
+            let kept = |layout: &Layout| {
+                (0..before.num_verts()).all(|v| {
+                    layout.node_layers[v] as i64 - previous.node_layers[v] as i64 == shift(layout)
+                })
+            };
+            assert!(kept(&layout));
+            assert!(!kept(&fresh));
+            assert_eq!(layout.node_layers[7] + 1, layout.node_layers[2]);
+            assert_eq!(layout.node_layers[8], layout.node_layers[9] + 1);
+
+            // They keep their positions across the layers, up to a translation. (The layers
+            // can move apart, since the flat edge 4 -> 1 needs room above its layer.) Edges
+            // whose ends keep their positions relative to each other keep their routes.
+            let across =
+                |layout: &Layout, v: usize| rank_dir.inverse_vector(layout.node_centers[v]).x;
+            let shift = across(&layout, 0) - across(&previous, 0);
+            for v in 0..before.num_verts() {
+                let moved = across(&layout, v) - across(&previous, v) - shift;
+                assert!(moved.abs() < 1e-6, "v{} moved by {}", v, moved);
+            }
+            for &(from, to) in [(1, 2), (4, 2), (5, 6)].iter() {
+                let find = |layout: &Layout| {
+                    let mut ends = layout.edge_endpoints.iter();
+                    ends.position(|&ends| ends == (from, to)).unwrap()
+                };
+                let moved =
+                    layout.node_centers[from as usize] - previous.node_centers[from as usize];
+                let old_points = previous.edge_points.entry_values(find(&previous));
+                let points = layout.edge_points.entry_values(find(&layout));
+                assert_eq!(old_points.len(), points.len());
+                for (&old, &p) in old_points.iter().zip(points.iter()) {
+                    let d = p - old - moved;
+                    assert!(d.x.abs() < 1e-6 && d.y.abs() < 1e-6, "{} -> {}", from, to);
+                }
+            }
+
+            // And they keep their order within each layer.
+            for v in 0..before.num_verts() {
+                for w in 0..before.num_verts() {
+                    if previous.node_layers[v] == previous.node_layers[w]
+                        && across(&previous, v) < across(&previous, w)
+                    {
+                        assert!(across(&layout, v) < across(&layout, w), "v{} and v{}", v, w);
+                    }
+                }
+            }
+        }
+
+        // Coordinate assignment by itself would move 1 to make room for the new vertex 3, and
+        // the routes of the edges between the old vertices would change. Instead, the old
+        // vertices stay in place, up to a translation, and the edges keep their routes.
+        let before = graph_from_paths(&[&[0, 1, 2], &[0, 2]]);
+        let after = graph_from_paths(&[&[0, 1, 2], &[0, 2], &[1, 3], &[2, 3]]);
+        for &rank_dir in ALL_RANK_DIRS.iter() {
+            let options = LayeredOptions {
+                rank_dir,
+                ..LayeredOptions::default()
+            };
+            let previous = layered(&before, &options).unwrap();
+            let layout = layered_incremental(&after, &options, &previous).unwrap();
+            check_layout(&after, &layout);
+            let moved = |v: usize| layout.node_centers[v] - previous.node_centers[v];
+            let same =
+                |a: Vec2<f64>, b: Vec2<f64>| (a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6;
+            for v in 0..before.num_verts() {
+                assert!(same(moved(v), moved(0)), "{:?}: v{} moved", rank_dir, v);
+            }
+            for (e, ends) in previous.edge_endpoints.iter().enumerate() {
+                let new_e = layout
+                    .edge_endpoints
+                    .iter()
+                    .position(|x| x == ends)
+                    .unwrap();
+                let old = previous.edge_splines.entry_values(e);
+                let new = layout.edge_splines.entry_values(new_e);
+                assert_eq!(old.len(), new.len());
+                assert!(old
+                    .iter()
+                    .zip(new.iter())
+                    .all(|(&a, &b)| same(b - a, moved(0))));
+            }
+        }
+
+        // Relaying out the same graph gives the same layers.
+        let previous = layered(&after, &LayeredOptions::default()).unwrap();
+        let layout = layered_incremental(&after, &LayeredOptions::default(), &previous).unwrap();
+        assert_eq!(layout.node_layers, previous.node_layers);
+
+        // The constraint between 0 and 2 cannot be satisfied, since 1 is between them, but it
+        // is dropped because they keep their layers. The new vertex 3 is put in the layer of
+        // 2, and 4 in the layer below 3.
+        let before = graph_from_paths(&[&[0, 1, 2]]);
+        let after = graph_from_paths(&[&[0, 1, 2], &[1, 3, 4]]);
+        let previous = layered(&before, &LayeredOptions::default()).unwrap();
+        let options = LayeredOptions {
+            rank_constraints: vec![
+                RankConstraint::Same(vec![0, 2]),
+                RankConstraint::Same(vec![2, 3]),
+            ],
+            ..LayeredOptions::default()
+        };
+        assert!(layered(&after, &options).is_err());
+        let layout = layered_incremental(&after, &options, &previous).unwrap();
+        check_layout(&after, &layout);
+        assert_eq!(layout.node_layers, vec![3, 2, 1, 1, 0]);
+
+        // A constraint between new vertices still has to be satisfied.
+        let after = graph_from_paths(&[&[0, 1, 2], &[1, 3, 4, 5]]);
+        let options = LayeredOptions {
+            rank_constraints: vec![RankConstraint::Same(vec![3, 5])],
+            ..LayeredOptions::default()
+        };
+        let result = layered_incremental(&after, &options, &previous);
+        assert!(matches!(result, Err(Error::ConflictingRankConstraints(_))));
+    }
+
+    #[test]
+    fn edge_ordering_test() {
+        init_test();
+
+        fn case(
+            description: &str,
+            graph: &Graph,
+            v: usize,
+            ordering: EdgeOrdering,
+            expected: &[usize],
+        ) {
+            for &rank_dir in ALL_RANK_DIRS.iter() {
+                let mut edge_ordering = vec![None; graph.num_verts()];
+                edge_ordering[v] = Some(ordering);
+                let options = LayeredOptions {
+                    rank_dir,
+                    edge_ordering,
+                    ..LayeredOptions::default()
+                };
+                let layout = layered(graph, &options).unwrap();
+                println!(
+                    "--- {} ({:?})\n{:#?}",
+                    description, rank_dir, layout.node_centers
+                );
+                check_layout(graph, &layout);
+                let across = |v: usize| {
+                    let p = layout.node_centers[v];
+                    if rank_dir.is_horizontal() {
+                        p.y
+                    } else {
+                        p.x
+                    }
+                };
+                for w in expected.windows(2) {
+                    assert!(
+                        across(w[0]) < across(w[1]),
+                        "{}: {:?}",
+                        description,
+                        rank_dir
+                    );
+                }
+            }
+        }
+
+        // Crossing minimization alone would put 1 on the left.
+        let out = graph_from_paths(&[&[0, 3], &[0, 2], &[0, 1], &[4, 1], &[5, 2], &[6, 3]]);
+        case("out", &out, 0, EdgeOrdering::Out, &[3, 2, 1]);
+
+        // The edges into 0 are numbered 1 -> 0, then 2 -> 0, then 3 -> 0. Crossing
+        // minimization alone would put 3 on the left.
+        let into = graph_from_paths(&[&[1, 0], &[2, 0], &[3, 0], &[4, 3], &[5, 2], &[6, 1]]);
+        case("in", &into, 0, EdgeOrdering::In, &[1, 2, 3]);
+
+        // The first three edges of 0 are long, so the constraints apply to their virtual
+        // vertices, which are the second points of the edges.
+        let long = graph_from_paths(&[
+            &[0, 3],
+            &[0, 2],
+            &[0, 1],
+            &[4, 1],
+            &[5, 2],
+            &[6, 7, 3],
+            &[0, 7],
+        ]);
+        let layout = layered(
+            &long,
+            &LayeredOptions {
+                edge_ordering: vec![Some(EdgeOrdering::Out)],
+                ..LayeredOptions::default()
+            },
+        )
+        .unwrap();
+        check_layout(&long, &layout);
+        let x = |e: usize| layout.edge_points.entry_values(e)[1].x;
+        assert!(x(0) < x(1) && x(1) < x(2));
+    }
+
+    #[test]
+    fn order_constraints_test() {
+        init_test();
+
+        // Layering puts 0 above 1 and 2, so the constraint between 0 and 1 is ignored, and the
+        // one between 2 and 1 is kept.
+        let graph = graph_from_paths(&[&[0, 1], &[0, 2]]);
+        let mut options = LayeredOptions::default();
+        options.ordering.constraints =
+            vec![OrderConstraint::LeftOf(0, 1), OrderConstraint::LeftOf(2, 1)];
+        let layout = layered(&graph, &options).unwrap();
+        println!("{:#?}", layout.node_centers);
+        check_layout(&graph, &layout);
+        assert!(layout.node_centers[2].x < layout.node_centers[1].x);
+
+        // `layered_incremental` chooses the layer of the new vertex 3, which is not that of 2.
+        let grown = graph_from_paths(&[&[0, 1], &[0, 2], &[3, 2]]);
+        options.ordering.constraints = vec![OrderConstraint::LeftOf(2, 3)];
+        let layout = layered_incremental(&grown, &options, &layout).unwrap();
+        check_layout(&grown, &layout);
+    }
+
+    #[test]
+    fn ports_test() {
+        init_test();
+
+        // Vertex 0 is a record with three fields, and its edges leave from the fields on the
+        // side that faces the next layer. The fields are in the opposite order from the edge
+        // numbers, so ordering should reverse the order of 1, 2 and 3.
+        let graph = graph_from_paths(&[&[0, 1], &[0, 2], &[0, 3], &[4, 1], &[4, 3]]);
+        for &rank_dir in ALL_RANK_DIRS.iter() {
+            let (compass, field) = match rank_dir {
+                RankDir::TopToBottom => (Compass::S, Vec2 { x: 30.0, y: 0.0 }),
+                RankDir::BottomToTop => (Compass::N, Vec2 { x: 30.0, y: 0.0 }),
+                RankDir::LeftToRight => (Compass::E, Vec2 { x: 0.0, y: 30.0 }),
+                RankDir::RightToLeft => (Compass::W, Vec2 { x: 0.0, y: 30.0 }),
+            };
+            let port = |i: f64| Port {
+                offset: field * i,
+                compass: Some(compass),
+            };
+            let edge_ports = vec![
+                EdgePorts {
+                    tail: port(1.0),
+                    ..EdgePorts::default()
+                },
+                EdgePorts {
+                    tail: port(0.0),
+                    ..EdgePorts::default()
+                },
+                EdgePorts {
+                    tail: port(-1.0),
+                    ..EdgePorts::default()
+                },
+            ];
+            let options = LayeredOptions {
+                rank_dir,
+                node_sizes: vec![Vec2 { x: 100.0, y: 100.0 }],
+                edge_ports: edge_ports.clone(),
+                ..LayeredOptions::default()
+            };
+            let layout = layered(&graph, &options).unwrap();
+            println!("--- ports ({:?})\n{:#?}", rank_dir, layout);
+            check_layout(&graph, &layout);
+
+            let across = |p: Vec2<f64>| if rank_dir.is_horizontal() { p.y } else { p.x };
+            let center = layout.node_centers[0];
+            let dir = compass.direction();
+            for (e, ports) in edge_ports.iter().enumerate() {
+                // The edge starts on the side, at the field, and then leaves in the direction
+                // of the side.
+                let points = layout.edge_points.entry_values(e);
+                let expected = Vec2 {
+                    x: center.x + ports.tail.offset.x + dir.x * 50.0,
+                    y: center.y + ports.tail.offset.y + dir.y * 50.0,
+                };
+                assert!((points[0].x - expected.x).abs() < EPSILON, "{:?}", rank_dir);
+                assert!((points[0].y - expected.y).abs() < EPSILON, "{:?}", rank_dir);
+                let d = points[1] - points[0];
+                assert!(d.x * dir.x >= 0.0 && d.y * dir.y >= 0.0 && (d.x != 0.0 || d.y != 0.0));
+            }
+            let x = |v: usize| across(layout.node_centers[v]);
+            assert!(x(3) < x(2) && x(2) < x(1), "{:?}", rank_dir);
+        }
+
+        // Ports without a compass point are aimed at, and ports of reversed edges stay with
+        // their vertices.
+        let cycle = graph_from_paths(&[&[0, 1], &[1, 0]]);
+        let options = LayeredOptions {
+            edge_ports: vec![
+                EdgePorts::default(),
+                EdgePorts {
+                    tail: Port {
+                        offset: Vec2 { x: 20.0, y: 0.0 },
+                        compass: Some(Compass::E),
+                    },
+                    head: Port {
+                        offset: Vec2 { x: -20.0, y: 0.0 },
+                        compass: None,
+                    },
+                },
+            ],
+            ..LayeredOptions::default()
+        };
+        let layout = layered(&cycle, &options).unwrap();
+        println!("--- reversed\n{:#?}", layout);
+        check_layout(&cycle, &layout);
+        let points = layout.edge_points.entry_values(1);
+        let r1 = layout.node_rect(1);
+        assert!((points[0].x - r1.max.x).abs() < EPSILON);
+        assert!(points[1].x > points[0].x);
+        let r0 = layout.node_rect(0);
+        let last = points[points.len() - 1];
+        assert!(last.x < (r0.min.x + r0.max.x) / 2.0);
+    }
+}
@@ -0,0 +1,446 @@
+// Curves for the edges of layered layouts, in the styles of `EdgeRouting`.
+//
+// Splines follow dot. Each edge is given a corridor: a sequence of boxes, stacked from the top
+// of the edge to the bottom, that contains no vertices other than the ends of the edge. Within a
+// layer, the box around the edge's vertex extends to the vertex's neighbors; between layers, the
+// box spans the whole drawing. The boxes are joined into a polygon, `Pshortestpath` finds the
+// shortest path through the polygon, and `Proutespline` fits a piecewise cubic Bézier curve to
+// the path, using the sides of the polygon as barriers.
+//
+// Orthogonal routes follow the polyline of the edge, but move between the x coordinates of its
+// vertices only in the space between layers.
+//
+// Curves are represented by their control points: the first point, and then three more points
+// for each segment, the last of which is the end of the segment. Polylines are converted to
+// curves whose segments are straight.
+
+use super::Rect;
+use crate::layering::ProperGraph;
+use crate::pathplan::route::Proutespline;
+use crate::pathplan::shortest::Pshortestpath;
+use crate::pathplan::Pedge_t;
+use crate::polyline::{Ppoly_t, Ppolyline_t};
+use crate::vec2::Vec2;
+use crate::V;
+use log::debug;
+
+/// The space that is available to the edges of a layered layout.
+pub(super) struct Corridors<'a> {
+    pub pg: &'a ProperGraph,
+
+    /// The left and right extents of each vertex of `pg`, including the space that is reserved
+    /// for its self-loops.
+    pub v_extent: &'a [(f64, f64)],
+
+    /// The top and bottom of each layer.
+    pub layer_extent: &'a [(f64, f64)],
+
+    /// The left and right extents of the drawing.
+    pub left: f64,
+    pub right: f64,
+}
+
+impl<'a> Corridors<'a> {
+    /// Builds the corridor of an edge whose chain of vertices (from the top layer to the bottom)
+    /// is `chain`, and whose polyline is `points`. If the edge has a label vertex, then the
+    /// corridor passes to the left of the label. The boxes at the ends of the edge extend from
+    /// its endpoints to the space between the layers.
+    pub fn boxes(&self, chain: &[V], points: &[Vec2<f64>], label_vert: Option<V>) -> Vec<Rect> {
+        let pg = self.pg;
+        let top = points.iter().map(|p| p.y).fold(f64::INFINITY, f64::min);
+        let bottom = points.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max);
+        let mut boxes: Vec<Rect> = Vec::with_capacity(chain.len() * 2);
+        for (i, &v) in chain.iter().enumerate() {
+            let layer = pg.v_layer[v as usize] as usize;
+            let layer_verts = pg.verts.entry_values(layer);
+            let pos = pg.v_pos[v as usize] as usize;
+            let left = match pos.checked_sub(1) {
+                Some(p) => self.v_extent[layer_verts[p] as usize].1,
+                None => self.left,
+            };
+            let mut right = match layer_verts.get(pos + 1) {
+                Some(&w) => self.v_extent[w as usize].0,
+                None => self.right,
+            };
+            if Some(v) == label_vert {
+                right = self.v_extent[v as usize].0;
+            }
+
+            let (mut layer_top, mut layer_bottom) = self.layer_extent[layer];
+            if i == 0 {
+                layer_top = top.min(layer_bottom);
+            }
+            if i == chain.len() - 1 {
+                layer_bottom = bottom.max(layer_top);
+            }
+            if i > 0 {
+                let above = self.layer_extent[pg.v_layer[chain[i - 1] as usize] as usize].1;
+                boxes.push(Rect {
+                    min: Vec2 {
+                        x: self.left,
+                        y: above,
+                    },
+                    max: Vec2 {
+                        x: self.right,
+                        y: layer_top,
+                    },
+                });
+            }
+            boxes.push(Rect {
+                min: Vec2 {
+                    x: left,
+                    y: layer_top,
+                },
+                max: Vec2 {
+                    x: right,
+                    y: layer_bottom,
+                },
+            });
+        }
+        boxes
+    }
+}
+
+/// Fits a piecewise cubic Bézier curve from `start` to `end` within `boxes`, which are stacked
+/// from top to bottom. `start_dir` and `end_dir` are the directions of the curve at its ends, or
+/// zero to leave them free. Returns `None` if the boxes do not form a corridor that contains
+/// the ends, in which case the caller should fall back to a polyline.
+pub(super) fn route_spline(
+    boxes: &[Rect],
+    start: Vec2<f64>,
+    end: Vec2<f64>,
+    start_dir: Vec2<f64>,
+    end_dir: Vec2<f64>,
+) -> Option<Vec<Vec2<f64>>> {
+    let polygon = corridor_polygon(boxes)?;
+    let path = match Pshortestpath(&polygon, &[start, end]) {
+        Ok(path) => path,
+        Err(e) => {
+            debug!("route_spline: no shortest path: {:?}", e);
+            return None;
+        }
+    };
+    let n = polygon.ps.len();
+    let barriers: Vec<Pedge_t> = (0..n)
+        .map(|i| Pedge_t {
+            a: polygon.ps[i],
+            b: polygon.ps[(i + 1) % n],
+        })
+        .collect();
+    let mut evs = [start_dir, end_dir];
+    let mut spline = Ppolyline_t::default();
+    if let Err(e) = Proutespline(&barriers, &path, &mut evs, &mut spline) {
+        debug!("route_spline: cannot fit spline: {:?}", e);
+        return None;
+    }
+    Some(spline.ps)
+}
+
+/// Converts a polyline to a piecewise cubic Bézier curve that has a straight segment for each
+/// of its segments.
+pub(super) fn polyline_to_bezier(points: &[Vec2<f64>]) -> Vec<Vec2<f64>> {
+    let mut curve: Vec<Vec2<f64>> = Vec::with_capacity(points.len() * 3);
+    curve.extend(points.first().copied());
+    for w in points.windows(2) {
+        let d = w[1] - w[0];
+        curve.push(w[0] + d / 3.0);
+        curve.push(w[0] + d * (2.0 / 3.0));
+        curve.push(w[1]);
+    }
+    curve
+}
+
+/// Returns a single cubic Bézier segment from `start` to `end`. The segment leaves and enters in
+/// the directions `start_dir` and `end_dir`, or if they are zero, bows to the left of the line
+/// from `start` to `end`, by a sixth of its length.
+pub(super) fn curved_arc(
+    start: Vec2<f64>,
+    end: Vec2<f64>,
+    start_dir: Vec2<f64>,
+    end_dir: Vec2<f64>,
+) -> Vec<Vec2<f64>> {
+    let d = end - start;
+    let len = (d.x * d.x + d.y * d.y).sqrt();
+    let bow = Vec2 { x: d.y, y: -d.x } / 6.0;
+    let unit = |v: Vec2<f64>| v / (v.x * v.x + v.y * v.y).sqrt();
+    let c1 = if start_dir == Vec2::default() {
+        start + d / 3.0 + bow
+    } else {
+        start + unit(start_dir) * (len / 3.0)
+    };
+    let c2 = if end_dir == Vec2::default() {
+        start + d * (2.0 / 3.0) + bow
+    } else {
+        end - unit(end_dir) * (len / 3.0)
+    };
+    vec![start, c1, c2, end]
+}
+
+/// Converts a polyline that goes down through the layers into horizontal and vertical segments.
+/// Where two adjacent points have different x and y coordinates, the route goes vertically to
+/// the middle of the part of the space between layers that is between them, across, and then
+/// vertically again. `layer_extent` has the top and bottom of each layer.
+pub(super) fn ortho_polyline(points: &[Vec2<f64>], layer_extent: &[(f64, f64)]) -> Vec<Vec2<f64>> {
+    // The space between each layer and the layer below it, as (top, bottom).
+    let gaps: Vec<(f64, f64)> = layer_extent.windows(2).map(|w| (w[1].1, w[0].0)).collect();
+    let mut route: Vec<Vec2<f64>> = Vec::with_capacity(points.len() * 3);
+    route.extend(points.first().copied());
+    for w in points.windows(2) {
+        let (p, q) = (w[0], w[1]);
+        if p.x != q.x && p.y != q.y {
+            let (lo, hi) = (p.y.min(q.y), p.y.max(q.y));
+            let y = gaps
+                .iter()
+                .map(|&(top, bottom)| (top.max(lo), bottom.min(hi)))
+                .find(|&(top, bottom)| top < bottom)
+                .map_or((lo + hi) / 2.0, |(top, bottom)| (top + bottom) / 2.0);
+            route.push(Vec2 { x: p.x, y });
+            route.push(Vec2 { x: q.x, y });
+        }
+        route.push(q);
+    }
+    route
+}
+
+/// Evaluates the cubic Bézier segment with control points `seg` at `t`, between 0 and 1.
+pub(super) fn bezier_point(seg: &[Vec2<f64>], t: f64) -> Vec2<f64> {
+    let u = 1.0 - t;
+    seg[0] * (u * u * u)
+        + seg[1] * (3.0 * u * u * t)
+        + seg[2] * (3.0 * u * t * t)
+        + seg[3] * (t * t * t)
+}
+
+/// Removes the start of a piecewise cubic Bézier curve, up to the point where it leaves `rect`.
+/// The first point must be inside `rect`. The curve is split within the first segment whose end
+/// is outside `rect`, at a point on the boundary that is found by bisection. Returns `None` if
+/// no segment ends outside `rect`.
+pub(super) fn clip_curve_start(curve: &[Vec2<f64>], rect: &Rect) -> Option<Vec<Vec2<f64>>> {
+    let i = (0..curve.len() / 3).find(|&i| !rect.contains(curve[i * 3 + 3]))?;
+    let seg = &curve[i * 3..i * 3 + 4];
+    let (mut inside, mut outside) = (0.0, 1.0);
+    for _ in 0..64 {
+        let t = (inside + outside) / 2.0;
+        if rect.contains(bezier_point(seg, t)) {
+            inside = t;
+        } else {
+            outside = t;
+        }
+    }
+
+    // de Casteljau's algorithm gives the control points of the part of the segment after t.
+    let t = inside;
+    let lerp = |a: Vec2<f64>, b: Vec2<f64>| a + (b - a) * t;
+    let (ab, bc, cd) = (
+        lerp(seg[0], seg[1]),
+        lerp(seg[1], seg[2]),
+        lerp(seg[2], seg[3]),
+    );
+    let (abc, bcd) = (lerp(ab, bc), lerp(bc, cd));
+    let mut clipped: Vec<Vec2<f64>> = Vec::with_capacity(curve.len() - i * 3);
+    clipped.push(lerp(abc, bcd));
+    clipped.push(bcd);
+    clipped.push(cd);
+    clipped.extend_from_slice(&curve[i * 3 + 3..]);
+    Some(clipped)
+}
+
+/// Joins a stack of boxes into a polygon, going down their right sides and then up their left
+/// sides. Empty boxes are skipped. Returns `None` if adjacent boxes do not touch.
+fn corridor_polygon(boxes: &[Rect]) -> Option<Ppoly_t> {
+    let boxes: Vec<&Rect> = boxes
+        .iter()
+        .filter(|b| b.width() > 0.0 && b.height() > 0.0)
+        .collect();
+    if boxes.is_empty() {
+        return None;
+    }
+    for w in boxes.windows(2) {
+        if w[0].max.y != w[1].min.y || w[0].max.x <= w[1].min.x || w[1].max.x <= w[0].min.x {
+            debug!("corridor_polygon: boxes do not touch: {:?}", w);
+            return None;
+        }
+    }
+
+    let mut ps: Vec<Vec2<f64>> = Vec::with_capacity(boxes.len() * 4);
+    for b in boxes.iter() {
+        ps.push(Vec2 {
+            x: b.max.x,
+            y: b.min.y,
+        });
+        ps.push(b.max);
+    }
+    for b in boxes.iter().rev() {
+        ps.push(Vec2 {
+            x: b.min.x,
+            y: b.max.y,
+        });
+        ps.push(b.min);
+    }
+
+    // Triangulation requires that no three adjacent points are collinear.
+    let mut i = 0;
+    while ps.len() > 3 && i < ps.len() {
+        let n = ps.len();
+        let (a, b, c) = (ps[(i + n - 1) % n], ps[i], ps[(i + 1) % n]);
+        let cross = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+        if cross == 0.0 {
+            ps.remove(i);
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+    Some(Ppoly_t { ps })
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    /// Evaluates a piecewise cubic Bézier curve at `steps` points along each segment.
+    pub(in crate::layout) fn sample_curve(curve: &[Vec2<f64>], steps: usize) -> Vec<Vec2<f64>> {
+        let mut samples = Vec::new();
+        for seg in (0..curve.len() / 3).map(|i| &curve[i * 3..i * 3 + 4]) {
+            for s in 0..=steps {
+                samples.push(bezier_point(seg, s as f64 / steps as f64));
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn route_spline_test() {
+        init_test();
+
+        fn rect(x0: f64, y0: f64, x1: f64, y1: f64) -> Rect {
+            Rect {
+                min: Vec2 { x: x0, y: y0 },
+                max: Vec2 { x: x1, y: y1 },
+            }
+        }
+
+        fn case(description: &str, boxes: &[Rect], start: Vec2<f64>, end: Vec2<f64>) {
+            let curve = route_spline(boxes, start, end, Vec2::default(), Vec2::default())
+                .expect("corridor should be routable");
+            println!("--- {}\n{:?}", description, curve);
+            assert_eq!(curve.len() % 3, 1, "{}", description);
+            assert!(curve[0] == start && curve[curve.len() - 1] == end);
+            for p in sample_curve(&curve, 16) {
+                let inside = boxes.iter().any(|b| {
+                    p.x >= b.min.x - 1e-6
+                        && p.x <= b.max.x + 1e-6
+                        && p.y >= b.min.y - 1e-6
+                        && p.y <= b.max.y + 1e-6
+                });
+                assert!(inside, "{}: {:?} is outside the corridor", description, p);
+            }
+        }
+
+        case(
+            "straight",
+            &[rect(0.0, 0.0, 10.0, 10.0), rect(0.0, 10.0, 10.0, 20.0)],
+            Vec2 { x: 5.0, y: 0.0 },
+            Vec2 { x: 5.0, y: 20.0 },
+        );
+        case(
+            "dogleg",
+            &[
+                rect(0.0, 0.0, 10.0, 10.0),
+                rect(0.0, 10.0, 100.0, 20.0),
+                rect(90.0, 20.0, 100.0, 30.0),
+                rect(0.0, 30.0, 100.0, 40.0),
+                rect(0.0, 40.0, 10.0, 50.0),
+            ],
+            Vec2 { x: 5.0, y: 0.0 },
+            Vec2 { x: 5.0, y: 50.0 },
+        );
+
+        // Boxes that do not touch are not a corridor.
+        let gap = [rect(0.0, 0.0, 10.0, 10.0), rect(20.0, 10.0, 30.0, 20.0)];
+        let curve = route_spline(
+            &gap,
+            Vec2 { x: 5.0, y: 0.0 },
+            Vec2 { x: 25.0, y: 20.0 },
+            Vec2::default(),
+            Vec2::default(),
+        );
+        assert!(curve.is_none());
+    }
+
+    #[test]
+    fn ortho_polyline_test() {
+        // Layers 2, 1 and 0 are at [0, 10], [30, 40] and [60, 70].
+        let layer_extent = [(60.0, 70.0), (30.0, 40.0), (0.0, 10.0)];
+        let points = [
+            Vec2 { x: 0.0, y: 10.0 },
+            Vec2 { x: 20.0, y: 35.0 },
+            Vec2 { x: 20.0, y: 40.0 },
+            Vec2 { x: 40.0, y: 50.0 },
+            Vec2 { x: 50.0, y: 60.0 },
+        ];
+        let route = ortho_polyline(&points, &layer_extent);
+        let expected = [
+            Vec2 { x: 0.0, y: 10.0 },
+            Vec2 { x: 0.0, y: 20.0 },
+            Vec2 { x: 20.0, y: 20.0 },
+            Vec2 { x: 20.0, y: 35.0 },
+            Vec2 { x: 20.0, y: 40.0 },
+            Vec2 { x: 20.0, y: 45.0 },
+            Vec2 { x: 40.0, y: 45.0 },
+            Vec2 { x: 40.0, y: 50.0 },
+            Vec2 { x: 40.0, y: 55.0 },
+            Vec2 { x: 50.0, y: 55.0 },
+            Vec2 { x: 50.0, y: 60.0 },
+        ];
+        assert!(route == expected, "{:?}", route);
+    }
+
+    #[test]
+    fn curved_arc_test() {
+        let start = Vec2 { x: 0.0, y: 0.0 };
+        let end = Vec2 { x: 0.0, y: 60.0 };
+        let zero = Vec2::default();
+        let arc = curved_arc(start, end, zero, zero);
+        assert_eq!(arc.len(), 4);
+        assert!(arc[0] == start && arc[3] == end);
+        // Going down the page, the left side is toward larger x.
+        assert!(arc[1].x == 10.0 && arc[2].x == 10.0);
+
+        let arc = curved_arc(start, end, Vec2 { x: -1.0, y: 0.0 }, zero);
+        assert!(arc[1] == Vec2 { x: -20.0, y: 0.0 });
+    }
+
+    #[test]
+    fn clip_curve_start_test() {
+        let rect = Rect {
+            min: Vec2 { x: -10.0, y: -10.0 },
+            max: Vec2 { x: 10.0, y: 10.0 },
+        };
+        // Two straight segments, from the center to (0, 30) and then to (30, 30).
+        let curve = polyline_to_bezier(&[
+            Vec2 { x: 0.0, y: 0.0 },
+            Vec2 { x: 0.0, y: 30.0 },
+            Vec2 { x: 30.0, y: 30.0 },
+        ]);
+        let clipped = clip_curve_start(&curve, &rect).unwrap();
+        assert_eq!(clipped.len(), 7);
+        assert!(clipped[0].x == 0.0 && (clipped[0].y - 10.0).abs() < 1e-9);
+        assert!(clipped[3..] == curve[3..]);
+        // The rest of the first segment is still on the same line.
+        assert!(clipped[1].x == 0.0 && clipped[2].x == 0.0);
+
+        let inside = polyline_to_bezier(&[Vec2 { x: 0.0, y: 0.0 }, Vec2 { x: 5.0, y: 5.0 }]);
+        assert!(clip_curve_start(&inside, &rect).is_none());
+    }
+
+    #[test]
+    fn polyline_to_bezier_test() {
+        let points = [Vec2 { x: 0.0, y: 0.0 }, Vec2 { x: 3.0, y: 6.0 }];
+        let curve = polyline_to_bezier(&points);
+        assert_eq!(curve.len(), 4);
+        assert!(curve[1] == Vec2 { x: 1.0, y: 2.0 } && curve[2] == Vec2 { x: 2.0, y: 4.0 });
+        assert!(polyline_to_bezier(&[]).is_empty());
+    }
+}
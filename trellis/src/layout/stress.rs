@@ -0,0 +1,700 @@
+// Drawings of undirected graphs by stress majorization, as in neato (Gansner, Koren and North,
+// "Graph Drawing by Stress Majorization").
+//
+// The direction of edges is ignored. The ideal distance between two vertices is `edge_length`
+// times the number of edges on a shortest path between them, which a breadth-first search from
+// every vertex finds. Vertices in different components have no such path, so they are given the
+// distance of one edge more than the farthest vertices that do. The stress of a drawing is the
+// sum over all pairs of vertices of w_ij (|p_i - p_j| - d_ij)², where w_ij = d_ij⁻², so that
+// the distances between near vertices count the most.
+//
+// The initial drawing is the classical scaling of the ideal distances: the two main eigenvectors
+// of the doubly centered matrix of squared distances, found by power iteration. Unlike the
+// random start of neato, this is deterministic, and for most graphs it is already close to the
+// final drawing. Each iteration then moves every vertex in turn to
+//
+//     p_i = Σ_j w_ij (p_j + d_ij (p_i - p_j) / |p_i - p_j|) / Σ_j w_ij
+//
+// which minimizes a quadratic majorant of the stress, so the stress never increases. Pinned
+// vertices do not move. The iterations stop when the stress falls by less than the fraction
+// `epsilon`, or after `max_iterations`.
+//
+// `Polyline` edges follow the shortest path around the boxes of the other vertices, through
+// the visibility graph of their corners (`Pobspath`). `Spline` edges fit a curve to that path
+// with `Proutespline`, using the sides of the boxes as barriers. If an end of the edge is inside
+// the box of another vertex, there is no such path, and the edge is drawn as a line.
+
+use super::{
+    clip_polyline_start, clip_to_box, self_loop_points, splines, EdgeRouting, Layout, Rect,
+};
+use crate::graph::Graph;
+use crate::pathplan::cvt::{Pobsbarriers, Pobsopen, Pobspath};
+use crate::pathplan::route::Proutespline;
+use crate::pathplan::vis::vconfig_t;
+use crate::pathplan::Pedge_t;
+use crate::polyline::{Ppoly_t, Ppolyline_t};
+use crate::ramp_table::RampTable;
+use crate::vec2::Vec2;
+use log::debug;
+use std::collections::VecDeque;
+
+/// Options for `stress`.
+#[derive(Clone, Debug)]
+pub struct StressOptions {
+    /// The size (width and height) of each vertex. Vertices that do not have an entry use
+    /// `default_node_size`.
+    pub node_sizes: Vec<Vec2<f64>>,
+
+    pub default_node_size: Vec2<f64>,
+
+    /// The ideal length of an edge, from center to center. Vertices are not otherwise kept
+    /// apart, so they may overlap if they are large compared to this.
+    pub edge_length: f64,
+
+    /// For each vertex, the position of its center if it is pinned, or `None` if the layout may
+    /// move it. Vertices that do not have an entry are not pinned.
+    pub pinned: Vec<Option<Vec2<f64>>>,
+
+    /// The maximum number of iterations of stress majorization.
+    pub max_iterations: u32,
+
+    /// The iterations stop when the stress falls by less than this fraction of its value.
+    pub epsilon: f64,
+
+    /// How edges are drawn. `Line` and `Curved` edges may pass through other vertices, and
+    /// `Polyline` and `Spline` edges go around them. `Ortho` needs layers, so it is drawn as
+    /// `Line`. Self-loops are drawn on the right side of their vertex, as in `layered`.
+    pub edge_routing: EdgeRouting,
+}
+
+impl Default for StressOptions {
+    fn default() -> Self {
+        Self {
+            node_sizes: Vec::new(),
+            default_node_size: Vec2 { x: 54.0, y: 36.0 },
+            edge_length: 72.0,
+            pinned: Vec::new(),
+            max_iterations: 200,
+            epsilon: 1e-4,
+            edge_routing: EdgeRouting::Line,
+        }
+    }
+}
+
+impl StressOptions {
+    /// Returns the size of vertex `v`.
+    pub fn node_size(&self, v: usize) -> Vec2<f64> {
+        self.node_sizes
+            .get(v)
+            .copied()
+            .unwrap_or(self.default_node_size)
+    }
+
+    /// Returns the position of vertex `v` if it is pinned.
+    pub fn pinned(&self, v: usize) -> Option<Vec2<f64>> {
+        self.pinned.get(v).copied().flatten()
+    }
+}
+
+/// Returns the number of edges on a shortest path between each pair of vertices of `graph`,
+/// ignoring the direction of edges, or `None` if there is no path. The result is a matrix in
+/// row-major order: the entry for `u` and `v` is at `u * graph.num_verts() + v`.
+pub fn shortest_path_lengths(graph: &Graph) -> Vec<Option<u32>> {
+    let nv = graph.num_verts();
+    let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); nv];
+    for (from, to) in graph.iter_edges_flattened() {
+        if from != to {
+            neighbors[from as usize].push(to as usize);
+            neighbors[to as usize].push(from as usize);
+        }
+    }
+
+    let mut lengths: Vec<Option<u32>> = vec![None; nv * nv];
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    for source in 0..nv {
+        let row = &mut lengths[source * nv..(source + 1) * nv];
+        row[source] = Some(0);
+        queue.push_back(source);
+        while let Some(u) = queue.pop_front() {
+            let next = row[u].map(|n| n + 1);
+            for &v in neighbors[u].iter() {
+                if row[v].is_none() {
+                    row[v] = next;
+                    queue.push_back(v);
+                }
+            }
+        }
+    }
+    lengths
+}
+
+/// Computes a drawing of `graph` by stress majorization, ignoring the direction of its edges.
+///
+/// Pinned vertices are centered at their positions in `options.pinned`. If no vertex is pinned,
+/// the drawing is moved so that the leftmost and topmost vertices touch the axes. Every vertex
+/// is in layer 0.
+pub fn stress(graph: &Graph, options: &StressOptions) -> Layout {
+    let nv = graph.num_verts();
+    let node_sizes: Vec<Vec2<f64>> = (0..nv).map(|v| options.node_size(v)).collect();
+    let pinned: Vec<Option<Vec2<f64>>> = (0..nv).map(|v| options.pinned(v)).collect();
+
+    let lengths = shortest_path_lengths(graph);
+    let longest = lengths.iter().flatten().copied().max().unwrap_or(0);
+    let dist: Vec<f64> = lengths
+        .iter()
+        .map(|n| n.unwrap_or(longest + 1) as f64 * options.edge_length)
+        .collect();
+
+    let mut centers = classical_scaling(&dist, nv);
+    let pins: Vec<(usize, Vec2<f64>)> = pinned
+        .iter()
+        .enumerate()
+        .filter_map(|(v, p)| p.map(|p| (v, p)))
+        .collect();
+    if !pins.is_empty() {
+        // Move the initial drawing so that it is centered on the pinned vertices.
+        let count = pins.len() as f64;
+        let mut shift = Vec2::default();
+        for &(v, p) in pins.iter() {
+            shift = shift + (p - centers[v]) / count;
+        }
+        for c in centers.iter_mut() {
+            *c = *c + shift;
+        }
+        for &(v, p) in pins.iter() {
+            centers[v] = p;
+        }
+    }
+    majorize(&dist, &pinned, &mut centers, options);
+    if pins.is_empty() {
+        let left = (0..nv)
+            .map(|v| centers[v].x - node_sizes[v].x / 2.0)
+            .fold(f64::INFINITY, f64::min);
+        let top = (0..nv)
+            .map(|v| centers[v].y - node_sizes[v].y / 2.0)
+            .fold(f64::INFINITY, f64::min);
+        for c in centers.iter_mut() {
+            *c = *c - Vec2 { x: left, y: top };
+        }
+    }
+
+    let obstacles = match options.edge_routing {
+        EdgeRouting::Polyline | EdgeRouting::Spline => Some(Obstacles::new(&centers, &node_sizes)),
+        _ => None,
+    };
+    let loop_step = options.edge_length / 4.0;
+    let mut num_loops: Vec<u32> = vec![0; nv];
+    for (from, to) in graph.iter_edges_flattened() {
+        if from == to {
+            num_loops[from as usize] += 1;
+        }
+    }
+    let mut loop_index: Vec<u32> = vec![0; nv];
+    let mut edge_points: RampTable<Vec2<f64>> =
+        RampTable::with_capacity(graph.num_edges(), 2 * graph.num_edges());
+    let mut edge_splines: RampTable<Vec2<f64>> =
+        RampTable::with_capacity(graph.num_edges(), 4 * graph.num_edges());
+    for (from, to) in graph.iter_edges_flattened() {
+        let (from, to) = (from as usize, to as usize);
+        if from == to {
+            let points = self_loop_points(
+                centers[from],
+                node_sizes[from],
+                loop_index[from],
+                num_loops[from],
+                loop_step,
+            );
+            loop_index[from] += 1;
+            edge_splines.push_entry_copy(&splines::polyline_to_bezier(&points));
+            edge_points.push_entry_copy(&points);
+            continue;
+        }
+
+        let (a, b) = (centers[from], centers[to]);
+        let (from_rect, to_rect) = (
+            Rect::from_center_size(a, node_sizes[from]),
+            Rect::from_center_size(b, node_sizes[to]),
+        );
+        let line = vec![
+            clip_to_box(a, node_sizes[from], a, b),
+            clip_to_box(b, node_sizes[to], b, a),
+        ];
+        let route = obstacles.as_ref().and_then(|o| o.route(from, to));
+        let points = route
+            .as_ref()
+            .and_then(|path| clip_path(path, &from_rect, &to_rect))
+            .unwrap_or_else(|| line.clone());
+        let curve = match options.edge_routing {
+            EdgeRouting::Curved => {
+                splines::curved_arc(line[0], line[1], Vec2::default(), Vec2::default())
+            }
+            EdgeRouting::Spline => obstacles
+                .as_ref()
+                .zip(route.as_ref())
+                .and_then(|(o, path)| o.fit_spline(from, to, path))
+                .and_then(|curve| clip_path_curve(&curve, &from_rect, &to_rect))
+                .unwrap_or_else(|| splines::polyline_to_bezier(&points)),
+            _ => splines::polyline_to_bezier(&points),
+        };
+        edge_points.push_entry_copy(&points);
+        edge_splines.push_entry_copy(&curve);
+    }
+
+    // Curved edges bow away from the line between their ends, so the control points of the
+    // curves are included in the bounding box too.
+    let curve_rects: Vec<Rect> = edge_splines
+        .all_values()
+        .iter()
+        .map(|&p| Rect { min: p, max: p })
+        .collect();
+    let bounding_box =
+        super::compute_bounding_box(&centers, &node_sizes, &edge_points, curve_rects.iter());
+
+    Layout {
+        node_centers: centers,
+        node_sizes,
+        bounding_box,
+        edge_points,
+        edge_splines,
+        cluster_boxes: Vec::new(),
+        edge_label_positions: vec![None; graph.num_edges()],
+        node_layers: vec![0; nv],
+        edge_endpoints: graph.iter_edges_flattened().collect(),
+    }
+}
+
+/// The number of iterations of the power method for each axis of the initial drawing.
+const POWER_ITERATIONS: usize = 100;
+
+/// Returns the classical scaling of `dist`, an `nv` by `nv` matrix of distances in row-major
+/// order, in two dimensions.
+fn classical_scaling(dist: &[f64], nv: usize) -> Vec<Vec2<f64>> {
+    if nv == 0 {
+        return Vec::new();
+    }
+
+    // b = -½ J D² J, where D² has the squares of the distances and J subtracts the mean. The
+    // distances are symmetric, so the means of the columns are the means of the rows.
+    let squares: Vec<f64> = dist.iter().map(|d| d * d).collect();
+    let row_mean: Vec<f64> = squares
+        .chunks(nv)
+        .map(|row| row.iter().sum::<f64>() / nv as f64)
+        .collect();
+    let mean = row_mean.iter().sum::<f64>() / nv as f64;
+    let b: Vec<f64> = (0..nv * nv)
+        .map(|k| -0.5 * (squares[k] - row_mean[k / nv] - row_mean[k % nv] + mean))
+        .collect();
+
+    // Graph distances are not always Euclidean, so b may have negative eigenvalues. Adding
+    // `shift` to the diagonal makes them all positive, so that the power method finds the
+    // largest eigenvalues rather than those with the largest magnitude.
+    let shift = b
+        .chunks(nv)
+        .map(|row| row.iter().map(|x| x.abs()).sum::<f64>())
+        .fold(0.0, f64::max);
+    let dot = |x: &[f64], y: &[f64]| x.iter().zip(y.iter()).map(|(a, b)| a * b).sum::<f64>();
+
+    let mut axes: Vec<Vec<f64>> = Vec::with_capacity(2);
+    let mut scales: Vec<f64> = Vec::with_capacity(2);
+    for &step in [0.618_033_988_749_895, 0.414_213_562_373_095].iter() {
+        // A start vector that is unlikely to be orthogonal to the eigenvector.
+        let mut x: Vec<f64> = (0..nv)
+            .map(|i| ((i + 1) as f64 * step).fract() - 0.5)
+            .collect();
+        let mut lambda = 0.0;
+        for _ in 0..POWER_ITERATIONS {
+            for axis in axes.iter() {
+                let along = dot(&x, axis);
+                for (x, a) in x.iter_mut().zip(axis.iter()) {
+                    *x -= along * a;
+                }
+            }
+            let norm = dot(&x, &x).sqrt();
+            if norm == 0.0 {
+                break;
+            }
+            for x in x.iter_mut() {
+                *x /= norm;
+            }
+            let y: Vec<f64> = b
+                .chunks(nv)
+                .zip(x.iter())
+                .map(|(row, &xi)| dot(row, &x) + shift * xi)
+                .collect();
+            lambda = dot(&x, &y) - shift;
+            x = y;
+        }
+        let norm = dot(&x, &x).sqrt();
+        if norm > 0.0 {
+            for x in x.iter_mut() {
+                *x /= norm;
+            }
+        }
+        axes.push(x);
+        scales.push(lambda.max(0.0).sqrt());
+    }
+
+    (0..nv)
+        .map(|i| Vec2 {
+            x: axes[0][i] * scales[0],
+            y: axes[1][i] * scales[1],
+        })
+        .collect()
+}
+
+/// Returns the stress of `centers`, given the ideal distances `dist`.
+fn stress_of(dist: &[f64], centers: &[Vec2<f64>]) -> f64 {
+    let nv = centers.len();
+    let mut stress = 0.0;
+    for i in 0..nv {
+        for j in i + 1..nv {
+            let d = dist[i * nv + j];
+            let delta = centers[i] - centers[j];
+            let len = (delta.x * delta.x + delta.y * delta.y).sqrt();
+            stress += (len - d) * (len - d) / (d * d);
+        }
+    }
+    stress
+}
+
+/// Moves the vertices that are not pinned to reduce the stress of `centers`.
+fn majorize(
+    dist: &[f64],
+    pinned: &[Option<Vec2<f64>>],
+    centers: &mut [Vec2<f64>],
+    options: &StressOptions,
+) {
+    let nv = centers.len();
+    let mut stress = stress_of(dist, centers);
+    for iteration in 0..options.max_iterations {
+        for i in 0..nv {
+            if pinned[i].is_some() {
+                continue;
+            }
+            let mut sum = Vec2::default();
+            let mut total_weight = 0.0;
+            for j in (0..nv).filter(|&j| j != i) {
+                let d = dist[i * nv + j];
+                let w = 1.0 / (d * d);
+                let delta = centers[i] - centers[j];
+                let len = (delta.x * delta.x + delta.y * delta.y).sqrt();
+                let target = if len > 0.0 {
+                    centers[j] + delta * (d / len)
+                } else {
+                    centers[j]
+                };
+                sum = sum + target * w;
+                total_weight += w;
+            }
+            if total_weight > 0.0 {
+                centers[i] = sum / total_weight;
+            }
+        }
+
+        let new_stress = stress_of(dist, centers);
+        debug!("stress: iteration {}: stress {}", iteration, new_stress);
+        let converged = stress - new_stress <= options.epsilon * stress;
+        stress = new_stress;
+        if converged {
+            break;
+        }
+    }
+}
+
+/// The boxes of the vertices, as obstacles for edge routing.
+struct Obstacles {
+    rects: Vec<Rect>,
+    centers: Vec<Vec2<f64>>,
+    config: vconfig_t,
+    barriers: Vec<Pedge_t>,
+}
+
+impl Obstacles {
+    fn new(centers: &[Vec2<f64>], sizes: &[Vec2<f64>]) -> Self {
+        let rects: Vec<Rect> = centers
+            .iter()
+            .zip(sizes.iter())
+            .map(|(&c, &s)| Rect::from_center_size(c, s))
+            .collect();
+        // `Pobsopen` wants the corners of each polygon in clockwise order, with y increasing
+        // upward.
+        let polygons: Vec<Ppoly_t> = rects
+            .iter()
+            .map(|r| Ppoly_t {
+                ps: vec![
+                    r.min,
+                    Vec2 {
+                        x: r.min.x,
+                        y: r.max.y,
+                    },
+                    r.max,
+                    Vec2 {
+                        x: r.max.x,
+                        y: r.min.y,
+                    },
+                ],
+            })
+            .collect();
+        let config = Pobsopen(&polygons.iter().collect::<Vec<&Ppoly_t>>());
+        let barriers = Pobsbarriers(&config);
+        Self {
+            rects,
+            centers: centers.to_vec(),
+            config,
+            barriers,
+        }
+    }
+
+    /// Returns the shortest path from the center of `from` to the center of `to` that does not
+    /// cross the boxes of other vertices, or `None` if either center is inside another box.
+    fn route(&self, from: usize, to: usize) -> Option<Vec<Vec2<f64>>> {
+        let (a, b) = (self.centers[from], self.centers[to]);
+        let blocked = self
+            .rects
+            .iter()
+            .enumerate()
+            .any(|(v, r)| v != from && v != to && (r.contains(a) || r.contains(b)));
+        if blocked {
+            return None;
+        }
+        Some(Pobspath(&self.config, a, from as i32, b, to as i32)?.ps)
+    }
+
+    /// Fits a curve to `path`, which goes from the center of `from` to the center of `to`,
+    /// using the sides of the boxes of the other vertices as barriers.
+    fn fit_spline(&self, from: usize, to: usize, path: &[Vec2<f64>]) -> Option<Vec<Vec2<f64>>> {
+        let ends =
+            [from, to].map(|v| self.config.start[v] as usize..self.config.start[v + 1] as usize);
+        let barriers: Vec<Pedge_t> = self
+            .barriers
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !ends.iter().any(|r| r.contains(i)))
+            .map(|(_, barrier)| barrier.clone())
+            .collect();
+        let input = Ppolyline_t { ps: path.to_vec() };
+        let mut evs = [Vec2::default(); 2];
+        let mut spline = Ppolyline_t::default();
+        if let Err(e) = Proutespline(&barriers, &input, &mut evs, &mut spline) {
+            debug!("stress: cannot fit spline: {:?}", e);
+            return None;
+        }
+        Some(spline.ps)
+    }
+}
+
+/// Removes the parts of `path` that are inside `from` at its start and inside `to` at its end.
+fn clip_path(path: &[Vec2<f64>], from: &Rect, to: &Rect) -> Option<Vec<Vec2<f64>>> {
+    let mut points = clip_polyline_start(path, from)?;
+    points.reverse();
+    let mut points = clip_polyline_start(&points, to)?;
+    points.reverse();
+    Some(points)
+}
+
+/// Like `clip_path`, for a piecewise cubic Bézier curve.
+fn clip_path_curve(curve: &[Vec2<f64>], from: &Rect, to: &Rect) -> Option<Vec<Vec2<f64>>> {
+    let mut points = splines::clip_curve_start(curve, from)?;
+    points.reverse();
+    let mut points = splines::clip_curve_start(&points, to)?;
+    points.reverse();
+    Some(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::check_layout;
+    use super::*;
+    use crate::testing::*;
+
+    fn distance(a: Vec2<f64>, b: Vec2<f64>) -> f64 {
+        let d = a - b;
+        (d.x * d.x + d.y * d.y).sqrt()
+    }
+
+    /// Returns true if `p` is strictly inside `r`.
+    fn inside(r: &Rect, p: Vec2<f64>) -> bool {
+        const MARGIN: f64 = 1e-6;
+        p.x > r.min.x + MARGIN
+            && p.x < r.max.x - MARGIN
+            && p.y > r.min.y + MARGIN
+            && p.y < r.max.y - MARGIN
+    }
+
+    #[test]
+    fn shortest_path_lengths_test() {
+        init_test();
+
+        // 0 -> 1 <- 2, 3 -> 3, and 4 on its own.
+        let mut graph = graph_from_paths(&[&[0, 1], &[2, 1], &[3, 3]]);
+        graph.finish_from();
+        let lengths = shortest_path_lengths(&graph);
+        println!("{:?}", lengths);
+        let n = graph.num_verts();
+        assert_eq!(n, 5);
+        for v in 0..n {
+            assert_eq!(lengths[v * n + v], Some(0));
+        }
+        assert_eq!(lengths[n], Some(1));
+        assert_eq!(lengths[2 * n], Some(2));
+        assert_eq!(lengths[2], Some(2));
+        assert_eq!(lengths[3], None);
+        assert_eq!(lengths[3 * n + 4], None);
+    }
+
+    #[test]
+    fn stress_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph) -> Layout {
+            // Stress majorization does not keep vertices apart, so they are small enough not to
+            // overlap when edges are a little shorter than ideal, as in K4.
+            let options = StressOptions {
+                default_node_size: Vec2 { x: 36.0, y: 36.0 },
+                ..StressOptions::default()
+            };
+            let layout = stress(graph, &options);
+            println!("--- {}\n{:#?}", description, layout);
+            check_layout(graph, &layout);
+            assert!(distance(layout.bounding_box.min, Vec2::default()) < 1e-9);
+            assert!(layout.node_layers.iter().all(|&layer| layer == 0));
+
+            // Majorization starts from classical scaling, and never increases the stress.
+            let lengths = shortest_path_lengths(graph);
+            let longest = lengths.iter().flatten().copied().max().unwrap_or(0);
+            let dist: Vec<f64> = lengths
+                .iter()
+                .map(|n| n.unwrap_or(longest + 1) as f64 * options.edge_length)
+                .collect();
+            let initial = classical_scaling(&dist, graph.num_verts());
+            assert!(stress_of(&dist, &layout.node_centers) <= stress_of(&dist, &initial) + 1e-9);
+
+            // Vertices that are connected by an edge are about one edge length apart.
+            for (from, to) in graph.iter_edges_flattened() {
+                if from != to {
+                    let d = distance(
+                        layout.node_centers[from as usize],
+                        layout.node_centers[to as usize],
+                    );
+                    assert!(
+                        (d - options.edge_length).abs() < 0.25 * options.edge_length,
+                        "v{} and v{} are {} apart",
+                        from,
+                        to,
+                        d
+                    );
+                }
+            }
+            layout
+        }
+
+        let mut single = Graph::new();
+        single.finish_from();
+        case("single", &single);
+        case("self-loop", &graph_from_paths(&[&[0, 0, 1]]));
+        case("triangle", &graph_from_paths(&[&[0, 1, 2, 0]]));
+        case("cycle", &graph_from_paths(&[&[0, 1, 2, 3, 4, 5, 0]]));
+        case(
+            "star",
+            &graph_from_paths(&[&[0, 1], &[0, 2], &[0, 3], &[0, 4]]),
+        );
+        case("k4", &graph_from_paths(&[&[0, 1, 2, 3, 0, 2], &[1, 3]]));
+        case("two components", &graph_from_paths(&[&[0, 1, 2], &[3, 4]]));
+        case(
+            "grid",
+            &graph_from_paths(&[
+                &[0, 1, 2],
+                &[3, 4, 5],
+                &[6, 7, 8],
+                &[0, 3, 6],
+                &[1, 4, 7],
+                &[2, 5, 8],
+            ]),
+        );
+
+        // A path is drawn straight, with the ideal distance between every pair of vertices.
+        let layout = case("path", &graph_from_paths(&[&[0, 1, 2, 3]]));
+        let ends = distance(layout.node_centers[0], layout.node_centers[3]);
+        assert!((ends - 3.0 * 72.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn pinned_test() {
+        init_test();
+
+        // v0 and v2 are pinned far apart. v1 is between them, and v3 hangs off v1.
+        let graph = graph_from_paths(&[&[0, 1, 2], &[1, 3]]);
+        let p0 = Vec2 { x: 100.0, y: 100.0 };
+        let p2 = Vec2 { x: 300.0, y: 100.0 };
+        let options = StressOptions {
+            pinned: vec![Some(p0), None, Some(p2)],
+            ..StressOptions::default()
+        };
+        let layout = stress(&graph, &options);
+        println!("{:#?}", layout);
+        check_layout(&graph, &layout);
+        assert!(layout.node_centers[0] == p0);
+        assert!(layout.node_centers[2] == p2);
+        let v1 = layout.node_centers[1];
+        assert!((v1.x - 200.0).abs() < 1.0);
+        assert!(distance(v1, layout.node_centers[3]) > 36.0);
+    }
+
+    #[test]
+    fn routed_edges_test() {
+        init_test();
+
+        // Every vertex is pinned on a line, so that the edge from v0 to v2 (edge 1) would pass
+        // through v1.
+        let graph = graph_from_paths(&[&[0, 1, 2], &[0, 2]]);
+        let pinned: Vec<Option<Vec2<f64>>> = (0..3)
+            .map(|v| {
+                Some(Vec2 {
+                    x: v as f64 * 100.0,
+                    y: 0.0,
+                })
+            })
+            .collect();
+        for &edge_routing in [
+            EdgeRouting::Line,
+            EdgeRouting::Polyline,
+            EdgeRouting::Ortho,
+            EdgeRouting::Spline,
+            EdgeRouting::Curved,
+        ]
+        .iter()
+        {
+            let options = StressOptions {
+                pinned: pinned.clone(),
+                edge_routing,
+                ..StressOptions::default()
+            };
+            let layout = stress(&graph, &options);
+            println!("--- {:?}\n{:#?}", edge_routing, layout);
+            check_layout(&graph, &layout);
+
+            // Sample the curve of the long edge, and check whether it crosses v1.
+            let curve = layout.edge_splines.entry_values(1);
+            let v1 = layout.node_rect(1);
+            let crosses = curve.windows(4).step_by(3).any(|c| {
+                (0..=16).any(|i| {
+                    let t = i as f64 / 16.0;
+                    let s = 1.0 - t;
+                    let p = c[0] * (s * s * s)
+                        + c[1] * (3.0 * s * s * t)
+                        + c[2] * (3.0 * s * t * t)
+                        + c[3] * (t * t * t);
+                    inside(&v1, p)
+                })
+            });
+            match edge_routing {
+                EdgeRouting::Line | EdgeRouting::Ortho => assert!(crosses),
+                EdgeRouting::Polyline | EdgeRouting::Spline => {
+                    assert!(!crosses);
+                    assert!(layout.edge_points.entry_values(1).len() > 2);
+                }
+                EdgeRouting::Curved => {}
+            }
+        }
+    }
+}
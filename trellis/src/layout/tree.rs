@@ -0,0 +1,577 @@
+// Tidy drawings of rooted trees, using Walker's algorithm ("A Node-Positioning Algorithm for
+// General Trees"), in the linear-time form of Buchheim, Jünger and Leipert ("Improving Walker's
+// Algorithm to Run in Linear Time"), which extends the binary-tree algorithm of Reingold and
+// Tilford.
+//
+// Each vertex is drawn on the level of its depth. The first walk visits the tree bottom-up and
+// places each subtree as a rigid unit: it is pushed to the right of its left siblings until
+// their contours are `node_sep` apart, and the shift is spread over the siblings in between so
+// that small subtrees are evenly spaced. A parent is centered over its first and last children.
+// The contours are followed through "threads", which link the last vertex of a shallow subtree
+// to the next vertex of the contour. The second walk sums the shifts to get the x coordinates.
+//
+// Both walks use explicit stacks, since trees such as syntax trees can be very deep.
+
+use super::{clip_to_box, splines, Layout, RankDir, Rect};
+use crate::error::Error;
+use crate::graph::Graph;
+use crate::ramp_table::RampTable;
+use crate::vec2::Vec2;
+use crate::V;
+
+/// Options for `tree`.
+#[derive(Clone, Debug)]
+pub struct TreeOptions {
+    /// The size (width and height) of each vertex. Vertices that do not have an entry use
+    /// `default_node_size`.
+    pub node_sizes: Vec<Vec2<f64>>,
+
+    pub default_node_size: Vec2<f64>,
+
+    /// The space between adjacent levels. This is vertical space for `TopToBottom` and
+    /// `BottomToTop`, and horizontal space for `LeftToRight` and `RightToLeft`.
+    pub rank_sep: f64,
+
+    /// The minimum space between adjacent vertices in the same level.
+    pub node_sep: f64,
+
+    pub rank_dir: RankDir,
+}
+
+impl Default for TreeOptions {
+    fn default() -> Self {
+        Self {
+            node_sizes: Vec::new(),
+            default_node_size: Vec2 { x: 54.0, y: 36.0 },
+            rank_sep: 36.0,
+            node_sep: 18.0,
+            rank_dir: RankDir::default(),
+        }
+    }
+}
+
+impl TreeOptions {
+    /// Returns the size of vertex `v`.
+    pub fn node_size(&self, v: usize) -> Vec2<f64> {
+        self.node_sizes
+            .get(v)
+            .copied()
+            .unwrap_or(self.default_node_size)
+    }
+}
+
+/// Finds the root of `graph`, if it is a rooted tree: exactly one vertex has no incoming edges,
+/// every other vertex has exactly one, and every vertex can be reached from the root.
+///
+/// Returns `Error::EmptyGraph` if `graph` has no vertices, and `Error::NotATree` otherwise if it
+/// is not a rooted tree.
+pub fn find_tree_root(graph: &Graph) -> Result<V, Error> {
+    let nv = graph.num_verts();
+    if nv == 0 {
+        return Err(Error::EmptyGraph);
+    }
+    let mut in_degree: Vec<u32> = vec![0; nv];
+    for (_, to) in graph.iter_edges_flattened() {
+        in_degree[to as usize] += 1;
+    }
+    let roots: Vec<V> = (0..nv as V)
+        .filter(|&v| in_degree[v as usize] == 0)
+        .collect();
+    if roots.len() != 1 {
+        return Err(Error::NotATree(roots));
+    }
+    let shared: Vec<V> = (0..nv as V)
+        .filter(|&v| in_degree[v as usize] > 1)
+        .collect();
+    if !shared.is_empty() {
+        return Err(Error::NotATree(shared));
+    }
+
+    // With one root and one parent for every other vertex, the only other way to fail is a
+    // cycle that cannot be reached from the root.
+    let root = roots[0];
+    let mut reached: Vec<bool> = vec![false; nv];
+    let mut stack: Vec<V> = vec![root];
+    reached[root as usize] = true;
+    while let Some(v) = stack.pop() {
+        for &w in graph.edges_from(v).iter() {
+            if !reached[w as usize] {
+                reached[w as usize] = true;
+                stack.push(w);
+            }
+        }
+    }
+    let unreached: Vec<V> = (0..nv as V).filter(|&v| !reached[v as usize]).collect();
+    if !unreached.is_empty() {
+        return Err(Error::NotATree(unreached));
+    }
+    Ok(root)
+}
+
+/// Computes a tidy drawing of `graph`, which must be a rooted tree. The children of each vertex
+/// are drawn from left to right (for `TopToBottom`) in the order of its edges, and each edge is
+/// drawn as a straight line. `Layout::node_layers` gives the level of each vertex, counted from
+/// the deepest level.
+///
+/// Returns `Error::EmptyGraph` if `graph` has no vertices, and `Error::NotATree` if it is not a
+/// rooted tree (see `find_tree_root`).
+pub fn tree(graph: &Graph, options: &TreeOptions) -> Result<Layout, Error> {
+    let root = find_tree_root(graph)?;
+    let nv = graph.num_verts();
+    let node_sizes: Vec<Vec2<f64>> = (0..nv).map(|v| options.node_size(v)).collect();
+    let rank_dir = options.rank_dir;
+    let tb_sizes: Vec<Vec2<f64>> = node_sizes.iter().map(|&s| rank_dir.swap_size(s)).collect();
+
+    // The parent, depth, and position among its siblings of each vertex, and the vertices in
+    // pre-order, visiting children from right to left. Reversed, this is a post-order that
+    // visits children from left to right, which is the order of Walker's first walk.
+    let mut parent: Vec<Option<V>> = vec![None; nv];
+    let mut depth: Vec<u32> = vec![0; nv];
+    let mut number: Vec<u32> = vec![0; nv];
+    let mut order: Vec<V> = Vec::with_capacity(nv);
+    let mut stack: Vec<V> = vec![root];
+    while let Some(v) = stack.pop() {
+        order.push(v);
+        for (i, &w) in graph.edges_from(v).iter().enumerate() {
+            parent[w as usize] = Some(v);
+            depth[w as usize] = depth[v as usize] + 1;
+            number[w as usize] = i as u32;
+            stack.push(w);
+        }
+    }
+
+    let mut walker = Walker {
+        graph,
+        parent: &parent,
+        number: &number,
+        width: tb_sizes.iter().map(|s| s.x).collect(),
+        node_sep: options.node_sep,
+        prelim: vec![0.0; nv],
+        modifier: vec![0.0; nv],
+        shift: vec![0.0; nv],
+        change: vec![0.0; nv],
+        thread: vec![None; nv],
+        ancestor: (0..nv as V).collect(),
+    };
+    let mut default_ancestor: Vec<V> = (0..nv as V)
+        .map(|v| graph.edges_from(v).first().copied().unwrap_or(v))
+        .collect();
+    for &v in order.iter().rev() {
+        walker.first_walk(v);
+        if let Some(p) = parent[v as usize] {
+            let p = p as usize;
+            default_ancestor[p] = walker.apportion(v, default_ancestor[p]);
+        }
+    }
+
+    // The second walk: the x coordinate of a vertex is its preliminary x plus the modifiers of
+    // all of its ancestors.
+    let mut x: Vec<f64> = vec![0.0; nv];
+    let mut modifier_sum: Vec<f64> = vec![0.0; nv];
+    for &v in order.iter() {
+        let v = v as usize;
+        x[v] = walker.prelim[v] + modifier_sum[v];
+        for &w in graph.edges_from(v as V).iter() {
+            modifier_sum[w as usize] = modifier_sum[v] + walker.modifier[v];
+        }
+    }
+
+    // Each level is as tall as its tallest vertex.
+    let num_levels = depth.iter().copied().max().unwrap_or(0) as usize + 1;
+    let mut level_height: Vec<f64> = vec![0.0; num_levels];
+    for (&d, s) in depth.iter().zip(tb_sizes.iter()) {
+        level_height[d as usize] = level_height[d as usize].max(s.y);
+    }
+    let mut level_y: Vec<f64> = Vec::with_capacity(num_levels);
+    let mut top = 0.0;
+    for &h in level_height.iter() {
+        level_y.push(top + h / 2.0);
+        top += h + options.rank_sep;
+    }
+    let left = x
+        .iter()
+        .zip(tb_sizes.iter())
+        .map(|(&x, s)| x - s.x / 2.0)
+        .fold(f64::INFINITY, f64::min);
+    let centers: Vec<Vec2<f64>> = (0..nv)
+        .map(|v| Vec2 {
+            x: x[v] - left,
+            y: level_y[depth[v] as usize],
+        })
+        .collect();
+
+    let mut edge_points: RampTable<Vec2<f64>> =
+        RampTable::with_capacity(graph.num_edges(), 2 * graph.num_edges());
+    for (from, to) in graph.iter_edges_flattened() {
+        let (from, to) = (from as usize, to as usize);
+        let (a, b) = (centers[from], centers[to]);
+        edge_points.push_entry_copy(&[
+            clip_to_box(a, tb_sizes[from], a, b),
+            clip_to_box(b, tb_sizes[to], b, a),
+        ]);
+    }
+
+    let tb_bounds =
+        super::compute_bounding_box(&centers, &tb_sizes, &edge_points, std::iter::empty());
+    let node_centers: Vec<Vec2<f64>> = centers
+        .iter()
+        .map(|&p| rank_dir.transform_point(p, &tb_bounds))
+        .collect();
+    for p in edge_points.all_values_mut().iter_mut() {
+        *p = rank_dir.transform_point(*p, &tb_bounds);
+    }
+    let mut edge_splines: RampTable<Vec2<f64>> =
+        RampTable::with_capacity(graph.num_edges(), 4 * graph.num_edges());
+    for points in edge_points.iter() {
+        edge_splines.push_entry_copy(&splines::polyline_to_bezier(points));
+    }
+    let corner = rank_dir.transform_point(tb_bounds.min, &tb_bounds);
+    let bounding_box = Rect {
+        min: corner,
+        max: corner,
+    }
+    .union_point(rank_dir.transform_point(tb_bounds.max, &tb_bounds));
+
+    Ok(Layout {
+        node_centers,
+        node_sizes,
+        bounding_box,
+        edge_points,
+        edge_splines,
+        cluster_boxes: Vec::new(),
+        edge_label_positions: vec![None; graph.num_edges()],
+        node_layers: depth.iter().map(|&d| num_levels as u32 - 1 - d).collect(),
+        edge_endpoints: graph.iter_edges_flattened().collect(),
+    })
+}
+
+/// The state of Walker's first walk. The names follow Buchheim, Jünger and Leipert.
+struct Walker<'a> {
+    graph: &'a Graph,
+    parent: &'a [Option<V>],
+
+    /// The position of each vertex among its siblings.
+    number: &'a [u32],
+
+    width: Vec<f64>,
+    node_sep: f64,
+
+    /// The x coordinate of each vertex relative to its parent's subtree, before the modifiers
+    /// of its ancestors are added.
+    prelim: Vec<f64>,
+
+    /// The amount by which the subtree of each vertex is moved, applied to its descendants in
+    /// the second walk.
+    modifier: Vec<f64>,
+
+    /// Shifts that are spread over siblings by `execute_shifts`.
+    shift: Vec<f64>,
+    change: Vec<f64>,
+
+    /// The next vertex on the contour, for vertices that have no children.
+    thread: Vec<Option<V>>,
+
+    ancestor: Vec<V>,
+}
+
+impl<'a> Walker<'a> {
+    fn children(&self, v: V) -> &'a [V] {
+        self.graph.edges_from(v)
+    }
+
+    fn left_sibling(&self, v: V) -> Option<V> {
+        let p = self.parent[v as usize]?;
+        let i = self.number[v as usize] as usize;
+        if i > 0 {
+            Some(self.children(p)[i - 1])
+        } else {
+            None
+        }
+    }
+
+    fn leftmost_sibling(&self, v: V) -> V {
+        match self.parent[v as usize] {
+            Some(p) => self.children(p)[0],
+            None => v,
+        }
+    }
+
+    /// The next vertex on the left contour of the subtree of `v`.
+    fn next_left(&self, v: V) -> Option<V> {
+        self.children(v)
+            .first()
+            .copied()
+            .or(self.thread[v as usize])
+    }
+
+    /// The next vertex on the right contour of the subtree of `v`.
+    fn next_right(&self, v: V) -> Option<V> {
+        self.children(v).last().copied().or(self.thread[v as usize])
+    }
+
+    /// The minimum distance between the centers of `left` and `right`, which are on the same
+    /// level.
+    fn distance(&self, left: V, right: V) -> f64 {
+        (self.width[left as usize] + self.width[right as usize]) / 2.0 + self.node_sep
+    }
+
+    /// Places `v`, whose children have already been placed, next to its left sibling and over
+    /// its children.
+    fn first_walk(&mut self, v: V) {
+        let vi = v as usize;
+        let children = self.children(v);
+        let left = self.left_sibling(v);
+        if children.is_empty() {
+            self.prelim[vi] = match left {
+                Some(w) => self.prelim[w as usize] + self.distance(w, v),
+                None => 0.0,
+            };
+            return;
+        }
+        self.execute_shifts(v);
+        let first = children[0] as usize;
+        let last = children[children.len() - 1] as usize;
+        let midpoint = (self.prelim[first] + self.prelim[last]) / 2.0;
+        match left {
+            Some(w) => {
+                self.prelim[vi] = self.prelim[w as usize] + self.distance(w, v);
+                self.modifier[vi] = self.prelim[vi] - midpoint;
+            }
+            None => self.prelim[vi] = midpoint,
+        }
+    }
+
+    /// Pushes the subtree of `v` to the right of the subtrees of its left siblings, comparing
+    /// the right contour of the siblings with the left contour of `v` on each level. Returns the
+    /// new default ancestor for the siblings of `v`.
+    fn apportion(&mut self, v: V, default_ancestor: V) -> V {
+        let w = match self.left_sibling(v) {
+            Some(w) => w,
+            None => return default_ancestor,
+        };
+        let mut default_ancestor = default_ancestor;
+
+        // The inside and outside contours, to the right (p) and left (m) of the gap.
+        let (mut vip, mut vop) = (v, v);
+        let (mut vim, mut vom) = (w, self.leftmost_sibling(v));
+        let mut sip = self.modifier[vip as usize];
+        let mut sop = self.modifier[vop as usize];
+        let mut sim = self.modifier[vim as usize];
+        let mut som = self.modifier[vom as usize];
+        while let (Some(next_im), Some(next_ip)) = (self.next_right(vim), self.next_left(vip)) {
+            vim = next_im;
+            vip = next_ip;
+            vom = self.next_left(vom).unwrap();
+            vop = self.next_right(vop).unwrap();
+            self.ancestor[vop as usize] = v;
+            let shift = (self.prelim[vim as usize] + sim) - (self.prelim[vip as usize] + sip)
+                + self.distance(vim, vip);
+            if shift > 0.0 {
+                let a = self.ancestor_of(vim, v, default_ancestor);
+                self.move_subtree(a, v, shift);
+                sip += shift;
+                sop += shift;
+            }
+            sim += self.modifier[vim as usize];
+            sip += self.modifier[vip as usize];
+            som += self.modifier[vom as usize];
+            sop += self.modifier[vop as usize];
+        }
+
+        // Thread the shallower contour to the deeper one.
+        if let (Some(next), None) = (self.next_right(vim), self.next_right(vop)) {
+            self.thread[vop as usize] = Some(next);
+            self.modifier[vop as usize] += sim - sop;
+        }
+        if let (Some(next), None) = (self.next_left(vip), self.next_left(vom)) {
+            self.thread[vom as usize] = Some(next);
+            self.modifier[vom as usize] += sip - som;
+            default_ancestor = v;
+        }
+        default_ancestor
+    }
+
+    /// The sibling of `v` whose subtree contains `vim`, if it is known, and otherwise the default
+    /// ancestor.
+    fn ancestor_of(&self, vim: V, v: V, default_ancestor: V) -> V {
+        let a = self.ancestor[vim as usize];
+        if self.parent[a as usize] == self.parent[v as usize] {
+            a
+        } else {
+            default_ancestor
+        }
+    }
+
+    /// Moves the subtree of `right` by `shift`, and records that the subtrees of the siblings
+    /// between `left` and `right` should move by a share of it.
+    fn move_subtree(&mut self, left: V, right: V, shift: f64) {
+        let subtrees = (self.number[right as usize] - self.number[left as usize]) as f64;
+        let (left, right) = (left as usize, right as usize);
+        self.change[right] -= shift / subtrees;
+        self.shift[right] += shift;
+        self.change[left] += shift / subtrees;
+        self.prelim[right] += shift;
+        self.modifier[right] += shift;
+    }
+
+    /// Applies the shifts recorded by `move_subtree` to the children of `v`.
+    fn execute_shifts(&mut self, v: V) {
+        let mut shift = 0.0;
+        let mut change = 0.0;
+        for &w in self.children(v).iter().rev() {
+            let w = w as usize;
+            self.prelim[w] += shift;
+            self.modifier[w] += shift;
+            change += self.change[w];
+            shift += self.shift[w] + change;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{check_layout, ALL_RANK_DIRS};
+    use super::*;
+    use crate::testing::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    #[test]
+    fn find_tree_root_test() {
+        init_test();
+
+        let mut single = Graph::new();
+        single.finish_from();
+        assert_eq!(find_tree_root(&Graph::new()), Err(Error::EmptyGraph));
+        assert_eq!(find_tree_root(&single), Ok(0));
+        assert_eq!(
+            find_tree_root(&graph_from_paths(&[&[2, 0], &[2, 1, 3]])),
+            Ok(2)
+        );
+        assert_eq!(
+            find_tree_root(&graph_from_paths(&[&[0, 2], &[1, 2]])),
+            Err(Error::NotATree(vec![0, 1]))
+        );
+        assert_eq!(
+            find_tree_root(&graph_from_paths(&[&[0, 1, 3], &[0, 2, 3]])),
+            Err(Error::NotATree(vec![3]))
+        );
+        assert_eq!(
+            find_tree_root(&graph_from_paths(&[&[0, 1], &[2, 3, 2]])),
+            Err(Error::NotATree(vec![2, 3]))
+        );
+    }
+
+    #[test]
+    fn tree_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph, node_sizes: Vec<Vec2<f64>>) -> Layout {
+            let mut tb = None;
+            for &rank_dir in ALL_RANK_DIRS.iter() {
+                let options = TreeOptions {
+                    node_sizes: node_sizes.clone(),
+                    rank_dir,
+                    ..TreeOptions::default()
+                };
+                let layout = tree(graph, &options).unwrap();
+                println!("--- {} ({:?})\n{:#?}", description, rank_dir, layout);
+                check_layout(graph, &layout);
+
+                // Each parent is on the level above its children, and centered over its first
+                // and last children.
+                let across = |v: V| rank_dir.inverse_vector(layout.node_centers[v as usize]).x;
+                let along = |v: V| rank_dir.inverse_vector(layout.node_centers[v as usize]).y;
+                for v in 0..graph.num_verts() as V {
+                    let children = graph.edges_from(v);
+                    for &w in children.iter() {
+                        assert!(along(w) > along(v));
+                        assert_eq!(
+                            layout.node_layers[v as usize],
+                            layout.node_layers[w as usize] + 1
+                        );
+                    }
+                    if let (Some(&first), Some(&last)) = (children.first(), children.last()) {
+                        let middle = (across(first) + across(last)) / 2.0;
+                        assert!(
+                            (across(v) - middle).abs() < EPSILON,
+                            "v{} is not centered",
+                            v
+                        );
+                    }
+                    // Siblings are in the order of the edges.
+                    for pair in children.windows(2) {
+                        assert!(across(pair[0]) < across(pair[1]));
+                    }
+                }
+                if rank_dir == RankDir::TopToBottom {
+                    tb = Some(layout);
+                }
+            }
+            tb.unwrap()
+        }
+
+        let mut single = Graph::new();
+        single.finish_from();
+        case("one vertex", &single, Vec::new());
+        case("path", &graph_from_paths(&[&[0, 1, 2, 3]]), Vec::new());
+
+        // The root is centered over three equal children.
+        let layout = case(
+            "star",
+            &graph_from_paths(&[&[0, 1], &[0, 2], &[0, 3]]),
+            Vec::new(),
+        );
+        let x: Vec<f64> = layout.node_centers.iter().map(|c| c.x).collect();
+        assert_eq!(x, vec![99.0, 27.0, 99.0, 171.0]);
+
+        // 1 has a deep subtree on the right, and 2 has a deep subtree on the left, so they are
+        // separated by their descendants rather than by themselves.
+        case(
+            "contours",
+            &graph_from_paths(&[
+                &[0, 1, 3, 5, 7],
+                &[1, 4, 6, 8],
+                &[0, 2, 9, 11, 13],
+                &[2, 10],
+                &[9, 12],
+            ]),
+            Vec::new(),
+        );
+
+        // A small subtree between two large ones is centered in the gap between them, rather
+        // than being packed against the left one.
+        let graph = graph_from_paths(&[
+            &[0, 1, 4],
+            &[1, 5],
+            &[1, 6],
+            &[0, 2],
+            &[0, 3, 7],
+            &[3, 8],
+            &[3, 9],
+        ]);
+        let layout = case("spread", &graph, Vec::new());
+        let x: Vec<f64> = layout.node_centers.iter().map(|c| c.x).collect();
+        assert!((x[2] - (x[1] + x[3]) / 2.0).abs() < EPSILON);
+
+        // Wide and tall vertices.
+        let sizes: Vec<Vec2<f64>> = (0..graph.num_verts())
+            .map(|v| Vec2 {
+                x: 20.0 + 15.0 * (v % 4) as f64,
+                y: 10.0 + 20.0 * (v % 3) as f64,
+            })
+            .collect();
+        case("sizes", &graph, sizes);
+    }
+
+    #[test]
+    fn tree_errors_test() {
+        init_test();
+        let options = TreeOptions::default();
+        assert_eq!(tree(&Graph::new(), &options).err(), Some(Error::EmptyGraph));
+        assert_eq!(
+            tree(&graph_from_paths(&[&[0, 1, 0]]), &options).err(),
+            Some(Error::NotATree(vec![]))
+        );
+    }
+}
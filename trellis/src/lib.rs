@@ -8,20 +8,27 @@
 use crate::ramp_table::RampTable;
 use core::u16;
 
+pub mod brandes_kopf;
 pub mod cgraph;
 pub mod common;
+pub mod crossings;
+pub mod cycle_removal;
 pub mod disjoint;
 pub mod error;
 pub mod find_chains;
 pub mod graph;
 pub mod gvc;
 pub mod layering;
+pub mod layout;
 pub mod math;
+pub mod network_simplex;
+pub mod ordering;
 pub mod ortho;
 pub mod pathplan;
 pub mod polyline;
 pub mod priority_queue;
 pub mod ramp_table;
+pub mod scc;
 pub mod topo_sort;
 pub mod vec2;
 pub mod vec_option;
@@ -0,0 +1,702 @@
+use crate::error::Error;
+use crate::graph::Graph;
+use crate::ramp_table::{RampTable, RampTableBuilder};
+use crate::{E, V};
+use log::{debug, warn};
+
+/// The maximum number of pivots that `network_simplex_ranks` will perform. This is only a
+/// safeguard; the algorithm normally terminates long before this. If it is reached, the ranks
+/// are feasible but their total edge length may not be minimal, and a warning is logged.
+const MAX_ITERATIONS: usize = 100_000;
+
+/// `leave_edge` returns the most negative of the first this many tree edges with negative cut
+/// values that it finds, like `Search_size` in dot.
+const SEARCH_SIZE: usize = 30;
+
+const NO_EDGE: E = !0u32;
+
+/// Assigns an integer rank to every vertex of an acyclic graph, using the network simplex
+/// method of Gansner, Koutsofios, North and Vo ("A Technique for Drawing Directed Graphs").
+///
+/// For every edge `e = from -> to`, the ranks satisfy `rank[to] - rank[from] >= edge_minlen[e]`,
+/// and the total weighted edge length, the sum of `edge_weight[e] * (rank[to] - rank[from])`,
+/// is minimized. `edge_minlen` and `edge_weight` are parallel to the edges of `graph` (in the
+/// order of `Graph::iter_edges_flattened`). A `minlen` of zero is allowed.
+///
+/// Each connected component is ranked independently, and its smallest rank is 0. Vertices that
+/// have the same in-weight and out-weight are then moved to the least-crowded feasible rank,
+/// which does not change the total edge length but makes the result less wide.
+///
+/// Returns `Error::FoundCycle` if the graph contains a cycle.
+pub fn network_simplex_ranks(
+    graph: &Graph,
+    edge_minlen: &[u32],
+    edge_weight: &[u32],
+) -> Result<Vec<u32>, Error> {
+    assert_eq!(edge_minlen.len(), graph.num_edges());
+    assert_eq!(edge_weight.len(), graph.num_edges());
+
+    let order = crate::topo_sort::topo_sort(graph)?;
+
+    let mut ns = NetworkSimplex::new(graph, edge_minlen, edge_weight);
+    ns.init_rank(&order);
+    ns.feasible_tree();
+    ns.compute_ranges();
+    ns.compute_cut_values();
+
+    let mut iterations = 0;
+    while let Some(leave) = ns.leave_edge() {
+        if iterations == MAX_ITERATIONS {
+            warn!(
+                "network simplex: stopped after {} iterations; the ranking may not be optimal",
+                MAX_ITERATIONS
+            );
+            break;
+        }
+        let enter = match ns.enter_edge(leave) {
+            Some(enter) => enter,
+            None => break,
+        };
+        ns.exchange(leave, enter);
+        iterations += 1;
+    }
+    debug!("network simplex: {} iterations", iterations);
+
+    ns.normalize();
+    ns.balance();
+
+    Ok(ns.rank.iter().map(|&r| r as u32).collect())
+}
+
+struct NetworkSimplex<'a> {
+    graph: &'a Graph,
+    minlen: &'a [u32],
+    weight: &'a [u32],
+
+    /// For each edge, its 'from' vertex. (The 'to' vertex is `graph.edges.values[e]`.)
+    tail: Vec<V>,
+
+    /// For each vertex, all of the edges that are incident on it, in either direction.
+    incident: RampTable<E>,
+
+    rank: Vec<i64>,
+
+    tree_edge: Vec<bool>,
+
+    /// The tree edges, in the order that `leave_edge` searches them, and for each tree edge, its
+    /// index in `tree_edges`.
+    tree_edges: Vec<E>,
+    tree_index: Vec<u32>,
+
+    /// One vertex from each connected component. These are the roots of the spanning trees.
+    roots: Vec<V>,
+
+    /// For each vertex, the tree edge that connects it to its parent, or `NO_EDGE` for roots.
+    par: Vec<E>,
+
+    /// `low` and `lim` number the vertices of the spanning forest in postorder. `lim[v]` is the
+    /// postorder number of `v` and `low[v]` is the smallest postorder number in the subtree
+    /// rooted at `v`. So `w` is in the subtree of `v` iff `low[v] <= lim[w] <= lim[v]`.
+    low: Vec<u32>,
+    lim: Vec<u32>,
+
+    /// The vertices, indexed by `lim`. The subtree of `v` is `postorder[low[v]..=lim[v]]`.
+    postorder: Vec<V>,
+
+    /// The stack of `dfs_range`, kept to avoid allocating it for every pivot.
+    dfs_stack: Vec<(V, usize)>,
+
+    cut_value: Vec<i64>,
+
+    /// Where the next search for a leaving edge begins.
+    search_start: usize,
+}
+
+impl<'a> NetworkSimplex<'a> {
+    fn new(graph: &'a Graph, minlen: &'a [u32], weight: &'a [u32]) -> Self {
+        let nv = graph.num_verts();
+        let ne = graph.num_edges();
+
+        let mut tail: Vec<V> = Vec::with_capacity(ne);
+        let mut incident_builder: RampTableBuilder<E> = RampTableBuilder::with_capacity(ne * 2);
+        for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+            tail.push(from);
+            incident_builder.push(from, e as E);
+            incident_builder.push(to, e as E);
+        }
+        let incident = incident_builder.finish_with_len(nv);
+
+        Self {
+            graph,
+            minlen,
+            weight,
+            tail,
+            incident,
+            rank: vec![0; nv],
+            tree_edge: vec![false; ne],
+            tree_edges: Vec::with_capacity(nv),
+            tree_index: vec![0; ne],
+            roots: Vec::new(),
+            par: vec![NO_EDGE; nv],
+            low: vec![0; nv],
+            lim: vec![0; nv],
+            postorder: vec![0; nv],
+            dfs_stack: Vec::new(),
+            cut_value: vec![0; ne],
+            search_start: 0,
+        }
+    }
+
+    fn head(&self, e: E) -> V {
+        self.graph.edges.values[e as usize]
+    }
+
+    fn slack(&self, e: E) -> i64 {
+        self.rank[self.head(e) as usize]
+            - self.rank[self.tail[e as usize] as usize]
+            - self.minlen[e as usize] as i64
+    }
+
+    /// Assigns initial ranks using the longest path from the sources. `order` must be a
+    /// topological order (sources to sinks).
+    fn init_rank(&mut self, order: &[V]) {
+        for &v in order.iter() {
+            let from_rank = self.rank[v as usize];
+            let range = self.graph.edges.entry_values_range(v as usize);
+            for e in range {
+                let to = self.graph.edges.values[e] as usize;
+                let r = from_rank + self.minlen[e] as i64;
+                if self.rank[to] < r {
+                    self.rank[to] = r;
+                }
+            }
+        }
+    }
+
+    /// Finds a spanning tree of tight edges for each connected component, adjusting ranks as
+    /// necessary so that such a tree exists.
+    fn feasible_tree(&mut self) {
+        let nv = self.graph.num_verts();
+        let mut in_tree: Vec<bool> = vec![false; nv];
+        let mut tree_verts: Vec<V> = Vec::new();
+        let mut stack: Vec<V> = Vec::new();
+
+        for root in 0..nv as V {
+            if in_tree[root as usize] {
+                continue;
+            }
+            self.roots.push(root);
+            in_tree[root as usize] = true;
+            tree_verts.clear();
+            tree_verts.push(root);
+            stack.push(root);
+
+            loop {
+                // Grow the tree along tight edges.
+                while let Some(v) = stack.pop() {
+                    for &e in self.incident.entry_values(v as usize).iter() {
+                        let other = self.other_end(e, v);
+                        if !in_tree[other as usize] && self.slack(e) == 0 {
+                            in_tree[other as usize] = true;
+                            self.tree_edge[e as usize] = true;
+                            tree_verts.push(other);
+                            stack.push(other);
+                        }
+                    }
+                }
+
+                // Find the non-tree edge incident on the tree that has the minimum slack.
+                let mut best: Option<(i64, E)> = None;
+                for &v in tree_verts.iter() {
+                    for &e in self.incident.entry_values(v as usize).iter() {
+                        if in_tree[self.other_end(e, v) as usize] {
+                            continue;
+                        }
+                        let slack = self.slack(e);
+                        if best.map(|(s, _)| slack < s).unwrap_or(true) {
+                            best = Some((slack, e));
+                        }
+                    }
+                }
+                let (slack, e) = match best {
+                    Some(best) => best,
+                    None => break, // The component is complete.
+                };
+
+                // Move the whole tree so that e becomes tight.
+                let delta = if in_tree[self.head(e) as usize] {
+                    -slack
+                } else {
+                    slack
+                };
+                for &v in tree_verts.iter() {
+                    self.rank[v as usize] += delta;
+                }
+                stack.extend(tree_verts.iter().copied());
+            }
+        }
+    }
+
+    fn other_end(&self, e: E, v: V) -> V {
+        let tail = self.tail[e as usize];
+        if tail == v {
+            self.head(e)
+        } else {
+            tail
+        }
+    }
+
+    /// Computes `par`, `low`, `lim` and `postorder` for the spanning forest, and the list of
+    /// tree edges.
+    fn compute_ranges(&mut self) {
+        self.tree_edges.clear();
+        for e in 0..self.tree_edge.len() {
+            if self.tree_edge[e] {
+                self.tree_index[e] = self.tree_edges.len() as u32;
+                self.tree_edges.push(e as E);
+            }
+        }
+
+        let mut next_lim: u32 = 0;
+        for i in 0..self.roots.len() {
+            let root = self.roots[i];
+            self.par[root as usize] = NO_EDGE;
+            next_lim = self.dfs_range(root, next_lim);
+        }
+    }
+
+    /// Numbers the subtree rooted at `root` in postorder, starting at `first_lim`, and sets
+    /// `par` for the vertices below `root`. Returns the next unused number.
+    fn dfs_range(&mut self, root: V, first_lim: u32) -> u32 {
+        let mut next_lim = first_lim;
+        let mut stack = std::mem::take(&mut self.dfs_stack);
+        self.low[root as usize] = next_lim;
+        stack.push((root, 0));
+        while let Some(top) = stack.last_mut() {
+            let (v, i) = *top;
+            let incident = self.incident.entry_values(v as usize);
+            if let Some(&e) = incident.get(i) {
+                top.1 += 1;
+                if self.tree_edge[e as usize] && e != self.par[v as usize] {
+                    let child = self.other_end(e, v);
+                    self.par[child as usize] = e;
+                    self.low[child as usize] = next_lim;
+                    stack.push((child, 0));
+                }
+            } else {
+                self.lim[v as usize] = next_lim;
+                self.postorder[next_lim as usize] = v;
+                next_lim += 1;
+                stack.pop();
+            }
+        }
+        self.dfs_stack = stack;
+        next_lim
+    }
+
+    fn in_subtree(&self, w: V, v: V) -> bool {
+        let lim_w = self.lim[w as usize];
+        self.low[v as usize] <= lim_w && lim_w <= self.lim[v as usize]
+    }
+
+    /// Computes the cut value of every tree edge. The cut value of a tree edge is the total
+    /// weight of the edges that go from the tail component to the head component (when the
+    /// tree edge is removed), minus the total weight of the edges that go the other way.
+    fn compute_cut_values(&mut self) {
+        for i in 0..self.postorder.len() {
+            let v = self.postorder[i];
+            let f = self.par[v as usize];
+            if f != NO_EDGE {
+                self.cut_value[f as usize] = self.x_cut_value(f, v);
+            }
+        }
+    }
+
+    /// Computes the cut value of tree edge `f`, where `v` is the endpoint of `f` that is the
+    /// child. The cut values of all tree edges below `v` must already be known.
+    fn x_cut_value(&self, f: E, v: V) -> i64 {
+        let dir_is_tail = self.tail[f as usize] == v;
+        let mut sum: i64 = 0;
+        for &e in self.incident.entry_values(v as usize).iter() {
+            let other = self.other_end(e, v);
+            let weight = self.weight[e as usize] as i64;
+            let (outside, mut value) = if !self.in_subtree(other, v) {
+                (true, weight)
+            } else {
+                let tree_value = if self.tree_edge[e as usize] {
+                    self.cut_value[e as usize]
+                } else {
+                    0
+                };
+                (false, tree_value - weight)
+            };
+            let mut d_positive = if dir_is_tail {
+                self.head(e) == v
+            } else {
+                self.tail[e as usize] == v
+            };
+            if outside {
+                d_positive = !d_positive;
+            }
+            if !d_positive {
+                value = -value;
+            }
+            sum += value;
+        }
+        sum
+    }
+
+    /// Finds a tree edge with a negative cut value. As in dot, the tree edges are searched
+    /// cyclically, starting where the last search stopped, and the most negative of the first
+    /// `SEARCH_SIZE` candidates is chosen.
+    fn leave_edge(&mut self) -> Option<E> {
+        let n = self.tree_edges.len();
+        let mut best: Option<E> = None;
+        let mut found = 0;
+        for i in 0..n {
+            let index = (self.search_start + i) % n;
+            let e = self.tree_edges[index];
+            let cut_value = self.cut_value[e as usize];
+            if cut_value < 0 {
+                if best
+                    .map(|b| cut_value < self.cut_value[b as usize])
+                    .unwrap_or(true)
+                {
+                    best = Some(e);
+                }
+                found += 1;
+                if found == SEARCH_SIZE {
+                    self.search_start = index;
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    /// Finds the non-tree edge with the least slack that can replace tree edge `leave`. It must
+    /// cross from the head component of `leave` to the tail component. Only the edges of the
+    /// subtree that is cut off by `leave` are searched, and the search stops at a tight edge.
+    fn enter_edge(&self, leave: E) -> Option<E> {
+        let tail = self.tail[leave as usize];
+        let head = self.head(leave);
+        // 'v' is the root of the subtree that is cut off by removing 'leave'.
+        let (v, subtree_is_tail) = if self.lim[tail as usize] < self.lim[head as usize] {
+            (tail, true)
+        } else {
+            (head, false)
+        };
+
+        let mut best: Option<(i64, E)> = None;
+        let subtree = self.low[v as usize] as usize..=self.lim[v as usize] as usize;
+        for &w in self.postorder[subtree].iter() {
+            for &e in self.incident.entry_values(w as usize).iter() {
+                if self.tree_edge[e as usize] {
+                    continue;
+                }
+                // The end of 'e' that is inside the subtree must be 'w'.
+                let (inside, outside) = if subtree_is_tail {
+                    (self.head(e), self.tail[e as usize])
+                } else {
+                    (self.tail[e as usize], self.head(e))
+                };
+                if inside != w || self.in_subtree(outside, v) {
+                    continue;
+                }
+                let slack = self.slack(e);
+                if best.map(|(s, _)| slack < s).unwrap_or(true) {
+                    best = Some((slack, e));
+                    if slack == 0 {
+                        return Some(e);
+                    }
+                }
+            }
+        }
+        best.map(|(_, e)| e)
+    }
+
+    /// Replaces tree edge `leave` with `enter`. As in dot, only the ranks of the subtree that
+    /// is cut off by `leave` change, only the cut values on the tree path between the ends of
+    /// `enter` change, and only the vertices below the lowest common ancestor of the ends of
+    /// `enter` are renumbered.
+    fn exchange(&mut self, leave: E, enter: E) {
+        // Move the subtree so that 'enter' becomes tight.
+        let delta = self.slack(enter);
+        if delta != 0 {
+            let tail = self.tail[leave as usize];
+            let head = self.head(leave);
+            let (v, delta) = if self.lim[tail as usize] < self.lim[head as usize] {
+                (tail, delta)
+            } else {
+                (head, -delta)
+            };
+            for i in self.low[v as usize]..=self.lim[v as usize] {
+                self.rank[self.postorder[i as usize] as usize] -= delta;
+            }
+        }
+
+        let cut_value = self.cut_value[leave as usize];
+        let enter_tail = self.tail[enter as usize];
+        let enter_head = self.head(enter);
+        let lca = self.tree_update(enter_tail, enter_head, cut_value, true);
+        let lca_2 = self.tree_update(enter_head, enter_tail, cut_value, false);
+        debug_assert_eq!(lca, lca_2);
+        self.cut_value[enter as usize] = -cut_value;
+        self.cut_value[leave as usize] = 0;
+
+        self.tree_edge[leave as usize] = false;
+        self.tree_edge[enter as usize] = true;
+        let index = self.tree_index[leave as usize];
+        self.tree_edges[index as usize] = enter;
+        self.tree_index[enter as usize] = index;
+
+        self.dfs_range(lca, self.low[lca as usize]);
+    }
+
+    /// Walks up the tree from `v` until reaching an ancestor of `w`, which it returns, and adds
+    /// `cut_value` to the cut values of the tree edges on the way that point up (if `up`) or
+    /// down, and subtracts it from the others. This is `treeupdate` in dot.
+    fn tree_update(&mut self, mut v: V, w: V, cut_value: i64, up: bool) -> V {
+        while !self.in_subtree(w, v) {
+            let e = self.par[v as usize];
+            let tail = self.tail[e as usize];
+            let head = self.head(e);
+            if (tail == v) == up {
+                self.cut_value[e as usize] += cut_value;
+            } else {
+                self.cut_value[e as usize] -= cut_value;
+            }
+            v = if self.lim[tail as usize] > self.lim[head as usize] {
+                tail
+            } else {
+                head
+            };
+        }
+        v
+    }
+
+    /// Shifts the ranks of each connected component so that its smallest rank is 0.
+    fn normalize(&mut self) {
+        // postorder visits each tree contiguously, ending with its root.
+        let mut start = 0;
+        for i in 0..self.postorder.len() {
+            if self.par[self.postorder[i] as usize] == NO_EDGE {
+                let tree = &self.postorder[start..=i];
+                let min_rank = tree.iter().map(|&v| self.rank[v as usize]).min().unwrap();
+                for &v in tree.iter() {
+                    self.rank[v as usize] -= min_rank;
+                }
+                start = i + 1;
+            }
+        }
+    }
+
+    /// Moves vertices whose in-weight equals their out-weight to a feasible rank that has fewer
+    /// vertices. This is the "TB balance" step of dot.
+    fn balance(&mut self) {
+        let nv = self.graph.num_verts();
+        if nv == 0 {
+            return;
+        }
+        let max_rank = *self.rank.iter().max().unwrap();
+        let mut rank_count: Vec<u32> = vec![0; max_rank as usize + 1];
+        for &r in self.rank.iter() {
+            rank_count[r as usize] += 1;
+        }
+
+        for v in 0..nv {
+            let mut in_weight: i64 = 0;
+            let mut out_weight: i64 = 0;
+            let mut low: i64 = 0;
+            let mut high: i64 = max_rank;
+            for &e in self.incident.entry_values(v).iter() {
+                let minlen = self.minlen[e as usize] as i64;
+                let weight = self.weight[e as usize] as i64;
+                if self.tail[e as usize] as usize == v {
+                    out_weight += weight;
+                    high = high.min(self.rank[self.head(e) as usize] - minlen);
+                } else {
+                    in_weight += weight;
+                    low = low.max(self.rank[self.tail[e as usize] as usize] + minlen);
+                }
+            }
+            if in_weight != out_weight || low > high {
+                continue;
+            }
+            let current = self.rank[v];
+            let mut best = current;
+            for r in low..=high {
+                if rank_count[r as usize] < rank_count[best as usize] {
+                    best = r;
+                }
+            }
+            if best != current {
+                rank_count[current as usize] -= 1;
+                rank_count[best as usize] += 1;
+                self.rank[v] = best;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    fn total_length(graph: &Graph, rank: &[u32], weight: &[u32]) -> u64 {
+        graph
+            .iter_edges_flattened()
+            .enumerate()
+            .map(|(e, (from, to))| {
+                weight[e] as u64 * (rank[to as usize] - rank[from as usize]) as u64
+            })
+            .sum()
+    }
+
+    fn check(description: &str, graph: &Graph, minlen: &[u32], weight: &[u32]) -> Vec<u32> {
+        let rank = network_simplex_ranks(graph, minlen, weight).unwrap();
+        println!("--- {}\nrank: {:?}", description, rank);
+        for (e, (from, to)) in graph.iter_edges_flattened().enumerate() {
+            assert!(
+                rank[to as usize] >= rank[from as usize] + minlen[e],
+                "{}: edge {} violates minlen",
+                description,
+                e
+            );
+        }
+        rank
+    }
+
+    #[test]
+    fn network_simplex_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph, expected_length: u64) {
+            let ne = graph.num_edges();
+            let minlen = vec![1; ne];
+            let weight = vec![1; ne];
+            let rank = check(description, graph, &minlen, &weight);
+            assert_eq!(
+                total_length(graph, &rank, &weight),
+                expected_length,
+                "{}",
+                description
+            );
+        }
+
+        case("empty", &Graph::new(), 0);
+        case("linear path", &graph_from_paths(&[&[0, 1, 2, 3]]), 3);
+
+        // Longest-path layering puts 10 in the top layer, which makes 10 -> 3 span three
+        // layers. Network simplex pulls 10 down next to its target.
+        case(
+            "short branch",
+            &graph_from_paths(&[&[0, 1, 2, 3], &[10, 3]]),
+            4,
+        );
+
+        case(
+            "diamond with a long side",
+            &graph_from_paths(&[&[0, 1, 2, 3, 4], &[0, 5, 4]]),
+            8,
+        );
+
+        case(
+            "two components",
+            &graph_from_paths(&[&[0, 1, 2], &[10, 11], &[12, 11]]),
+            4,
+        );
+    }
+
+    #[test]
+    fn network_simplex_minlen_weight_test() {
+        init_test();
+
+        // 0 -> 1 -> 2 and 0 -> 2, where 0 -> 2 must span at least 3 ranks.
+        let graph = graph_from_paths(&[&[0, 1, 2], &[0, 2]]);
+        let minlen = [1, 3, 1];
+        let rank = check("minlen", &graph, &minlen, &[1, 1, 1]);
+        assert_eq!(rank[2] - rank[0], 3);
+
+        // A heavy edge should be kept short, at the expense of light edges.
+        // 0 -> 1 (weight 10), 2 -> 1, 2 -> 3 -> 4 -> 5, 0 -> 5
+        let graph = graph_from_paths(&[&[0, 1], &[2, 1], &[2, 3, 4, 5], &[0, 5]]);
+        let mut weight = vec![1; graph.num_edges()];
+        let heavy = graph
+            .iter_edges_flattened()
+            .position(|edge| edge == (0, 1))
+            .unwrap();
+        weight[heavy] = 10;
+        let rank = check("weight", &graph, &vec![1; graph.num_edges()], &weight);
+        assert_eq!(rank[1] - rank[0], 1);
+
+        // minlen of 0 allows an edge to be flat.
+        let graph = graph_from_paths(&[&[0, 1], &[0, 2], &[1, 2]]);
+        let rank = check("flat", &graph, &[1, 1, 0], &[1, 1, 1]);
+        assert_eq!(rank[1], rank[2]);
+    }
+
+    #[test]
+    fn exchange_test() {
+        init_test();
+
+        // A pseudo-random DAG that needs many pivots. After each pivot, the cut values and
+        // ranges that `exchange` updated must match those computed from scratch.
+        let nv = 60;
+        let mut seed: u32 = 1;
+        let mut random = |n: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) % n
+        };
+        let mut b = graph_builder();
+        for _ in 0..90 {
+            let (u, v) = (random(nv), random(nv));
+            if u != v {
+                b.edge(u.min(v), u.max(v));
+            }
+        }
+        let graph = b.build();
+        let ne = graph.num_edges();
+        let minlen: Vec<u32> = (0..ne).map(|_| random(3)).collect();
+        let weight: Vec<u32> = (0..ne).map(|_| 1 + random(4)).collect();
+
+        let order = crate::topo_sort::topo_sort(&graph).unwrap();
+        let mut ns = NetworkSimplex::new(&graph, &minlen, &weight);
+        ns.init_rank(&order);
+        ns.feasible_tree();
+        ns.compute_ranges();
+        ns.compute_cut_values();
+        let mut pivots = 0;
+        while let Some(leave) = ns.leave_edge() {
+            let enter = ns.enter_edge(leave).unwrap();
+            ns.exchange(leave, enter);
+            pivots += 1;
+
+            for e in 0..ne {
+                assert!(ns.slack(e as E) >= 0);
+                assert!(!ns.tree_edge[e] || ns.slack(e as E) == 0);
+            }
+            let (cut_value, low, lim) = (ns.cut_value.clone(), ns.low.clone(), ns.lim.clone());
+            ns.compute_ranges();
+            ns.compute_cut_values();
+            assert_eq!(low, ns.low);
+            assert_eq!(lim, ns.lim);
+            for &e in ns.tree_edges.iter() {
+                let e = e as usize;
+                assert_eq!(cut_value[e], ns.cut_value[e], "cut value of edge {}", e);
+            }
+        }
+        println!("{} pivots", pivots);
+        assert!(pivots > 5);
+    }
+
+    #[test]
+    fn network_simplex_cycle_test() {
+        let graph = graph_from_paths(&[&[0, 1, 0]]);
+        assert!(matches!(
+            network_simplex_ranks(&graph, &[1, 1], &[1, 1]),
+            Err(Error::FoundCycle(_))
+        ));
+    }
+}
@@ -0,0 +1,1217 @@
+// Vertex ordering: the crossing-reduction phase of layered graph drawing.
+//
+// This follows the approach of dot (Gansner, Koutsofios, North and Vo, "A Technique for
+// Drawing Directed Graphs"): an initial order is found by depth-first search, then the layers
+// are repeatedly swept, alternating downward and upward. Each sweep sorts a layer by the
+// weighted median of the positions of its neighbors in the previous layer, and then the
+// "transpose" step swaps adjacent vertices while doing so reduces the number of crossings.
+//
+// If the vertices are grouped into clusters, then every layer is sorted hierarchically: the
+// clusters within a cluster (or within the layer) are sorted as units, by the mean of the sort
+// keys of their vertices. This keeps each cluster contiguous, and transpose only swaps vertices
+// that are in the same innermost cluster. Sibling clusters must also be in the same order on
+// every layer, or their boxes would overlap. Before each iteration, the clusters are ranked by
+// their mean position, and every layer is sorted so that sibling clusters follow that ranking.
+//
+// Flat edges (edges within a layer) are drawn from left to right, as in dot. Whenever a layer
+// is reordered, it is then adjusted so that the `from` vertex of each flat edge is to the left of
+// its `to` vertex, and next to it if possible. Transpose never swaps the ends of a flat edge.
+// Flat edges are not counted as crossings. `OrderConstraint`s are enforced in the same way: a
+// `LeftOf` constraint is like a flat edge whose ends need not be next to each other, and pinned
+// vertices are moved to their positions after everything else has been placed.
+//
+// Edges may attach to ports, which are offsets from the centers of their vertices. Medians and
+// transpose use the positions of the ports, so that edges that share a vertex can be kept from
+// crossing, and those crossings are counted along with the others.
+
+use crate::crossings::{count_crossings, count_port_crossings};
+use crate::layering::ProperGraph;
+use crate::ramp_table::{RampTable, RampTableBuilder};
+use crate::V;
+use log::debug;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Stop iterating if this many iterations in a row did not reduce the number of crossings.
+const MIN_QUIT: usize = 8;
+
+#[derive(Clone, Debug)]
+pub struct OrderingOptions {
+    /// The maximum number of iterations. Each iteration is a single sweep over all of the layers;
+    /// even iterations sweep downward and odd iterations sweep upward.
+    pub max_iterations: usize,
+
+    /// If true, then the transpose heuristic is run after every sweep.
+    pub transpose: bool,
+
+    /// Constraints on the order of vertices within their layers, which are respected by every
+    /// step of ordering.
+    pub constraints: Vec<OrderConstraint>,
+
+    /// For each proper edge (in the same order as `ProperGraph::edges.all_values()`), where the
+    /// edge attaches to its `from` vertex and to its `to` vertex, as an offset from the center
+    /// of the vertex in units of positions (so between -0.5 and 0.5). These come from ports.
+    /// If this is empty, then all edges attach to the centers of their vertices.
+    pub port_offsets: Vec<(f64, f64)>,
+}
+
+impl Default for OrderingOptions {
+    fn default() -> Self {
+        Self {
+            max_iterations: 24,
+            transpose: true,
+            constraints: Vec::new(),
+            port_offsets: Vec::new(),
+        }
+    }
+}
+
+/// A constraint on the order of the vertices within a layer.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum OrderConstraint {
+    /// The first vertex is somewhere to the left of the second. A constraint between vertices
+    /// in different layers is ignored, since layering chooses the layers and callers cannot
+    /// know them in advance. If the `LeftOf` constraints of a layer form a cycle, then some of
+    /// them are ignored. A constraint between vertices in different clusters is ignored, since
+    /// it could split a cluster.
+    LeftOf(V, V),
+
+    /// The vertex is at the given position in its layer, or at the end of the layer if the
+    /// layer has fewer vertices. Vertices that are pinned to the same position are placed next
+    /// to each other, starting at that position. Pins take priority over `LeftOf` constraints
+    /// and flat edges, and are ignored for vertices that are in a cluster.
+    Pin(V, u32),
+}
+
+/// Which end of its cluster a border vertex is kept at.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ClusterBorder {
+    Left,
+    Right,
+}
+
+/// Describes how the vertices of a proper graph are grouped into nested clusters. Ordering keeps
+/// the vertices of each cluster contiguous within every layer.
+#[derive(Clone, Debug, Default)]
+pub struct OrderingClusters {
+    /// For each cluster, its parent cluster, or `None` for top-level clusters.
+    pub parent: Vec<Option<u32>>,
+
+    /// For each vertex (real and virtual), the innermost cluster that contains it, or `None`.
+    pub v_cluster: Vec<Option<u32>>,
+
+    /// For each vertex, `Some` if it is a border vertex of its cluster (`v_cluster`). Border
+    /// vertices are always kept at the ends of their cluster. This may be empty if there are no
+    /// border vertices.
+    pub v_border: Vec<Option<ClusterBorder>>,
+}
+
+impl OrderingClusters {
+    pub fn is_empty(&self) -> bool {
+        self.parent.is_empty()
+    }
+
+    pub fn cluster_of(&self, v: V) -> Option<u32> {
+        self.v_cluster.get(v as usize).copied().flatten()
+    }
+
+    pub fn border_of(&self, v: V) -> Option<ClusterBorder> {
+        self.v_border.get(v as usize).copied().flatten()
+    }
+
+    /// Returns `c` and the clusters that contain it, outermost first.
+    pub fn path(&self, c: Option<u32>) -> Vec<u32> {
+        let mut path = Vec::new();
+        let mut c = c;
+        while let Some(cc) = c {
+            path.push(cc);
+            c = self.parent[cc as usize];
+        }
+        path.reverse();
+        path
+    }
+
+    /// Returns the innermost cluster that contains both `a` and `b`, where `None` stands for the
+    /// whole graph.
+    pub fn common_ancestor(&self, a: Option<u32>, b: Option<u32>) -> Option<u32> {
+        let path_a = self.path(a);
+        let path_b = self.path(b);
+        path_a
+            .iter()
+            .zip(path_b.iter())
+            .take_while(|(x, y)| x == y)
+            .last()
+            .map(|(&x, _)| x)
+    }
+}
+
+/// The neighbors of every vertex of a proper graph, in the layers above and below it.
+pub(crate) struct LayerAdjacency {
+    /// For each vertex, its neighbors in the layer above (the 'from' vertices of its edges).
+    pub up: RampTable<V>,
+
+    /// For each vertex, its neighbors in the layer below (the 'to' vertices of its edges).
+    pub down: RampTable<V>,
+
+    /// Parallel to `up`: the port offset of each edge at the neighbor's end.
+    pub up_port: RampTable<f64>,
+
+    /// Parallel to `down`: the port offset of each edge at the neighbor's end.
+    pub down_port: RampTable<f64>,
+}
+
+impl LayerAdjacency {
+    /// `port_offsets` is as in `OrderingOptions`, and may be empty.
+    pub fn new(pg: &ProperGraph, port_offsets: &[(f64, f64)]) -> Self {
+        let nv = pg.num_verts();
+        let num_edges = pg.edges.num_values();
+        assert!(port_offsets.is_empty() || port_offsets.len() == num_edges);
+        let mut up: RampTableBuilder<V> = RampTableBuilder::with_capacity(num_edges);
+        let mut down: RampTableBuilder<V> = RampTableBuilder::with_capacity(num_edges);
+        let mut up_port: RampTableBuilder<f64> = RampTableBuilder::with_capacity(num_edges);
+        let mut down_port: RampTableBuilder<f64> = RampTableBuilder::with_capacity(num_edges);
+        for (i, &(from, to)) in pg.edges.all_values().iter().enumerate() {
+            let (from_port, to_port) = port_offsets.get(i).copied().unwrap_or((0.0, 0.0));
+            up.push(to, from);
+            up_port.push(to, from_port);
+            down.push(from, to);
+            down_port.push(from, to_port);
+        }
+        Self {
+            up: up.finish_with_len(nv),
+            down: down.finish_with_len(nv),
+            up_port: up_port.finish_with_len(nv),
+            down_port: down_port.finish_with_len(nv),
+        }
+    }
+
+    /// Finds the positions of the neighbors of `v` in the layer above (if `upward`) or below,
+    /// adjusted by the port offsets of the edges, in increasing order.
+    fn neighbor_positions(&self, pg: &ProperGraph, v: V, upward: bool, positions: &mut Vec<f64>) {
+        let (neighbors, ports) = if upward {
+            (&self.up, &self.up_port)
+        } else {
+            (&self.down, &self.down_port)
+        };
+        positions.clear();
+        positions.extend(
+            neighbors
+                .entry_values(v as usize)
+                .iter()
+                .zip(ports.entry_values(v as usize).iter())
+                .map(|(&w, &port)| pg.v_pos[w as usize] as f64 + port),
+        );
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    }
+}
+
+/// The flat edges and `OrderConstraint`s of a proper graph, indexed by vertex.
+struct LayerConstraints {
+    /// For each vertex, the 'to' vertices of its flat edges.
+    flat: RampTable<V>,
+
+    /// For each vertex, the vertices that it must be to the left of.
+    left_of: RampTable<V>,
+
+    /// For each vertex, the position that it is pinned to, if any.
+    pinned: Vec<Option<u32>>,
+
+    /// For each layer, true if it has any flat edges or constraints.
+    is_constrained: Vec<bool>,
+}
+
+impl LayerConstraints {
+    fn new(pg: &ProperGraph, clusters: &OrderingClusters, constraints: &[OrderConstraint]) -> Self {
+        let nv = pg.num_verts();
+        let mut is_constrained: Vec<bool> = vec![false; pg.num_layers()];
+        let mut flat: RampTableBuilder<V> =
+            RampTableBuilder::with_capacity(pg.flat_edges.num_values());
+        for &(from, to) in pg.flat_edges.all_values().iter() {
+            flat.push(from, to);
+            is_constrained[pg.v_layer[from as usize] as usize] = true;
+        }
+        let mut left_of: RampTableBuilder<V> = RampTableBuilder::new();
+        let mut pinned: Vec<Option<u32>> = vec![None; nv];
+        for &constraint in constraints.iter() {
+            match constraint {
+                OrderConstraint::LeftOf(left, right) => {
+                    assert!((left as usize) < nv && (right as usize) < nv);
+                    let layer = pg.v_layer[left as usize];
+                    if layer != pg.v_layer[right as usize] {
+                        debug!(
+                            "ignoring LeftOf({}, {}): the vertices are in different layers",
+                            left, right
+                        );
+                        continue;
+                    }
+                    left_of.push(left, right);
+                    is_constrained[layer as usize] = true;
+                }
+                OrderConstraint::Pin(v, pos) => {
+                    assert!((v as usize) < nv);
+                    if clusters.cluster_of(v).is_none() {
+                        pinned[v as usize] = Some(pos);
+                        is_constrained[pg.v_layer[v as usize] as usize] = true;
+                    }
+                }
+            }
+        }
+        Self {
+            flat: flat.finish_with_len(nv),
+            left_of: left_of.finish_with_len(nv),
+            pinned,
+            is_constrained,
+        }
+    }
+
+    /// Returns true if `v` may not be moved to the right of `w`.
+    fn must_be_left_of(&self, v: V, w: V) -> bool {
+        self.flat.entry_values(v as usize).contains(&w)
+            || self.left_of.entry_values(v as usize).contains(&w)
+    }
+}
+
+/// Reorders the vertices within each layer of `pg` to reduce the number of edge crossings.
+/// Returns the number of crossings in the final order, including the crossings between edges
+/// whose ports are out of order (see `count_port_crossings`).
+///
+/// The result is deterministic: vertices whose median positions are equal keep their relative
+/// order, and vertices that have no neighbors in the layer being compared to stay where they are.
+pub fn minimize_crossings(pg: &mut ProperGraph, options: &OrderingOptions) -> u64 {
+    minimize_crossings_clustered(pg, &OrderingClusters::default(), options)
+}
+
+/// Like `minimize_crossings`, but keeps the vertices of each cluster contiguous within every
+/// layer, with the cluster's border vertices (if any) at its ends.
+pub fn minimize_crossings_clustered(
+    pg: &mut ProperGraph,
+    clusters: &OrderingClusters,
+    options: &OrderingOptions,
+) -> u64 {
+    let adj = LayerAdjacency::new(pg, &options.port_offsets);
+    let cons = LayerConstraints::new(pg, clusters, &options.constraints);
+
+    init_order(pg, &adj, &cons, clusters);
+    let mut cluster_ranks: Vec<u32> = Vec::new();
+    if !clusters.is_empty() {
+        cluster_ranks = rank_clusters(pg, clusters);
+        sort_layers_by_position(pg, &cons, clusters, &cluster_ranks);
+    }
+
+    let crossings_of =
+        |pg: &ProperGraph| count_crossings(pg) + count_port_crossings(pg, &options.port_offsets);
+    let mut best_crossings = crossings_of(pg);
+    let mut best_order: Vec<V> = pg.verts.values.clone();
+    debug!("initial order: {} crossings", best_crossings);
+
+    let mut iterations_without_improvement = 0;
+    for iteration in 0..options.max_iterations {
+        if best_crossings == 0 || iterations_without_improvement == MIN_QUIT {
+            break;
+        }
+
+        // As in dot, half of the iterations break ties in the opposite direction, which lets
+        // the search escape from some local minima.
+        let downward = iteration % 2 == 0;
+        let reverse = iteration % 4 < 2;
+        if !clusters.is_empty() && iteration > 0 {
+            cluster_ranks = rank_clusters(pg, clusters);
+            sort_layers_by_position(pg, &cons, clusters, &cluster_ranks);
+        }
+        median_sweep(pg, &adj, &cons, clusters, &cluster_ranks, downward, reverse);
+        if options.transpose {
+            transpose(pg, &adj, &cons, clusters, reverse);
+        }
+
+        let crossings = crossings_of(pg);
+        debug!("iteration {}: {} crossings", iteration, crossings);
+        if crossings < best_crossings {
+            best_crossings = crossings;
+            best_order.copy_from_slice(&pg.verts.values);
+            iterations_without_improvement = 0;
+        } else {
+            iterations_without_improvement += 1;
+        }
+    }
+
+    restore_order(pg, &best_order);
+    best_crossings
+}
+
+/// Sets the order of every layer from a copy of `pg.verts.values`.
+fn restore_order(pg: &mut ProperGraph, order: &[V]) {
+    pg.verts.values.copy_from_slice(order);
+    for layer_verts in pg.verts.iter() {
+        for (pos, &v) in layer_verts.iter().enumerate() {
+            pg.v_pos[v as usize] = pos as u32;
+        }
+    }
+}
+
+/// Computes an initial order using depth-first search, starting with the vertices in the top
+/// layer. Each vertex is appended to its layer when it is first visited, which tends to keep
+/// the vertices of a subtree together.
+fn init_order(
+    pg: &mut ProperGraph,
+    adj: &LayerAdjacency,
+    cons: &LayerConstraints,
+    clusters: &OrderingClusters,
+) {
+    let nv = pg.num_verts();
+    let num_layers = pg.num_layers();
+    let mut visited: Vec<bool> = vec![false; nv];
+    let mut new_order: Vec<Vec<V>> = vec![Vec::new(); num_layers];
+    let mut stack: Vec<V> = Vec::new();
+
+    for layer in (0..num_layers).rev() {
+        for &start in pg.verts.entry_values(layer).iter() {
+            if visited[start as usize] {
+                continue;
+            }
+            visited[start as usize] = true;
+            stack.push(start);
+            while let Some(v) = stack.pop() {
+                new_order[pg.v_layer[v as usize] as usize].push(v);
+                // Push in reverse, so that the first neighbor is visited first.
+                for &to in adj.down.entry_values(v as usize).iter().rev() {
+                    if !visited[to as usize] {
+                        visited[to as usize] = true;
+                        stack.push(to);
+                    }
+                }
+            }
+        }
+    }
+
+    for (layer, order) in new_order.iter().enumerate() {
+        pg.set_layer_order(layer, order);
+        apply_constraints(pg, cons, clusters, layer);
+    }
+}
+
+/// Reorders `layer` so that it satisfies the flat edges and constraints. First, the vertices are
+/// placed in topological order of the flat edges and `LeftOf` constraints, choosing the
+/// leftmost vertex that is ready at each step, except that a vertex is placed immediately after
+/// the `from` vertex of its last flat edge. This keeps the ends of flat edges next to each other
+/// when possible, and otherwise keeps the current order. Then, the pinned vertices are moved to
+/// their positions.
+///
+/// Flat edges and constraints between vertices in different innermost clusters are ignored,
+/// since moving their ends together could split a cluster.
+fn apply_constraints(
+    pg: &mut ProperGraph,
+    cons: &LayerConstraints,
+    clusters: &OrderingClusters,
+    layer: usize,
+) {
+    if !cons.is_constrained[layer] {
+        return;
+    }
+    let layer_verts = pg.verts.entry_values(layer);
+    let same_cluster = |v: V, w: V| clusters.cluster_of(v) == clusters.cluster_of(w);
+    let mut in_degree: HashMap<V, u32> = HashMap::new();
+    for &v in layer_verts.iter() {
+        let successors = cons.flat.entry_values(v as usize).iter();
+        for &w in successors.chain(cons.left_of.entry_values(v as usize).iter()) {
+            if same_cluster(v, w) {
+                *in_degree.entry(w).or_default() += 1;
+            }
+        }
+    }
+
+    let v_pos = &pg.v_pos;
+    let mut ready: BinaryHeap<Reverse<(u32, V)>> = layer_verts
+        .iter()
+        .filter(|v| !in_degree.contains_key(v))
+        .map(|&v| Reverse((v_pos[v as usize], v)))
+        .collect();
+    let mut is_placed: HashMap<V, bool> = HashMap::new();
+    let mut new_order: Vec<V> = Vec::with_capacity(layer_verts.len());
+    let mut next: Option<V> = None;
+    while new_order.len() < layer_verts.len() {
+        let v = match next.take() {
+            Some(v) => v,
+            None => match ready.pop() {
+                Some(Reverse((_, v))) => v,
+                // The constraints form a cycle, so ignore the constraints of the leftmost
+                // vertex that has not been placed.
+                None => *layer_verts
+                    .iter()
+                    .find(|v| !is_placed.contains_key(v))
+                    .unwrap(),
+            },
+        };
+        if is_placed.insert(v, true).is_some() {
+            continue;
+        }
+        new_order.push(v);
+        for &w in cons.flat.entry_values(v as usize).iter() {
+            if !same_cluster(v, w) || is_placed.contains_key(&w) {
+                continue;
+            }
+            let degree = in_degree.get_mut(&w).unwrap();
+            *degree -= 1;
+            if *degree != 0 {
+                continue;
+            }
+            // The leftmost flat successor that becomes ready goes next; the others wait
+            // their turn.
+            match next {
+                Some(n) if v_pos[n as usize] < v_pos[w as usize] => {
+                    ready.push(Reverse((v_pos[w as usize], w)));
+                }
+                _ => {
+                    if let Some(n) = next.replace(w) {
+                        ready.push(Reverse((v_pos[n as usize], n)));
+                    }
+                }
+            }
+        }
+        for &w in cons.left_of.entry_values(v as usize).iter() {
+            if !same_cluster(v, w) || is_placed.contains_key(&w) {
+                continue;
+            }
+            let degree = in_degree.get_mut(&w).unwrap();
+            *degree -= 1;
+            if *degree == 0 {
+                ready.push(Reverse((v_pos[w as usize], w)));
+            }
+        }
+    }
+
+    // Move the pinned vertices to their positions, starting with the lowest position.
+    let mut pins: Vec<(u32, V)> = new_order
+        .iter()
+        .filter_map(|&v| cons.pinned[v as usize].map(|pos| (pos, v)))
+        .collect();
+    if !pins.is_empty() {
+        pins.sort_unstable();
+        new_order.retain(|&v| cons.pinned[v as usize].is_none());
+        for &(pos, v) in pins.iter() {
+            let pos = (pos as usize).min(new_order.len());
+            new_order.insert(pos, v);
+        }
+    }
+    pg.set_layer_order(layer, &new_order);
+}
+
+/// Ranks all of the clusters by the mean relative position (within their layers) of their
+/// vertices. Returns the rank of each cluster.
+fn rank_clusters(pg: &ProperGraph, clusters: &OrderingClusters) -> Vec<u32> {
+    let num_clusters = clusters.parent.len();
+    let mut sums: Vec<(f64, usize)> = vec![(0.0, 0); num_clusters];
+    for layer_verts in pg.verts.iter() {
+        for (pos, &v) in layer_verts.iter().enumerate() {
+            let relative_pos = (pos as f64 + 0.5) / layer_verts.len() as f64;
+            for c in clusters.path(clusters.cluster_of(v)) {
+                let sum = &mut sums[c as usize];
+                sum.0 += relative_pos;
+                sum.1 += 1;
+            }
+        }
+    }
+    let mean = |c: usize| match sums[c] {
+        (_, 0) => 0.0,
+        (sum, count) => sum / count as f64,
+    };
+    let mut by_mean: Vec<usize> = (0..num_clusters).collect();
+    by_mean.sort_by(|&a, &b| mean(a).partial_cmp(&mean(b)).unwrap().then(a.cmp(&b)));
+    let mut ranks: Vec<u32> = vec![0; num_clusters];
+    for (rank, &c) in by_mean.iter().enumerate() {
+        ranks[c] = rank as u32;
+    }
+    ranks
+}
+
+/// Sorts every layer using the current positions as keys. This makes the order of the clusters
+/// follow `cluster_ranks`, while otherwise changing the order as little as possible.
+fn sort_layers_by_position(
+    pg: &mut ProperGraph,
+    cons: &LayerConstraints,
+    clusters: &OrderingClusters,
+    cluster_ranks: &[u32],
+) {
+    for layer in 0..pg.num_layers() {
+        let layer_verts = pg.verts.entry_values(layer);
+        let keys: Vec<f64> = (0..layer_verts.len()).map(|i| i as f64).collect();
+        let new_order = sort_clustered(layer_verts, &keys, clusters, cluster_ranks, false);
+        pg.set_layer_order(layer, &new_order);
+        apply_constraints(pg, cons, clusters, layer);
+    }
+}
+
+/// Sorts the vertices of a layer by `keys` (which is parallel to `layer_verts`), keeping the
+/// vertices of each cluster contiguous. Clusters are sorted as units, by the mean key of their
+/// vertices, except that sibling clusters are always kept in the order of `cluster_ranks`. Left
+/// border vertices are placed first within their cluster, and right border vertices last. Ties
+/// keep their current order, or the opposite order if `reverse` is true.
+fn sort_clustered(
+    layer_verts: &[V],
+    keys: &[f64],
+    clusters: &OrderingClusters,
+    cluster_ranks: &[u32],
+    reverse: bool,
+) -> Vec<V> {
+    #[derive(Copy, Clone, Eq, PartialEq, Hash)]
+    enum Item {
+        Cluster(u32),
+        Vert(V),
+    }
+
+    // The path from the outermost cluster down to each vertex.
+    let paths: Vec<Vec<Item>> = layer_verts
+        .iter()
+        .map(|&v| {
+            let mut path: Vec<Item> = clusters
+                .path(clusters.cluster_of(v))
+                .into_iter()
+                .map(Item::Cluster)
+                .collect();
+            path.push(Item::Vert(v));
+            path
+        })
+        .collect();
+
+    // For each item: the sum of its keys, the number of keys, and its first position.
+    let mut item_sums: HashMap<Item, (f64, usize, usize)> = HashMap::new();
+    for (i, path) in paths.iter().enumerate() {
+        for &item in path.iter() {
+            let sums = item_sums.entry(item).or_insert((0.0, 0, i));
+            sums.0 += keys[i];
+            sums.1 += 1;
+        }
+    }
+
+    // The sibling clusters (grouped by their parent) in the order of their ranks. If their mean
+    // keys are out of order, then adjacent clusters are pooled into blocks, which are sorted as
+    // units (using the mean key of the block). The keys of the blocks are strictly increasing.
+    let mut siblings: HashMap<Option<u32>, Vec<u32>> = HashMap::new();
+    for (&item, _) in item_sums.iter() {
+        if let Item::Cluster(c) = item {
+            siblings
+                .entry(clusters.parent[c as usize])
+                .or_default()
+                .push(c);
+        }
+    }
+    // For each cluster: the key and first position of its block, and the block number.
+    let mut cluster_block: HashMap<u32, (f64, usize, usize)> = HashMap::new();
+    let mut num_blocks = 0;
+    for group in siblings.values_mut() {
+        group.sort_unstable_by_key(|&c| cluster_ranks[c as usize]);
+        // Each block is (sum of keys, number of keys, first position, number of clusters).
+        let mut blocks: Vec<(f64, usize, usize, usize)> = Vec::new();
+        for &c in group.iter() {
+            let (sum, count, first) = item_sums[&Item::Cluster(c)];
+            blocks.push((sum, count, first, 1));
+            while blocks.len() >= 2 {
+                let b = blocks[blocks.len() - 1];
+                let a = blocks[blocks.len() - 2];
+                if a.0 / (a.1 as f64) < b.0 / (b.1 as f64) {
+                    break;
+                }
+                blocks.pop();
+                *blocks.last_mut().unwrap() = (a.0 + b.0, a.1 + b.1, a.2.min(b.2), a.3 + b.3);
+            }
+        }
+        let mut members = group.iter();
+        for &(sum, count, first, num_clusters) in blocks.iter() {
+            for &c in members.by_ref().take(num_clusters) {
+                cluster_block.insert(c, (sum / count as f64, first, num_blocks));
+            }
+            num_blocks += 1;
+        }
+    }
+
+    // Returns the sort key of an item: its key, its first position, and (for clusters) its
+    // block.
+    let item_key = |item: Item| -> (f64, usize, Option<usize>) {
+        match item {
+            Item::Vert(v) => {
+                let (sum, _, first) = item_sums[&item];
+                let key = match clusters.border_of(v) {
+                    Some(ClusterBorder::Left) => f64::NEG_INFINITY,
+                    Some(ClusterBorder::Right) => f64::INFINITY,
+                    None => sum,
+                };
+                (key, first, None)
+            }
+            Item::Cluster(c) => {
+                let (key, first, block) = cluster_block[&c];
+                (key, first, Some(block))
+            }
+        }
+    };
+
+    let mut order: Vec<usize> = (0..layer_verts.len()).collect();
+    order.sort_by(|&a, &b| {
+        // Compare the first items that differ. These are siblings: either clusters or vertices
+        // that are directly in the same cluster.
+        let (path_a, path_b) = (&paths[a], &paths[b]);
+        let i = path_a
+            .iter()
+            .zip(path_b.iter())
+            .take_while(|(x, y)| x == y)
+            .count();
+        let (key_a, first_a, block_a) = item_key(path_a[i]);
+        let (key_b, first_b, block_b) = item_key(path_b[i]);
+        if let (Item::Cluster(c_a), Item::Cluster(c_b)) = (path_a[i], path_b[i]) {
+            if block_a == block_b {
+                return cluster_ranks[c_a as usize].cmp(&cluster_ranks[c_b as usize]);
+            }
+        }
+        key_a.partial_cmp(&key_b).unwrap().then_with(|| {
+            if reverse {
+                first_b.cmp(&first_a)
+            } else {
+                first_a.cmp(&first_b)
+            }
+        })
+    });
+    order.iter().map(|&i| layer_verts[i]).collect()
+}
+
+/// Computes the weighted median of a sorted list of neighbor positions, as described by
+/// Gansner et al. Returns `None` if there are no neighbors.
+fn weighted_median(positions: &[f64]) -> Option<f64> {
+    let n = positions.len();
+    let m = n / 2;
+    match n {
+        0 => None,
+        _ if n % 2 == 1 => Some(positions[m]),
+        2 => Some((positions[0] + positions[1]) / 2.0),
+        _ => {
+            let left = positions[m - 1] - positions[0];
+            let right = positions[n - 1] - positions[m];
+            if left + right == 0.0 {
+                Some((positions[m - 1] + positions[m]) / 2.0)
+            } else {
+                Some((positions[m - 1] * right + positions[m] * left) / (left + right))
+            }
+        }
+    }
+}
+
+/// Sorts each layer by the weighted median of its neighbors in the adjacent layer. A downward
+/// sweep starts below the top layer and uses the neighbors above; an upward sweep starts above
+/// the bottom layer and uses the neighbors below. If `reverse` is true, then vertices that have
+/// equal medians swap their relative order.
+///
+/// Vertices that have no neighbors in the adjacent layer keep their positions. If there are
+/// clusters, then these vertices are instead sorted using their current position as the key.
+fn median_sweep(
+    pg: &mut ProperGraph,
+    adj: &LayerAdjacency,
+    cons: &LayerConstraints,
+    clusters: &OrderingClusters,
+    cluster_ranks: &[u32],
+    downward: bool,
+    reverse: bool,
+) {
+    let num_layers = pg.num_layers();
+    if num_layers < 2 {
+        return;
+    }
+    let layers: Vec<usize> = if downward {
+        (0..num_layers - 1).rev().collect()
+    } else {
+        (1..num_layers).collect()
+    };
+
+    let mut positions: Vec<f64> = Vec::new();
+    let mut movable: Vec<(f64, V)> = Vec::new();
+    let mut new_order: Vec<V> = Vec::new();
+    for layer in layers {
+        if !clusters.is_empty() {
+            let layer_verts = pg.verts.entry_values(layer);
+            let keys: Vec<f64> = layer_verts
+                .iter()
+                .enumerate()
+                .map(|(pos, &v)| {
+                    adj.neighbor_positions(pg, v, downward, &mut positions);
+                    weighted_median(&positions).unwrap_or(pos as f64)
+                })
+                .collect();
+            let new_order = sort_clustered(layer_verts, &keys, clusters, cluster_ranks, reverse);
+            pg.set_layer_order(layer, &new_order);
+            apply_constraints(pg, cons, clusters, layer);
+            continue;
+        }
+
+        movable.clear();
+        let layer_verts = pg.verts.entry_values(layer);
+        let mut fixed: Vec<Option<V>> = Vec::with_capacity(layer_verts.len());
+        for &v in layer_verts.iter() {
+            adj.neighbor_positions(pg, v, downward, &mut positions);
+            match weighted_median(&positions) {
+                Some(median) => {
+                    movable.push((median, v));
+                    fixed.push(None);
+                }
+                None => fixed.push(Some(v)),
+            }
+        }
+
+        // Stable sort, so that ties keep their current order (or the opposite order).
+        if reverse {
+            movable.reverse();
+        }
+        movable.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        new_order.clear();
+        let mut movable_iter = movable.iter();
+        for slot in fixed.iter() {
+            match slot {
+                Some(v) => new_order.push(*v),
+                None => new_order.push(movable_iter.next().unwrap().1),
+            }
+        }
+        pg.set_layer_order(layer, &new_order);
+        apply_constraints(pg, cons, clusters, layer);
+    }
+}
+
+/// Counts the crossings between the edges of `v` and the edges of `w` (in both adjacent layers),
+/// assuming that `v` is to the left of `w`. Edges that go to the same neighbor cross if their
+/// ports on the neighbor are in the opposite order.
+fn pair_crossings(pg: &ProperGraph, adj: &LayerAdjacency, v: V, w: V) -> u64 {
+    let mut count = 0;
+    let mut v_positions: Vec<f64> = Vec::new();
+    let mut w_positions: Vec<f64> = Vec::new();
+    for &upward in [true, false].iter() {
+        adj.neighbor_positions(pg, v, upward, &mut v_positions);
+        adj.neighbor_positions(pg, w, upward, &mut w_positions);
+        for &a in v_positions.iter() {
+            for &b in w_positions.iter() {
+                if a > b {
+                    count += 1;
+                }
+            }
+        }
+    }
+    count
+}
+
+/// Repeatedly swaps adjacent vertices in each layer, as long as doing so reduces the number
+/// of crossings. If `reverse` is true, then vertices are also swapped if doing so does not
+/// change the number of crossings; only swaps that reduce crossings cause another pass.
+///
+/// Only vertices in the same innermost cluster are swapped, border vertices and pinned vertices
+/// are never swapped, and the ends of a flat edge or `LeftOf` constraint are never swapped.
+/// Afterward, the ends of flat edges are moved next to each other again, if swaps separated
+/// them.
+fn transpose(
+    pg: &mut ProperGraph,
+    adj: &LayerAdjacency,
+    cons: &LayerConstraints,
+    clusters: &OrderingClusters,
+    reverse: bool,
+) {
+    let num_layers = pg.num_layers();
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for layer in 0..num_layers {
+            let range = pg.verts.entry_values_range(layer);
+            for i in range.start..range.end.saturating_sub(1) {
+                let v = pg.verts.values[i];
+                let w = pg.verts.values[i + 1];
+                if clusters.cluster_of(v) != clusters.cluster_of(w)
+                    || clusters.border_of(v).is_some()
+                    || clusters.border_of(w).is_some()
+                    || cons.must_be_left_of(v, w)
+                    || cons.pinned[v as usize].is_some()
+                    || cons.pinned[w as usize].is_some()
+                {
+                    continue;
+                }
+                let c0 = pair_crossings(pg, adj, v, w);
+                let c1 = pair_crossings(pg, adj, w, v);
+                if c1 < c0 || (reverse && c0 > 0 && c1 == c0) {
+                    pg.verts.values.swap(i, i + 1);
+                    pg.v_pos.swap(v as usize, w as usize);
+                    improved |= c1 < c0;
+                }
+            }
+        }
+    }
+
+    // Swaps can separate the ends of flat edges, so bring them back together.
+    for layer in 0..num_layers {
+        apply_constraints(pg, cons, clusters, layer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Graph;
+    use crate::layering::create_proper_graph;
+    use crate::testing::*;
+
+    fn check_order(pg: &ProperGraph) {
+        for (layer, layer_verts) in pg.verts.iter().enumerate() {
+            for (pos, &v) in layer_verts.iter().enumerate() {
+                assert_eq!(pg.v_layer[v as usize] as usize, layer);
+                assert_eq!(pg.v_pos[v as usize] as usize, pos);
+            }
+        }
+    }
+
+    #[test]
+    fn minimize_crossings_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph, expected_crossings: u64) {
+            let mut pg = create_proper_graph(graph).unwrap();
+            let crossings = minimize_crossings(&mut pg, &OrderingOptions::default());
+            println!("--- {}\norder: {:?}", description, pg.verts);
+            check_order(&pg);
+            assert_eq!(crossings, count_crossings(&pg));
+            assert_eq!(crossings, expected_crossings, "{}", description);
+
+            // The result should be deterministic.
+            let mut pg2 = create_proper_graph(graph).unwrap();
+            minimize_crossings(&mut pg2, &OrderingOptions::default());
+            assert_eq!(pg.verts, pg2.verts);
+        }
+
+        case("empty", &Graph::new(), 0);
+        case("linear path", &graph_from_paths(&[&[0, 1, 2, 3]]), 0);
+        case(
+            "crossed pair",
+            &graph_from_paths(&[&[0, 3], &[1, 2], &[0, 4], &[1, 4]]),
+            0,
+        );
+        case(
+            "complete bipartite 2x2",
+            &graph_from_paths(&[&[0, 2], &[0, 3], &[1, 2], &[1, 3]]),
+            1,
+        );
+        case(
+            "long edges",
+            &graph_from_paths(&[
+                &[1, 10, 11, 12, 13, 14],
+                &[1, 20, 21, 22, 23, 24],
+                &[1, 14],
+                &[1, 24],
+                &[10, 23],
+            ]),
+            0,
+        );
+        case(
+            "two trees joined at the leaves",
+            &graph_from_paths(&[
+                &[0, 2, 6],
+                &[0, 3, 7],
+                &[1, 4, 6],
+                &[1, 5, 7],
+                &[0, 4],
+                &[1, 3],
+            ]),
+            1,
+        );
+    }
+
+    /// Checks that every cluster is contiguous within every layer, with its border vertices at
+    /// its ends, and that clusters that are not nested are in the same order on every layer.
+    fn check_clusters(pg: &ProperGraph, clusters: &OrderingClusters) {
+        let num_clusters = clusters.parent.len() as u32;
+        let mut left_of: Vec<Option<bool>> = vec![None; (num_clusters * num_clusters) as usize];
+        for layer_verts in pg.verts.iter() {
+            let first_pos = |c: u32| {
+                layer_verts
+                    .iter()
+                    .position(|&v| clusters.path(clusters.cluster_of(v)).contains(&c))
+            };
+            for a in 0..num_clusters {
+                for b in 0..num_clusters {
+                    let path_a = clusters.path(Some(a));
+                    let path_b = clusters.path(Some(b));
+                    if path_a.contains(&b) || path_b.contains(&a) {
+                        continue;
+                    }
+                    if let (Some(pos_a), Some(pos_b)) = (first_pos(a), first_pos(b)) {
+                        let slot = &mut left_of[(a * num_clusters + b) as usize];
+                        assert_ne!(
+                            *slot,
+                            Some(pos_b < pos_a),
+                            "clusters {} and {} change order",
+                            a,
+                            b
+                        );
+                        *slot = Some(pos_a < pos_b);
+                    }
+                }
+            }
+        }
+
+        for layer_verts in pg.verts.iter() {
+            for c in 0..clusters.parent.len() as u32 {
+                let members: Vec<usize> = layer_verts
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &v)| clusters.path(clusters.cluster_of(v)).contains(&c))
+                    .map(|(pos, _)| pos)
+                    .collect();
+                if let (Some(&first), Some(&last)) = (members.first(), members.last()) {
+                    assert_eq!(
+                        last - first + 1,
+                        members.len(),
+                        "cluster {} is not contiguous in {:?}",
+                        c,
+                        layer_verts
+                    );
+                }
+                for &pos in members.iter() {
+                    let v = layer_verts[pos];
+                    if clusters.cluster_of(v) != Some(c) {
+                        continue;
+                    }
+                    match clusters.border_of(v) {
+                        Some(ClusterBorder::Left) => assert_eq!(pos, members[0]),
+                        Some(ClusterBorder::Right) => assert_eq!(pos, *members.last().unwrap()),
+                        None => {}
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn minimize_crossings_clustered_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph, clusters: &OrderingClusters) {
+            let mut pg = create_proper_graph(graph).unwrap();
+            let crossings =
+                minimize_crossings_clustered(&mut pg, clusters, &OrderingOptions::default());
+            println!("--- {}\norder: {:?}", description, pg.verts);
+            check_order(&pg);
+            check_clusters(&pg, clusters);
+            assert_eq!(crossings, count_crossings(&pg));
+        }
+
+        let trees = graph_from_paths(&[
+            &[0, 2, 6],
+            &[0, 3, 7],
+            &[1, 4, 6],
+            &[1, 5, 7],
+            &[0, 4],
+            &[1, 3],
+        ]);
+        case(
+            "separated leaves",
+            &trees,
+            &OrderingClusters {
+                parent: vec![None],
+                v_cluster: vec![None, None, None, None, None, None, Some(0), None, None],
+                ..OrderingClusters::default()
+            },
+        );
+        case(
+            "nested",
+            &trees,
+            &OrderingClusters {
+                parent: vec![None, Some(0), None],
+                v_cluster: vec![
+                    Some(1),
+                    None,
+                    Some(0),
+                    Some(1),
+                    Some(2),
+                    Some(0),
+                    Some(0),
+                    Some(2),
+                ],
+                ..OrderingClusters::default()
+            },
+        );
+
+        // Vertices 2 and 3 have no edges; they are the borders of cluster 0.
+        let borders = graph_from_paths(&[&[0, 1], &[0, 4], &[0, 5]]);
+        case(
+            "borders",
+            &borders,
+            &OrderingClusters {
+                parent: vec![None],
+                v_cluster: vec![None, Some(0), Some(0), Some(0), None, Some(0)],
+                v_border: vec![
+                    None,
+                    None,
+                    Some(ClusterBorder::Left),
+                    Some(ClusterBorder::Right),
+                    None,
+                    None,
+                ],
+            },
+        );
+    }
+
+    #[test]
+    fn minimize_crossings_flat_edges_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph, v_layer: Vec<u32>, expected_order: &[V]) {
+            let layers = crate::layering::LayerMap {
+                num_layers: 2,
+                v_layer,
+            };
+            let mut pg = crate::layering::create_proper_graph_from_layers(graph, &layers);
+            let crossings = minimize_crossings(&mut pg, &OrderingOptions::default());
+            println!(
+                "--- {}\ncrossings: {}, order: {:?}",
+                description, crossings, pg.verts
+            );
+            check_order(&pg);
+            assert_eq!(pg.verts.entry_values(1), expected_order, "{}", description);
+        }
+
+        // The flat edges 3 -> 0 -> 2 form a chain, so their ends are next to each other.
+        case(
+            "chain",
+            &graph_from_paths(&[&[3, 0, 2], &[0, 4], &[1, 5], &[2, 5], &[3, 4]]),
+            vec![1, 1, 1, 1, 0, 0],
+            &[3, 0, 2, 1],
+        );
+
+        // Both edges point into 2, so it goes right after the later of 0 and 1.
+        case(
+            "two edges in",
+            &graph_from_paths(&[&[0, 2], &[1, 2], &[3, 4]]),
+            vec![1, 1, 1, 1, 0],
+            &[0, 1, 2, 3],
+        );
+    }
+
+    #[test]
+    fn minimize_crossings_constraints_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph, constraints: &[OrderConstraint]) -> ProperGraph {
+            let mut pg = create_proper_graph(graph).unwrap();
+            let options = OrderingOptions {
+                constraints: constraints.to_vec(),
+                ..OrderingOptions::default()
+            };
+            let crossings = minimize_crossings(&mut pg, &options);
+            println!(
+                "--- {}\ncrossings: {}, order: {:?}",
+                description, crossings, pg.verts
+            );
+            check_order(&pg);
+            assert_eq!(crossings, count_crossings(&pg));
+            pg
+        }
+
+        // Without constraints, 2 and 3 would be swapped to remove the crossings.
+        let crossed = graph_from_paths(&[&[0, 3], &[1, 2], &[0, 4], &[1, 4]]);
+        let pg = case("left of", &crossed, &[OrderConstraint::LeftOf(2, 3)]);
+        assert!(pg.v_pos[2] < pg.v_pos[3]);
+        let pg = case(
+            "left of, both layers",
+            &crossed,
+            &[OrderConstraint::LeftOf(2, 3), OrderConstraint::LeftOf(0, 1)],
+        );
+        assert!(pg.v_pos[2] < pg.v_pos[3]);
+        assert!(pg.v_pos[0] < pg.v_pos[1]);
+
+        let pg = case(
+            "pins",
+            &crossed,
+            &[OrderConstraint::Pin(4, 0), OrderConstraint::Pin(1, 5)],
+        );
+        assert_eq!(pg.v_pos[4], 0);
+        assert_eq!(pg.v_pos[1], 1);
+
+        // A constraint between vertices in different layers is ignored.
+        let pg = case(
+            "different layers",
+            &crossed,
+            &[OrderConstraint::LeftOf(1, 3), OrderConstraint::LeftOf(2, 3)],
+        );
+        assert!(pg.v_pos[2] < pg.v_pos[3]);
+
+        // A cycle of constraints is broken somewhere.
+        let pg = case(
+            "cycle",
+            &crossed,
+            &[OrderConstraint::LeftOf(2, 3), OrderConstraint::LeftOf(3, 2)],
+        );
+        assert_eq!(pg.verts.entry_values(0).len(), 3);
+
+        // Many constraints on one layer.
+        let fan = graph_from_paths(&[&[0, 1], &[0, 2], &[0, 3], &[0, 4], &[5, 1], &[6, 4]]);
+        let constraints = [
+            OrderConstraint::LeftOf(4, 3),
+            OrderConstraint::LeftOf(3, 2),
+            OrderConstraint::LeftOf(2, 1),
+            OrderConstraint::Pin(6, 0),
+        ];
+        let pg = case("fan", &fan, &constraints);
+        for &c in constraints.iter() {
+            match c {
+                OrderConstraint::LeftOf(a, b) => {
+                    assert!(pg.v_pos[a as usize] < pg.v_pos[b as usize])
+                }
+                OrderConstraint::Pin(v, pos) => assert_eq!(pg.v_pos[v as usize], pos),
+            }
+        }
+    }
+
+    #[test]
+    fn minimize_crossings_ports_test() {
+        init_test();
+
+        // Vertex 0 has an edge to each of 1, 2 and 3. Without ports, every order of the bottom
+        // layer has no crossings, so the ports decide the order.
+        let graph = graph_from_paths(&[&[0, 1], &[0, 2], &[0, 3]]);
+        fn case(description: &str, graph: &Graph, offsets: &[f64], expected: &[V]) {
+            let mut pg = create_proper_graph(graph).unwrap();
+            let port_offsets: Vec<(f64, f64)> = pg
+                .edges
+                .all_values()
+                .iter()
+                .map(|&(_, to)| (offsets[to as usize - 1], 0.0))
+                .collect();
+            let options = OrderingOptions {
+                port_offsets,
+                ..OrderingOptions::default()
+            };
+            let crossings = minimize_crossings(&mut pg, &options);
+            println!(
+                "--- {}\ncrossings: {}, order: {:?}",
+                description, crossings, pg.verts
+            );
+            check_order(&pg);
+            assert_eq!(pg.verts.entry_values(0), expected, "{}", description);
+        }
+
+        case("left to right", &graph, &[-0.4, 0.0, 0.4], &[1, 2, 3]);
+        case("right to left", &graph, &[0.4, 0.0, -0.4], &[3, 2, 1]);
+        case("middle first", &graph, &[0.0, -0.4, 0.4], &[2, 1, 3]);
+    }
+
+    #[test]
+    fn common_ancestor_test() {
+        let clusters = OrderingClusters {
+            parent: vec![None, Some(0), Some(0), Some(2), None],
+            ..OrderingClusters::default()
+        };
+        assert_eq!(clusters.path(Some(3)), vec![0, 2, 3]);
+        assert_eq!(clusters.common_ancestor(Some(3), Some(1)), Some(0));
+        assert_eq!(clusters.common_ancestor(Some(3), Some(2)), Some(2));
+        assert_eq!(clusters.common_ancestor(Some(3), Some(4)), None);
+        assert_eq!(clusters.common_ancestor(None, Some(4)), None);
+    }
+
+    #[test]
+    fn weighted_median_test() {
+        assert_eq!(weighted_median(&[]), None);
+        assert_eq!(weighted_median(&[3.0]), Some(3.0));
+        assert_eq!(weighted_median(&[1.0, 4.0]), Some(2.5));
+        assert_eq!(weighted_median(&[0.0, 1.0, 5.0]), Some(1.0));
+        // The median is pulled toward the side where the neighbors are more tightly packed.
+        assert_eq!(weighted_median(&[0.0, 1.0, 2.0, 6.0]), Some(1.2));
+    }
+}
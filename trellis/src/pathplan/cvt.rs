@@ -1,6 +1,8 @@
+use super::shortestpth::makePath;
 use super::vis::*;
+use super::visibility::ptVis;
 use super::*;
-use crate::polyline::Ppoly_t;
+use crate::polyline::{Ppoly_t, Ppolyline_t};
 use crate::vec2::Ppoint_t;
 
 pub type ilcoord_t = Ppoint_t;
@@ -53,79 +55,49 @@ void Pobsclose(vconfig_t * config)
 }
 */
 
-/*
-
 /* route a polyline from p0 to p1, avoiding obstacles.
  * if an endpoint is inside an obstacle, pass the polygon's index >=0
  * if the endpoint is not inside an obstacle, pass POLYID_NONE
  * if the endpoint location is not known, pass POLYID_UNKNOWN
+ * Returns None if the obstacles separate p0 from p1.
  */
-int Pobspath(vconfig_t * config, Ppoint_t p0, int poly0, Ppoint_t p1,
-         int poly1, Ppolyline_t * output_route)
-{
-    int i, j, *dad;
-    size_t opn;
-    COORD *ptvis0, *ptvis1;
-
-#ifdef GASP
-    gasp_print_obstacles(config);
-#endif
-    ptvis0 = ptVis(config, poly0, p0);
-    ptvis1 = ptVis(config, poly1, p1);
-
-#ifdef GASP
-    gasp_print_point(p0);
-    gasp_print_point(p1);
-#endif
-    dad = makePath(p0, poly0, ptvis0, p1, poly1, ptvis1, config);
-
-    opn = 1;
-    for (i = dad[config->N]; i != config->N + 1; i = dad[i])
-    opn++;
-    opn++;
-    std::vector<Ppoint_t> ops{ opn };
-
-    j = opn - 1;
-    ops[j--] = p1;
-    for (i = dad[config->N]; i != config->N + 1; i = dad[i])
-    ops[j--] = config->P[i];
-    ops[j] = p0;
-    assert(j == 0);
-
-#ifdef DEBUG
-    printVconfig(config);
-    printVis("p", ptvis0, config->N + 1);
-    printVis("q", ptvis1, config->N + 1);
-    printDad(dad, config->N + 1);
-#endif
-
-    if (ptvis0)
-    free(ptvis0);
-    if (ptvis1)
-    free(ptvis1);
-
-    output_route->ps = std::move(ops);
-#ifdef GASP
-    gasp_print_polyline(output_route);
-#endif
-    free(dad);
-    return TRUE;
+pub fn Pobspath(
+    config: &vconfig_t,
+    p0: Ppoint_t,
+    poly0: i32,
+    p1: Ppoint_t,
+    poly1: i32,
+) -> Option<Ppolyline_t> {
+    let ptvis0 = ptVis(config, poly0, p0);
+    let ptvis1 = ptVis(config, poly1, p1);
+    let dad = makePath(p0, poly0, &ptvis0, p1, poly1, &ptvis1, config);
+
+    /* the path runs backward, from p1 to p0 */
+    let mut ops: Vec<Ppoint_t> = vec![p1];
+    let mut i = dad[config.N];
+    while i != config.N as i32 + 1 {
+        if i < 0 || ops.len() > config.N {
+            return None;
+        }
+        ops.push(config.P[i as usize]);
+        i = dad[i as usize];
+    }
+    ops.push(p0);
+    ops.reverse();
+    Some(Ppolyline_t { ps: ops })
 }
 
-int Pobsbarriers(vconfig_t* config, std::vector<Pedge_t>* barriers, int* n_barriers) {
-    barriers->clear();
-    barriers->resize(config->N);
-
-    for (int i = 0; i < config->N; i++) {
-        int j = config->next[i];
-        (*barriers)[i] = Pedge_t{
-            config->P[i],
-            config->P[j]
-        };
-    }
-    return 1;
+pub fn Pobsbarriers(config: &vconfig_t) -> Vec<Pedge_t> {
+    (0..config.N)
+        .map(|i| Pedge_t {
+            a: config.P[i],
+            b: config.P[config.next[i] as usize],
+        })
+        .collect()
 }
 
+/*
+
 #ifdef DEBUG
 static void printVconfig(vconfig_t * cp)
 {
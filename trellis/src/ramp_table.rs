@@ -354,4 +354,16 @@ impl<T> RampTableBuilder<T> {
         }
         table
     }
+
+    /// Builds the table, like `finish`, and then adds empty entries to the end of the table so
+    /// that it has exactly `num_keys` keys. All keys that were pushed must be less than
+    /// `num_keys`.
+    pub fn finish_with_len(self, num_keys: usize) -> RampTable<T> {
+        let mut table = self.finish();
+        assert!(table.len() <= num_keys);
+        while table.len() < num_keys {
+            table.finish_key();
+        }
+        table
+    }
 }
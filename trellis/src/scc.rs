@@ -0,0 +1,150 @@
+use crate::graph::Graph;
+use crate::ramp_table::RampTable;
+use crate::V;
+use log::debug;
+
+const NOT_VISITED: u32 = !0u32;
+
+/// Finds the strongly-connected components of a graph, using Tarjan's algorithm.
+///
+/// Returns a table with one key for each component. The values for each key are the vertices
+/// in that component, in increasing order. Every vertex is in exactly one component; vertices
+/// that are not part of any cycle are in components of their own. Components are reported in
+/// reverse topological order: if there is an edge from component `a` to component `b`, then
+/// `b` is reported before `a`.
+pub fn find_strongly_connected_components(graph: &Graph) -> RampTable<V> {
+    let nv = graph.num_verts();
+
+    // The order in which each vertex was first visited, and the lowest visit order that is
+    // reachable from that vertex using the vertices that are still on the component stack.
+    let mut v_index: Vec<u32> = vec![NOT_VISITED; nv];
+    let mut v_lowlink: Vec<u32> = vec![0; nv];
+    let mut on_stack: Vec<bool> = vec![false; nv];
+    let mut next_index: u32 = 0;
+
+    // Vertices which have been visited but not yet assigned to a component.
+    let mut component_stack: Vec<V> = Vec::new();
+
+    // Depth-first search stack. Each entry is a vertex and the index of its next edge.
+    let mut work_stack: Vec<(V, usize)> = Vec::new();
+
+    let mut components: RampTable<V> = RampTable::with_capacity(nv, nv);
+
+    for root in 0..nv as V {
+        if v_index[root as usize] != NOT_VISITED {
+            continue;
+        }
+
+        v_index[root as usize] = next_index;
+        v_lowlink[root as usize] = next_index;
+        next_index += 1;
+        component_stack.push(root);
+        on_stack[root as usize] = true;
+        work_stack.push((root, 0));
+
+        while let Some(top) = work_stack.last_mut() {
+            let (v, edge_pos) = *top;
+            let to_list = graph.edges_from(v);
+            if let Some(&to) = to_list.get(edge_pos) {
+                top.1 += 1;
+                if v_index[to as usize] == NOT_VISITED {
+                    v_index[to as usize] = next_index;
+                    v_lowlink[to as usize] = next_index;
+                    next_index += 1;
+                    component_stack.push(to);
+                    on_stack[to as usize] = true;
+                    work_stack.push((to, 0));
+                } else if on_stack[to as usize] {
+                    v_lowlink[v as usize] = v_lowlink[v as usize].min(v_index[to as usize]);
+                }
+                continue;
+            }
+
+            // We're done with the subgraph under v.
+            work_stack.pop();
+            if let Some(&(parent, _)) = work_stack.last() {
+                v_lowlink[parent as usize] = v_lowlink[parent as usize].min(v_lowlink[v as usize]);
+            }
+
+            if v_lowlink[v as usize] == v_index[v as usize] {
+                // v is the root of a component. Everything above it on the component stack
+                // belongs to the same component.
+                let start = component_stack
+                    .iter()
+                    .rposition(|&cv| cv == v)
+                    .unwrap();
+                let mut members: Vec<V> = component_stack.drain(start..).collect();
+                members.sort_unstable();
+                for &member in members.iter() {
+                    on_stack[member as usize] = false;
+                }
+                debug!("component {}: {:?}", components.len(), members);
+                components.push_entry_copy(&members);
+            }
+        }
+    }
+
+    assert!(component_stack.is_empty());
+    components
+}
+
+/// Finds the strongly-connected components which contain at least one cycle. These are the
+/// components that have more than one vertex, and the single vertices that have self-loops.
+///
+/// The result has the same form as `find_strongly_connected_components`.
+pub fn find_cyclic_components(graph: &Graph) -> RampTable<V> {
+    let all = find_strongly_connected_components(graph);
+    let mut cyclic: RampTable<V> = RampTable::new();
+    for members in all.iter() {
+        let is_cyclic = match *members {
+            [v] => graph.edges_from(v).contains(&v),
+            _ => true,
+        };
+        if is_cyclic {
+            cyclic.push_entry_copy(members);
+        }
+    }
+    cyclic
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    #[test]
+    fn find_cyclic_components_test() {
+        init_test();
+
+        fn case(description: &str, graph: &Graph, expected: &[&[V]]) {
+            let cyclic = find_cyclic_components(graph);
+            let mut actual: Vec<Vec<V>> = cyclic.iter().map(|c| c.to_vec()).collect();
+            actual.sort();
+            assert_eq!(actual, expected, "{}", description);
+        }
+
+        case("empty", &Graph::new(), &[]);
+        case("acyclic", &graph_from_paths(&[&[0, 1, 2], &[0, 2]]), &[]);
+        case("self-edge", &graph_from_paths(&[&[0, 1, 1]]), &[&[1]]);
+        case("simple loop", &graph_from_paths(&[&[0, 1, 2, 0]]), &[&[0, 1, 2]]);
+        case(
+            "two loops joined by an edge",
+            &graph_from_paths(&[&[0, 1, 0], &[1, 2], &[2, 3, 4, 2]]),
+            &[&[0, 1], &[2, 3, 4]],
+        );
+        case(
+            "two loops sharing a vertex",
+            &graph_from_paths(&[&[0, 1, 2, 0], &[2, 3, 4, 2]]),
+            &[&[0, 1, 2, 3, 4]],
+        );
+    }
+
+    #[test]
+    fn components_are_reverse_topological_test() {
+        let graph = graph_from_paths(&[&[0, 1, 0], &[1, 2], &[2, 3, 2]]);
+        let components = find_strongly_connected_components(&graph);
+        assert_eq!(components.len(), 2);
+        assert_eq!(components.entry_values(0), &[2, 3]);
+        assert_eq!(components.entry_values(1), &[0, 1]);
+    }
+}
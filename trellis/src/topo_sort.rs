@@ -19,6 +19,9 @@ use log::debug;
 ///
 /// It is legal for the input to contain degenerate vertices, meaning vertices that have
 /// no edges (in-degree = 0 and out-degree = 0). The output will _not_ contain these vertices.
+///
+/// If the graph contains a cycle, then this returns `Error::FoundCycle` with the vertices of
+/// the first cycle that was found.
 pub fn topo_sort_reverse(graph: &Graph) -> Result<Vec<u32>, Error> {
     debug!("topo_sort");
     let nv = graph.num_verts();
@@ -101,8 +104,16 @@ pub fn topo_sort_reverse(graph: &Graph) -> Result<Vec<u32>, Error> {
             if let Some(&next_v) = v_edges.next() {
                 if in_work_set[next_v as usize] {
                     debug!("... found cycle");
-                    // We have found a cycle.
-                    return Err(Error::FoundCycle);
+                    // We have found a cycle. The vertices on the work stack, starting from
+                    // next_v, form a path to v, and v has an edge back to next_v.
+                    let cycle_start = work_stack
+                        .iter()
+                        .position(|&(wv, _)| wv == next_v)
+                        .unwrap_or(work_stack.len());
+                    let mut cycle: Vec<V> =
+                        work_stack[cycle_start..].iter().map(|&(wv, _)| wv).collect();
+                    cycle.push(v);
+                    return Err(Error::FoundCycle(cycle));
                 }
                 if visited[next_v as usize] {
                     debug!("... v{} --> v{}, already seen v{}", v, next_v, next_v);
@@ -188,7 +199,7 @@ mod tests {
             |g| {
                 g.from(0).to(0);
             },
-            Err(Error::FoundCycle),
+            Err(Error::FoundCycle(vec![0])),
         );
 
         case(
@@ -212,7 +223,7 @@ mod tests {
             |g| {
                 g.from(0).to(1).to(2).to(0);
             },
-            Err(Error::FoundCycle),
+            Err(Error::FoundCycle(vec![0, 1, 2])),
         );
 
         case(
@@ -233,13 +244,21 @@ mod tests {
             Ok(vec![0, 5, 6, 3, 4, 1, 2]),
         );
 
+        case(
+            "cycle reachable from a path",
+            |g| {
+                g.path(&[0, 1, 2, 3, 4, 2]);
+            },
+            Err(Error::FoundCycle(vec![2, 3, 4])),
+        );
+
         case(
             "lots of small loops",
             |g| {
                 g.path(&[1, 2, 3, 4, 5]);
                 g.path(&[5, 4, 3, 2, 1]);
             },
-            Err(Error::FoundCycle),
+            Err(Error::FoundCycle(vec![4, 5])),
         );
     }
 